@@ -4,6 +4,7 @@ use std::io;
 use super::opcodes;
 use super::tokens;
 
+#[allow(dead_code)]
 struct Lattice {
     /// The current possibilities
     options: Vec<tokens::Token>,
@@ -13,6 +14,7 @@ struct Lattice {
 }
 
 pub struct Lexer {
+    #[allow(dead_code)]
     /// The lattice; i.e., what our current possiblities are
     lattice: Lattice,
     /// The input stream, tokenized into an output stream
@@ -34,9 +36,7 @@ impl Lexer {
 
         for maybeline in stream.lines() {
             let line = maybeline.expect("Could not get line");
-            if let Err(e) = ldata.lex_line(line) {
-                return Err(e);
-            }
+            ldata.lex_line(line)?;
         }
 
         Ok(ldata)
@@ -65,7 +65,7 @@ impl Lexer {
     }
 
     /// Try to parse `ch` as part of an Opcode, given the present state of the Lexer.
-    fn try_opcode(&mut self, ch: char) -> Option<opcodes::Opcode> {
+    fn try_opcode(&mut self, _ch: char) -> Option<opcodes::Opcode> {
         // TODO
         None
     }
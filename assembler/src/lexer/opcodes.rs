@@ -1,5 +1,6 @@
 //! This file provides an enum of Opcode variants
 
+#[allow(dead_code, clippy::upper_case_acronyms)]
 pub enum Opcode {
     ADD
 }
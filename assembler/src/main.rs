@@ -28,7 +28,7 @@ fn main() {
     let mut source_paths = Vec::<&path::Path>::new();
     for fpath in source_paths_strings {
         let p = path::Path::new(fpath);
-        source_paths.push(&p);
+        source_paths.push(p);
     }
 
     // Make sure each path is valid
@@ -0,0 +1,160 @@
+//! This module contains the MEGA-CHIP's true color screen, which sits on top of a `PixelGrid` when the program
+//! turns MEGA-CHIP mode on.
+//!
+//! Every pixel has a full 32 bit ARGB color, plus the index of the palette entry that was last drawn there (which
+//! is what collisions are checked against). The screen is double buffered: sprites are drawn onto the back
+//! buffer, and the program shows it (and starts again on a clean one) with CLS.
+
+/// The number of entries in the palette. Sprites are one palette index per pixel.
+pub const PALETTE_SIZE: usize = 256;
+/// The palette index that is never drawn.
+pub const TRANSPARENT: u8 = 0;
+
+/// How a sprite's colors are combined with whatever is already on the screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The sprite's color replaces the screen's.
+    #[default]
+    Normal,
+    /// The sprite is 25% opaque.
+    Opacity25,
+    /// The sprite is 50% opaque.
+    Opacity50,
+    /// The sprite is 75% opaque.
+    Opacity75,
+    /// The colors are added together, stopping at full brightness.
+    Add,
+    /// The colors are multiplied together.
+    Multiply,
+}
+
+impl BlendMode {
+    /// Every blend mode, in the order 080n numbers them.
+    pub const ALL: [BlendMode; 6] = [
+        BlendMode::Normal,
+        BlendMode::Opacity25,
+        BlendMode::Opacity50,
+        BlendMode::Opacity75,
+        BlendMode::Add,
+        BlendMode::Multiply,
+    ];
+
+    /// The blend mode 080n selects with `n`, if there is one.
+    pub fn from_number(n: u8) -> Option<Self> {
+        BlendMode::ALL.get(n as usize).cloned()
+    }
+
+    /// The number 080n selects this blend mode with.
+    pub fn number(self) -> u8 {
+        BlendMode::ALL.iter().position(|m| *m == self).unwrap() as u8
+    }
+
+    /// Combines the ARGB colors `src` (from the sprite) and `dst` (on the screen). The result is always opaque.
+    pub fn blend(self, src: u32, dst: u32) -> u32 {
+        let channel = |c: u32, shift: u32| (c >> shift) & 0xFF;
+        let mix = |s: u32, d: u32| match self {
+            BlendMode::Normal => s,
+            BlendMode::Opacity25 => (s + 3 * d) / 4,
+            BlendMode::Opacity50 => (s + d) / 2,
+            BlendMode::Opacity75 => (3 * s + d) / 4,
+            BlendMode::Add => std::cmp::min(0xFF, s + d),
+            BlendMode::Multiply => s * d / 0xFF,
+        };
+
+        [16, 8, 0].iter().fold(0xFF00_0000, |color, shift| {
+            color | (mix(channel(src, *shift), channel(dst, *shift)) << shift)
+        })
+    }
+}
+
+/// A sprite of palette indexes, ready to be drawn onto an `ArgbLayer`.
+#[derive(Debug, Clone)]
+pub struct ArgbSprite<'a> {
+    /// One palette index per pixel, a row at a time.
+    pub indexes: &'a [u8],
+    /// The number of pixels in each row.
+    pub width: u32,
+    /// The x location of the top left corner.
+    pub x: u32,
+    /// The y location of the top left corner.
+    pub y: u32,
+}
+
+/// The MEGA-CHIP's screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgbLayer {
+    /// The ARGB color of each pixel on the screen the user sees, in the same order as the grid's pixels.
+    pub shown: Vec<u32>,
+    /// The ARGB color of each pixel on the screen being drawn.
+    pub drawing: Vec<u32>,
+    /// The palette index last drawn on each pixel of the screen being drawn, or `TRANSPARENT` for none.
+    pub indexes: Vec<u8>,
+    /// How opaque the whole screen is, from 0 (invisible) to 255.
+    pub alpha: u8,
+}
+
+impl ArgbLayer {
+    /// A black screen with nothing drawn on it.
+    pub fn new(npixels: usize) -> Self {
+        ArgbLayer {
+            shown: vec![0; npixels],
+            drawing: vec![0; npixels],
+            indexes: vec![TRANSPARENT; npixels],
+            alpha: 0xFF,
+        }
+    }
+
+    /// Shows the screen being drawn, and starts drawing on a blank one.
+    pub fn flip(&mut self) {
+        std::mem::swap(&mut self.shown, &mut self.drawing);
+        self.drawing.iter_mut().for_each(|c| *c = 0);
+        self.indexes.iter_mut().for_each(|i| *i = TRANSPARENT);
+    }
+
+    /// Draws `sprite` onto the screen being drawn, which is `ncols` pixels wide, looking up its colors in `palette`
+    /// and blending them in with `mode`. Pixels that are transparent or off the edge of the screen are left out.
+    ///
+    /// Returns true if any pixel was drawn over one last drawn in palette index `collision_index`. A collision index
+    /// of `TRANSPARENT` means nothing ever collides.
+    pub fn draw(&mut self, ncols: u32, sprite: &ArgbSprite, palette: &[u32], mode: BlendMode, collision_index: u8) -> bool {
+        let nrows = self.indexes.len() as u32 / ncols;
+        let mut collision = false;
+        for (i, index) in sprite.indexes.iter().enumerate() {
+            let x = sprite.x + i as u32 % sprite.width;
+            let y = sprite.y + i as u32 / sprite.width;
+            if *index == TRANSPARENT || x >= ncols || y >= nrows {
+                continue;
+            }
+
+            let pos = (y * ncols + x) as usize;
+            if collision_index != TRANSPARENT && self.indexes[pos] == collision_index {
+                collision = true;
+            }
+            self.indexes[pos] = *index;
+            self.drawing[pos] = mode.blend(palette[*index as usize], self.drawing[pos]);
+        }
+        collision
+    }
+
+    /// Moves the screen being drawn (which is `ncols` pixels wide) right by `dx` pixels and down by `dy`
+    /// (left and up if they are negative). Whatever is uncovered is blank.
+    pub fn scroll(&mut self, ncols: u32, dx: i32, dy: i32) {
+        let nrows = (self.indexes.len() as u32 / ncols) as i32;
+        let ncols = ncols as i32;
+        let (old_colors, old_indexes) = (self.drawing.clone(), self.indexes.clone());
+        for y in 0..nrows {
+            for x in 0..ncols {
+                let (fromx, fromy) = (x - dx, y - dy);
+                let to = (y * ncols + x) as usize;
+                if fromx >= 0 && fromx < ncols && fromy >= 0 && fromy < nrows {
+                    let from = (fromy * ncols + fromx) as usize;
+                    self.drawing[to] = old_colors[from];
+                    self.indexes[to] = old_indexes[from];
+                } else {
+                    self.drawing[to] = 0;
+                    self.indexes[to] = TRANSPARENT;
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::argb::{ArgbLayer, ArgbSprite, BlendMode};
 use super::panel::{self, PanelData};
 use super::piston_window as pwindow;
 use super::{DrawingContext, Point32};
@@ -9,6 +10,22 @@ const DRAW_INTERVAL: usize = 1;
 const SPRITE_COLOR: &str = "001a00";
 /// The color of the Chip8 background (pixels that are off)
 const BACKGROUND_COLOR: &str = "e6ffcc";
+/// The color of pixels that are only on in the second XO-CHIP plane
+const PLANE2_COLOR: &str = "8fbf66";
+/// The color of pixels that are on in both XO-CHIP planes
+const BOTH_PLANES_COLOR: &str = "4d7333";
+/// The CHIP-8X background colors, in the order 02A0 steps through them: blue, black, green, and red.
+pub const BACKGROUND_PALETTE: [&str; 4] = ["000080", "000000", "008000", "800000"];
+/// The CHIP-8X foreground colors, indexed by the 3 bit color number Bxyn sets:
+/// black, red, blue, violet, green, yellow, aqua, and white.
+pub const FOREGROUND_PALETTE: [&str; 8] = ["000000", "ff0000", "0000ff", "ff00ff", "00ff00", "ffff00", "00ffff", "ffffff"];
+/// The foreground color every zone starts out in (red).
+pub const DEFAULT_FOREGROUND: u8 = 1;
+/// The bit for each plane in a plane mask. Plain Chip-8 and SCHIP programs only ever use the first plane.
+pub const PLANE_1: u8 = 0b01;
+pub const PLANE_2: u8 = 0b10;
+/// A plane mask with every plane in it.
+pub const ALL_PLANES: u8 = PLANE_1 | PLANE_2;
 /// The pixel scale factor
 pub const CHIP8_SCALE_FACTOR: f64 = 4.0;
 /// The width of the Chip-8 display in pixels before applying the scale factor
@@ -17,17 +34,46 @@ pub const CHIP8_WIDTH_BEFORE_SF: u32 = 128;
 pub const CHIP8_HEIGHT_BEFORE_SF: u32 = 64;
 
 /// Possible pixel colors
+///
+/// Each color is a combination of the planes a pixel is on in. Without XO-CHIP's second plane, pixels are only
+/// ever Black or White.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum Pxcolor {
-    /// Sprite color
+pub enum Pxcolor {
+    /// Sprite color (on in the first plane)
     Black,
-    /// Background color
+    /// Background color (off in every plane)
     White,
+    /// On in the second plane only
+    Plane2,
+    /// On in both planes
+    BothPlanes,
+}
+
+impl Pxcolor {
+    /// The plane mask of the planes this color is on in.
+    pub fn planes(self) -> u8 {
+        match self {
+            Pxcolor::White => 0,
+            Pxcolor::Black => PLANE_1,
+            Pxcolor::Plane2 => PLANE_2,
+            Pxcolor::BothPlanes => PLANE_1 | PLANE_2,
+        }
+    }
+
+    /// The color of a pixel that is on in the planes in the mask `planes`.
+    pub fn from_planes(planes: u8) -> Self {
+        match planes & ALL_PLANES {
+            0 => Pxcolor::White,
+            PLANE_1 => Pxcolor::Black,
+            PLANE_2 => Pxcolor::Plane2,
+            _ => Pxcolor::BothPlanes,
+        }
+    }
 }
 
 /// A Pixel is a virtual pixel - a solid black or solid white block at the appropriate scale factor.
 #[derive(Debug, Clone)]
-struct Pixel {
+pub struct Pixel {
     /// The x-location of this pixel in the scaled pixel screen
     pub x: u32,
     /// The y-location of this pixel in the scaled pixel screen
@@ -47,7 +93,7 @@ impl Pixel {
 
     /// Returns:
     ///
-    /// ```
+    /// ```text
     /// a       b       return
     /// BLACK   BLACK   WHITE
     /// BLACK   WHITE   BLACK
@@ -55,16 +101,28 @@ impl Pixel {
     /// WHITE   WHITE   WHITE
     /// ```
     ///
+    /// The other colors work the same way, one plane at a time.
     pub fn xor(a: Pxcolor, b: Pxcolor) -> Pxcolor {
-        match a {
-            Pxcolor::Black => match b {
-                Pxcolor::Black => Pxcolor::White,
-                Pxcolor::White => Pxcolor::Black,
-            },
-            Pxcolor::White => match b {
-                Pxcolor::Black => Pxcolor::Black,
-                Pxcolor::White => Pxcolor::White,
-            },
+        Pxcolor::from_planes(a.planes() ^ b.planes())
+    }
+}
+
+/// The CHIP-8X color board's view of the screen: one background color for the whole screen, and a foreground
+/// color (for the pixels that are on) for each pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorLayer {
+    /// Index into `BACKGROUND_PALETTE`.
+    pub background: u8,
+    /// Index into `FOREGROUND_PALETTE` for each pixel, in the same order as the grid's pixels.
+    pub foreground: Vec<u8>,
+}
+
+impl ColorLayer {
+    /// A blue background with every pixel in the default foreground color.
+    pub fn new(npixels: usize) -> Self {
+        ColorLayer {
+            background: 0,
+            foreground: vec![DEFAULT_FOREGROUND; npixels],
         }
     }
 }
@@ -74,7 +132,7 @@ impl Pixel {
 /// We ignore the scale factors for the pixels and simply treat them as if they are truly pixels.
 /// It is the Panel's responsibility for drawing the Pixels correctly based on the scale factors.
 #[derive(Debug, Clone)]
-struct PixelGrid {
+pub struct PixelGrid {
     /// Number of rows of pixels
     pub nrows: u32,
     /// Number of columns of pixels
@@ -85,6 +143,12 @@ struct PixelGrid {
     /// internally whenever we know that it has, but it is the Pixel8Panel's responsibility
     /// to set it back to false upon painting.
     pub has_changed: bool,
+    /// The CHIP-8X colors, if the color board is plugged in. Without it, the screen is drawn in the usual two colors
+    /// (four, with XO-CHIP's second plane).
+    pub colors: Option<ColorLayer>,
+    /// The MEGA-CHIP's true color screen, if MEGA-CHIP mode is on. While it is, the pixels themselves just say
+    /// whether anything has been drawn on them since the last flip.
+    pub argb: Option<ArgbLayer>,
 }
 
 impl PixelGrid {
@@ -98,69 +162,236 @@ impl PixelGrid {
         }
 
         PixelGrid {
-            nrows: nrows,
-            ncols: ncols,
-            pixels: pixels,
+            nrows,
+            ncols,
+            pixels,
             has_changed: true,
+            colors: None,
+            argb: None,
+        }
+    }
+
+    /// Changes the size of the grid to `nrows` by `ncols`, turning every pixel off (and resetting the colors,
+    /// if there are any).
+    pub fn resize(&mut self, nrows: u32, ncols: u32) {
+        let colors = self.colors.is_some();
+        let argb = self.argb.is_some();
+        *self = PixelGrid::new(nrows, ncols);
+        self.enable_colors(colors);
+        self.enable_argb(argb);
+    }
+
+    /// Turns the MEGA-CHIP's true color screen on (blank) or off.
+    pub fn enable_argb(&mut self, enable: bool) {
+        self.argb = if enable { Some(ArgbLayer::new(self.pixels.len())) } else { None };
+        self.has_changed = true;
+    }
+
+    /// Draws `sprite` onto the true color screen being drawn, with the colors in `palette` blended in with `mode`.
+    /// Returns true if it collided with something drawn in palette index `collision_index`.
+    /// Does nothing without a true color screen.
+    pub fn draw_argb(&mut self, sprite: &ArgbSprite, palette: &[u32], mode: BlendMode, collision_index: u8) -> bool {
+        let ncols = self.ncols;
+        let argb = match self.argb {
+            Some(ref mut argb) => argb,
+            None => return false,
+        };
+
+        let collision = argb.draw(ncols, sprite, palette, mode, collision_index);
+        for (pixel, index) in self.pixels.iter_mut().zip(argb.indexes.iter()) {
+            pixel.value = if *index == super::argb::TRANSPARENT { Pxcolor::White } else { Pxcolor::Black };
+        }
+        self.has_changed = true;
+        collision
+    }
+
+    /// Shows the true color screen being drawn and starts on a blank one, turning every pixel off.
+    /// Does nothing without a true color screen.
+    pub fn flip(&mut self) {
+        if let Some(ref mut argb) = self.argb {
+            argb.flip();
+            self.clear();
+        }
+    }
+
+    /// Plugs in the CHIP-8X color board (with every color reset) or takes it out.
+    pub fn enable_colors(&mut self, enable: bool) {
+        self.colors = if enable { Some(ColorLayer::new(self.pixels.len())) } else { None };
+        self.has_changed = true;
+    }
+
+    /// Steps the background to the next color in `BACKGROUND_PALETTE`, going back to the first after the last.
+    /// Does nothing without the color board.
+    pub fn cycle_background(&mut self) {
+        if let Some(ref mut colors) = self.colors {
+            colors.background = (colors.background + 1) % BACKGROUND_PALETTE.len() as u8;
+            self.has_changed = true;
+        }
+    }
+
+    /// Sets the foreground color of the `width` x `height` rectangle with its top left corner at (`x`, `y`) to
+    /// `color` (an index into `FOREGROUND_PALETTE`). Whatever is off the edge of the screen is left out.
+    /// Does nothing without the color board.
+    pub fn set_foreground(&mut self, x: u32, y: u32, width: u32, height: u32, color: u8) {
+        let ncols = self.ncols;
+        let nrows = self.nrows;
+        if let Some(ref mut colors) = self.colors {
+            let color = color % FOREGROUND_PALETTE.len() as u8;
+            for row in y..std::cmp::min(y.saturating_add(height), nrows) {
+                for col in x..std::cmp::min(x.saturating_add(width), ncols) {
+                    colors.foreground[(row * ncols + col) as usize] = color;
+                }
+            }
+            self.has_changed = true;
+        }
+    }
+
+    /// The hex color the pixel at the given x and y should be painted with the color board plugged in,
+    /// or None if it isn't.
+    pub fn palette_color_at(&self, x: usize, y: usize) -> Option<&'static str> {
+        let colors = self.colors.as_ref()?;
+        let idx = (y * self.ncols as usize) + x;
+        if self.pixels[idx].value == Pxcolor::White {
+            Some(BACKGROUND_PALETTE[colors.background as usize % BACKGROUND_PALETTE.len()])
+        } else {
+            Some(FOREGROUND_PALETTE[colors.foreground[idx] as usize % FOREGROUND_PALETTE.len()])
         }
     }
 
     pub fn clear(&mut self) {
+        self.clear_planes(ALL_PLANES);
+    }
+
+    /// Turns every pixel off in the planes in the mask `planes`, leaving the other planes alone.
+    pub fn clear_planes(&mut self, planes: u8) {
         for p in self.pixels.iter_mut() {
-            p.value = Pxcolor::White;
+            p.value = Pxcolor::from_planes(p.value.planes() & !planes);
         }
 
         self.has_changed = true;
     }
 
-    /// Adds the given sprite to the grid of pixels. If any part of this sprite overwrites any
-    /// part of any other sprite, true is returned.
-    pub fn add_sprite(&mut self, s: &sprite::Sprite) -> bool {
-        let spritex = s.x % CHIP8_WIDTH_BEFORE_SF;
-        let spritey = s.y % CHIP8_HEIGHT_BEFORE_SF;
-        let mut collision = false;
-        let start = spritey;
-        let end = spritey + s.rows.len() as u32;
+    /// Adds the given sprite to the grid of pixels, wrapping it around the edges (or cutting it off at them, if the sprite is clipped).
+    ///
+    /// The sprite is XORed onto each of the planes in its plane mask.
+    ///
+    /// Returns the number of rows of the sprite that overwrote some part of another sprite, that is,
+    /// that turned off at least one pixel that was on in one of those planes.
+    pub fn add_sprite(&mut self, s: &sprite::Sprite) -> usize {
+        let spritex = (s.x * s.scale) % self.ncols;
+        let spritey = (s.y * s.scale) % self.nrows;
+        let mut ncollisions = 0;
 
         // Iterate from the top of the sprite downwards over however many rows the sprite contains
-        for (byte, prewrap_y) in s.rows.iter().zip(start..end) {
-            let y = prewrap_y % CHIP8_HEIGHT_BEFORE_SF;
+        for row in 0..s.nrows() {
+            let mut collision = false;
 
-            // Each row in the sprite is a byte.
-            // Iterate over that byte from left to right.
-            for (bitidx, xadd) in (0..8).rev().zip(0..8) {
-                // Determine the x coordinate of this bit
-                let x = (spritex + xadd as u32) % CHIP8_WIDTH_BEFORE_SF;
+            // Each pixel of the sprite covers a scale x scale block of ours
+            for col in 0..s.width {
+                // We know whether or not the incoming pixel is occupied from the sprite
+                let incoming_pixel_value = if s.bit(row, col) { Pxcolor::from_planes(s.planes) } else { Pxcolor::White };
 
-                // Check the value of the sprite at that bit
-                let bit = if byte & (1 << bitidx) != 0 { 1 } else { 0 };
+                for dy in 0..s.scale {
+                    let y = spritey + row * s.scale + dy;
+                    if s.clip && y >= self.nrows {
+                        continue;
+                    }
+                    let y = y % self.nrows;
+                    for dx in 0..s.scale {
+                        // Determine the x coordinate of this bit
+                        let x = spritex + col * s.scale + dx;
+                        if s.clip && x >= self.ncols {
+                            continue;
+                        }
+                        let x = x % self.ncols;
+                        let our_pixel_value = self.get_pixel_at(x as usize, y as usize).value;
 
-                // We now have an x and a y for our pixel and whether or not the incoming pixel is occupied
-                let incoming_pixel_value = if bit == 1 { Pxcolor::Black } else { Pxcolor::White };
-                let our_pixel_value = self.get_pixel_at(x as usize, y as usize).value;
+                        // Check if the incoming pixel and our pixel are both on in the same plane. If so, that's a collision.
+                        if incoming_pixel_value.planes() & our_pixel_value.planes() != 0 {
+                            collision = true;
+                        }
 
-                // Check if incoming pixel value is BLACK and our pixel value is also BLACK. If so, that's a collision.
-                if (incoming_pixel_value == our_pixel_value) && (incoming_pixel_value == Pxcolor::Black) {
-                    collision = true;
+                        // XOR the two pixels.
+                        let xored_value = Pixel::xor(incoming_pixel_value, our_pixel_value);
+                        self.set_pixel_at(xored_value, x as usize, y as usize);
+                    }
                 }
+            }
 
-                // XOR the two pixels.
-                let xored_value = Pixel::xor(incoming_pixel_value, our_pixel_value);
-                self.set_pixel_at(xored_value, x as usize, y as usize);
+            if collision {
+                ncollisions += 1;
+            }
+        }
+
+        self.has_changed = true;
+        ncollisions
+    }
+
+    /// Moves everything in the planes in the mask `planes` right by `dx` pixels and down by `dy` (left and up if they
+    /// are negative). Whatever moves off the edge is gone, and whatever is uncovered is cleared. The other planes stay put.
+    pub fn scroll(&mut self, dx: i32, dy: i32, planes: u8) {
+        let old: Vec<u8> = self.pixels.iter().map(|p| p.value.planes()).collect();
+        for y in 0..self.nrows as i32 {
+            for x in 0..self.ncols as i32 {
+                let (fromx, fromy) = (x - dx, y - dy);
+                let inside = fromx >= 0 && fromx < self.ncols as i32 && fromy >= 0 && fromy < self.nrows as i32;
+                let moved = if inside { old[(fromy * self.ncols as i32 + fromx) as usize] } else { 0 };
+                let stayed = old[(y * self.ncols as i32 + x) as usize];
+                let value = Pxcolor::from_planes((moved & planes) | (stayed & !planes));
+                self.set_pixel_at(value, x as usize, y as usize);
+            }
+        }
+        if let Some(ref mut argb) = self.argb {
+            argb.scroll(self.ncols, dx, dy);
+        }
+
+        self.has_changed = true;
+    }
+
+    /// Packs the first plane of the screen, as a program drawing at `scale` sees it, into one bit per pixel: eight
+    /// pixels to a byte with the leftmost in the top bit, a row at a time. This is how the COSMAC VIP kept its
+    /// screen in RAM.
+    pub fn to_bitmap(&self, scale: u32) -> Vec<u8> {
+        let (width, height) = (self.ncols / scale, self.nrows / scale);
+        let mut bitmap = vec![0u8; (width * height / 8) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                if self.get_pixel_at((x * scale) as usize, (y * scale) as usize).value.planes() & PLANE_1 != 0 {
+                    let bit = y * width + x;
+                    bitmap[(bit / 8) as usize] |= 0x80 >> (bit % 8);
+                }
+            }
+        }
+        bitmap
+    }
+
+    /// Sets the first plane of the screen from a bitmap packed the way `to_bitmap` packs it.
+    pub fn load_bitmap(&mut self, bitmap: &[u8], scale: u32) {
+        let (width, height) = (self.ncols / scale, self.nrows / scale);
+        for y in 0..height {
+            for x in 0..width {
+                let bit = y * width + x;
+                let on = bitmap[(bit / 8) as usize] & (0x80 >> (bit % 8)) != 0;
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = ((x * scale + dx) as usize, (y * scale + dy) as usize);
+                        let planes = self.get_pixel_at(px, py).value.planes() & !PLANE_1;
+                        self.set_pixel_at(Pxcolor::from_planes(if on { planes | PLANE_1 } else { planes }), px, py);
+                    }
+                }
             }
         }
 
         self.has_changed = true;
-        collision
     }
 
     /// Get the pixel at the given x and y.
-    fn get_pixel_at<'a>(&'a self, x: usize, y: usize) -> &'a Pixel {
+    pub fn get_pixel_at(&self, x: usize, y: usize) -> &Pixel {
         &self.pixels[(y * self.ncols as usize) + x]
     }
 
     /// Set the value of the given pixel.
-    fn set_pixel_at(&mut self, val: Pxcolor, x: usize, y: usize) {
+    pub fn set_pixel_at(&mut self, val: Pxcolor, x: usize, y: usize) {
         let idx = (y * self.ncols as usize) + x;
         self.pixels[idx].value = val;
     }
@@ -180,7 +411,7 @@ impl panel::Panel for Chip8Panel {
     fn new(origin: Point32, height: u32, width: u32) -> Self {
         Chip8Panel {
             data: panel::PanelData::new(origin, height, width),
-            pixelgrid: PixelGrid::new(CHIP8_WIDTH_BEFORE_SF, CHIP8_HEIGHT_BEFORE_SF),
+            pixelgrid: PixelGrid::new(CHIP8_HEIGHT_BEFORE_SF, CHIP8_WIDTH_BEFORE_SF),
             draw_ticks: 0,
         }
     }
@@ -196,19 +427,36 @@ impl panel::Panel for Chip8Panel {
     }
 
     fn draw(&mut self, window: &mut pwindow::PistonWindow, event: &pwindow::Event, _args: DrawingContext) {
-        if self.pixelgrid.has_changed || self.draw_ticks % DRAW_INTERVAL == 0 {
+        if self.pixelgrid.has_changed || self.draw_ticks.is_multiple_of(DRAW_INTERVAL) {
             let spritecolor = pwindow::color::hex(SPRITE_COLOR);
             let backgroundcolor = pwindow::color::hex(BACKGROUND_COLOR);
-            let pixwidth = CHIP8_SCALE_FACTOR as u32;
+            let plane2color = pwindow::color::hex(PLANE2_COLOR);
+            let bothplanescolor = pwindow::color::hex(BOTH_PLANES_COLOR);
+            // Stretch however many pixels the grid has over the whole panel
+            let ncols = self.pixelgrid.ncols as usize;
+            let pixwidth = self.data.width_npixels as f64 / self.pixelgrid.ncols as f64;
+            let pixheight = self.data.height_npixels as f64 / self.pixelgrid.nrows as f64;
 
             window.draw_2d(event, |context, graphics| {
-                for pixel in self.pixelgrid.pixels.iter() {
-                    let xored_color = if pixel.value == Pxcolor::Black { spritecolor } else { backgroundcolor };
-                    let x1 = pixel.x as f64;
-                    let y1 = pixel.y as f64;
-                    let x2 = ((pixel.x + pixwidth) % CHIP8_WIDTH_BEFORE_SF) as f64;
-                    let y2 = ((pixel.y + pixwidth) % CHIP8_HEIGHT_BEFORE_SF) as f64;
-                    let rect = [x1, y1, x2, y2];
+                for (idx, pixel) in self.pixelgrid.pixels.iter().enumerate() {
+                    // On the MEGA-CHIP's true color screen, or with the CHIP-8X color board, the colors are right there.
+                    // Otherwise, it's down to which planes are on.
+                    let palette_color = self.pixelgrid.palette_color_at(idx % ncols, idx / ncols);
+                    let xored_color = match (&self.pixelgrid.argb, palette_color) {
+                        (Some(argb), _) => {
+                            let channel = |shift: u32| ((argb.shown[idx] >> shift) & 0xFF) as f32 / 255.0;
+                            [channel(16), channel(8), channel(0), argb.alpha as f32 / 255.0]
+                        },
+                        (None, Some(hex)) => pwindow::color::hex(hex),
+                        (None, None) => match pixel.value {
+                            Pxcolor::Black => spritecolor,
+                            Pxcolor::White => backgroundcolor,
+                            Pxcolor::Plane2 => plane2color,
+                            Pxcolor::BothPlanes => bothplanescolor,
+                        },
+                    };
+                    let (col, row) = ((idx % ncols) as f64, (idx / ncols) as f64);
+                    let rect = [col * pixwidth, row * pixheight, pixwidth, pixheight];
                     pwindow::rectangle(xored_color, rect, context.transform, graphics);
                 }
             });
@@ -224,9 +472,13 @@ impl panel::Panel for Chip8Panel {
 }
 
 impl Chip8Panel {
-    /// Adds the given Sprite to the display the next time draw() is called. Returns true if any part of
-    /// this Sprite overwrites any part of any Sprite already in existence.
-    pub fn add_sprite(&mut self, spr: sprite::Sprite) -> bool {
-        self.pixelgrid.add_sprite(&spr)
+    /// The pixels that will be drawn the next time draw() is called.
+    pub fn pixelgrid(&self) -> &PixelGrid {
+        &self.pixelgrid
+    }
+
+    /// Mutable access to the pixels that will be drawn the next time draw() is called.
+    pub fn pixelgrid_mut(&mut self) -> &mut PixelGrid {
+        &mut self.pixelgrid
     }
 }
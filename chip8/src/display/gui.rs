@@ -1,12 +1,15 @@
 //! The GUI
 
 use super::panel::Panel;
-use super::chip8panel::{self, Chip8Panel};
+use super::chip8panel::{self, Chip8Panel, PixelGrid};
 use super::rampanel::RamPanel;
 use super::stackpanel::StackPanel;
 use super::piston_window as pwindow;
-use super::sprite;
 use super::{Point32, DrawingContext};
+use std::collections::VecDeque;
+use self::pwindow::{PressEvent, ReleaseEvent, RenderEvent};
+use crate::emulator::backend::{DisplayBackend, UserRequest};
+use crate::emulator::keyboard::{self, Keypad};
 
 /// Width of the whole GUI in pixels
 const WIDTH_NPIXELS: u32 = 640;
@@ -30,7 +33,11 @@ const BORDER_RADIUS: f64 = 3.0;
 /// The whole GUI for the Chip8, including several Panels.
 pub struct Gui {
     chip8_panel: Chip8Panel,
+    /// The keypad that key presses in the window get forwarded to.
+    keypad: Keypad,
     ram_panel: RamPanel,
+    /// Things the user has asked for with hotkeys that the emulator hasn't picked up yet.
+    requests: VecDeque<UserRequest>,
     stack_panel: StackPanel,
     window: pwindow::PistonWindow,
 }
@@ -39,14 +46,17 @@ impl Gui {
     pub fn new() -> Self {
         Gui {
             chip8_panel: Chip8Panel::new(Point32{x: 0, y: 0}, CHIP8_HEIGHT_AFTER_SF, CHIP8_WIDTH_AFTER_SF),
+            keypad: Keypad::new(),
             ram_panel: RamPanel::new(Point32{x: 0, y: CHIP8_HEIGHT_AFTER_SF + BORDER_RADIUS as u32}, BOTTOM_PANEL_HEIGHT_NPIXELS, BOTTOM_PANEL_WIDTH_NPIXELS),
+            requests: VecDeque::new(),
             stack_panel: StackPanel::new(Point32{x: CHIP8_WIDTH_AFTER_SF + BORDER_RADIUS as u32, y: 0}, RIGHT_PANEL_HEIGHT_NPIXELS, RIGHT_PANEL_WIDTH_NPIXELS),
             window: pwindow::WindowSettings::new("CHIP-8", [WIDTH_NPIXELS, HEIGHT_NPIXELS]).exit_on_esc(true).build().unwrap(),
         }
     }
 
-    pub fn next(&mut self) -> Option<pwindow::Event> {
-        self.window.next()
+    /// A handle to the keypad that this window forwards its key presses to.
+    pub fn keypad(&self) -> Keypad {
+        self.keypad.clone()
     }
 
     pub fn clear_chip8(&mut self, event: &pwindow::Event) {
@@ -83,16 +93,11 @@ impl Gui {
         });
     }
 
-    /// Returns true if any pixel in the given sprite overwrites any pixels of any sprites already in the panel.
-    pub fn draw_sprite(&mut self, spr: sprite::Sprite) -> bool {
-        self.chip8_panel.add_sprite(spr)
-    }
-
     /// Draw the video game display
     ///
     /// Draws the pixels in this object's internal representation of the game display.
     /// Emulated instructions should change the internal representation, and then this
-    /// function should get called once per emulation cycle, or perhaps only whenever
+    /// function should get called once per frame, or perhaps only whenever
     /// anything has changed in the display.
     pub fn draw_chip8(&mut self, event: &pwindow::Event) {
         self.chip8_panel.draw(&mut self.window, event, DrawingContext {
@@ -125,3 +130,123 @@ impl Gui {
         });
     }
 }
+
+impl Default for Gui {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DisplayBackend for Gui {
+    fn framebuffer(&self) -> &PixelGrid {
+        self.chip8_panel.pixelgrid()
+    }
+
+    fn framebuffer_mut(&mut self) -> &mut PixelGrid {
+        self.chip8_panel.pixelgrid_mut()
+    }
+
+    fn update(&mut self, pc: u16, ram: &[u8], sp: u8, stack: &[u16]) -> bool {
+        // Handle everything that has happened in the window since last frame, up to the next chance to draw
+        while let Some(event) = self.window.next() {
+            // Forward any keys that map onto the hex keypad, and pick up any hotkeys
+            if let Some(pwindow::Button::Keyboard(key)) = event.press_args() {
+                if let Some(k) = map_key(key) {
+                    self.keypad.press(k);
+                } else if let Some(k) = map_second_key(key) {
+                    self.keypad.press_second(k);
+                } else if let Some(request) = map_hotkey(key, true) {
+                    self.requests.push_back(request);
+                }
+            }
+            if let Some(pwindow::Button::Keyboard(key)) = event.release_args() {
+                if let Some(k) = map_key(key) {
+                    self.keypad.release(k);
+                } else if let Some(k) = map_second_key(key) {
+                    self.keypad.release_second(k);
+                } else if let Some(request) = map_hotkey(key, false) {
+                    self.requests.push_back(request);
+                }
+            }
+
+            // Draw everything
+            if event.render_args().is_some() {
+                self.draw_chip8(&event);
+                self.draw_ram(&event, pc, ram);
+                self.draw_stack(&event, sp, stack);
+                self.draw_paneling(&event);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn poll_request(&mut self) -> Option<UserRequest> {
+        self.requests.pop_front()
+    }
+}
+
+/// Maps a key on the computer's keyboard being `pressed` (or released) to something the user wants the emulator
+/// to do, if it is a hotkey.
+///
+/// F5 saves the state and F9 loads it back. Holding Backspace rewinds.
+fn map_hotkey(key: pwindow::Key, pressed: bool) -> Option<UserRequest> {
+    match (key, pressed) {
+        (pwindow::Key::F5, true) => Some(UserRequest::SaveState),
+        (pwindow::Key::F9, true) => Some(UserRequest::LoadState),
+        (pwindow::Key::Backspace, true) => Some(UserRequest::StartRewind),
+        (pwindow::Key::Backspace, false) => Some(UserRequest::StopRewind),
+        _ => None,
+    }
+}
+
+/// Maps a key on the computer's keyboard to a key on the hex keypad, if it is part of the keypad layout.
+fn map_key(key: pwindow::Key) -> Option<u8> {
+    let name = match key {
+        pwindow::Key::D1 => "1",
+        pwindow::Key::D2 => "2",
+        pwindow::Key::D3 => "3",
+        pwindow::Key::D4 => "4",
+        pwindow::Key::Q => "Q",
+        pwindow::Key::W => "W",
+        pwindow::Key::E => "E",
+        pwindow::Key::R => "R",
+        pwindow::Key::A => "A",
+        pwindow::Key::S => "S",
+        pwindow::Key::D => "D",
+        pwindow::Key::F => "F",
+        pwindow::Key::Z => "Z",
+        pwindow::Key::X => "X",
+        pwindow::Key::C => "C",
+        pwindow::Key::V => "V",
+        _ => return None,
+    };
+
+    keyboard::inverse_map(name.to_string()).ok()
+}
+
+/// Maps a key on the computer's keyboard to a key on the CHIP-8X's second hex keypad, if it is on the number pad.
+///
+/// The digits are themselves, and /, *, -, +, Enter, and . are A through F.
+fn map_second_key(key: pwindow::Key) -> Option<u8> {
+    Some(match key {
+        pwindow::Key::NumPad0 => 0x0,
+        pwindow::Key::NumPad1 => 0x1,
+        pwindow::Key::NumPad2 => 0x2,
+        pwindow::Key::NumPad3 => 0x3,
+        pwindow::Key::NumPad4 => 0x4,
+        pwindow::Key::NumPad5 => 0x5,
+        pwindow::Key::NumPad6 => 0x6,
+        pwindow::Key::NumPad7 => 0x7,
+        pwindow::Key::NumPad8 => 0x8,
+        pwindow::Key::NumPad9 => 0x9,
+        pwindow::Key::NumPadDivide => 0xA,
+        pwindow::Key::NumPadMultiply => 0xB,
+        pwindow::Key::NumPadMinus => 0xC,
+        pwindow::Key::NumPadPlus => 0xD,
+        pwindow::Key::NumPadEnter => 0xE,
+        pwindow::Key::NumPadPeriod => 0xF,
+        _ => return None,
+    })
+}
@@ -0,0 +1,41 @@
+//! A display that never opens a window. The Chip-8 screen only exists in memory.
+
+use super::chip8panel::{self, PixelGrid};
+use crate::emulator::backend::DisplayBackend;
+
+/// A DisplayBackend that keeps the Chip-8 screen in memory and draws it nowhere.
+#[derive(Debug, Clone)]
+pub struct HeadlessDisplay {
+    /// The pixels of the Chip-8 screen.
+    pixelgrid: PixelGrid,
+}
+
+impl HeadlessDisplay {
+    pub fn new() -> Self {
+        HeadlessDisplay {
+            pixelgrid: PixelGrid::new(chip8panel::CHIP8_HEIGHT_BEFORE_SF, chip8panel::CHIP8_WIDTH_BEFORE_SF),
+        }
+    }
+}
+
+impl Default for HeadlessDisplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DisplayBackend for HeadlessDisplay {
+    fn framebuffer(&self) -> &PixelGrid {
+        &self.pixelgrid
+    }
+
+    fn framebuffer_mut(&mut self) -> &mut PixelGrid {
+        &mut self.pixelgrid
+    }
+
+    fn update(&mut self, _pc: u16, _ram: &[u8], _sp: u8, _stack: &[u16]) -> bool {
+        // Nobody is looking, so there is nothing to paint.
+        self.pixelgrid.has_changed = false;
+        true
+    }
+}
@@ -5,11 +5,13 @@ extern crate piston_window;
 extern crate rusttype;
 
 /* Public interfaces */
+pub mod argb;
+pub mod chip8panel;
 pub mod gui;
+pub mod headless;
 pub mod sprite;
 
 /* Internal mods */
-mod chip8panel;
 mod panel;
 mod rampanel;
 mod stackpanel;
@@ -103,7 +103,7 @@ impl PanelData {
         PanelData {
             height_npixels: height,
             width_npixels: width,
-            origin: origin,
+            origin,
         }
     }
 }
@@ -34,7 +34,7 @@ impl panel::Panel for RamPanel {
         let pc = args.pc.expect("RAM Panel's draw() method requires a PC as part of its context, but none was found.");
         let ram = args.ram.expect("RAM Panel's draw() method requires a RAM as part of its context, but none was found.");
 
-        if !self.args_already_cached(pc, &ram) || self.draw_ticks % DRAW_INTERVAL == 0 {
+        if !self.args_already_cached(pc, &ram) || self.draw_ticks.is_multiple_of(DRAW_INTERVAL) {
             // Do the drawing, since we haven't done this one yet; then update the cache.
             self.draw_no_cache_check(window, event, pc, &ram);
             self.update_cache(pc, &ram);
@@ -79,13 +79,13 @@ impl RamPanel {
     }
 
     /// Updates the cache with the given args.
-    fn update_cache(&mut self, pc: u16, ram: &Vec<u8>) {
+    fn update_cache(&mut self, pc: u16, ram: &[u8]) {
         self.cached_pc = Some(pc);
-        self.cached_ram = Some(ram.clone());
+        self.cached_ram = Some(ram.to_vec());
     }
 
     /// Don't check the cache, just draw the Panel.
-    fn draw_no_cache_check(&mut self, window: &mut pwindow::PistonWindow, event: &pwindow::Event, pc: u16, ram: &Vec<u8>) {
+    fn draw_no_cache_check(&mut self, window: &mut pwindow::PistonWindow, event: &pwindow::Event, pc: u16, ram: &[u8]) {
         // Construct the glyph cache
         let font_data: &[u8] = include_bytes!("../../assets/fonts/roboto/Roboto-Regular.ttf");
         let font: rusttype::Font<'static> = rusttype::Font::from_bytes(font_data).expect("Fatal error: Corrupt font binary?");
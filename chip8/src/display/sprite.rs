@@ -1,37 +1,95 @@
 //! Module to contain a Sprite struct.
 
+use super::chip8panel::PLANE_1;
+
 /// A graphical sprite.
 ///
-/// Each sprite consists of up to 15 rows of eight pixels each.
+/// Each sprite consists of rows of either eight pixels (one byte) or sixteen pixels (two bytes, left half first).
 #[derive(Debug, Clone)]
 pub struct Sprite {
     /// The x-coordinate of the top left pixel of this sprite.
     pub x: u32,
     /// The y-coordinate of the top left pixel of this sprite.
     pub y: u32,
-    /// Vector of bytes. Each byte is interpreted as a row of eight pixels.
+    /// Vector of bytes. Each byte (or pair of bytes, for a sixteen pixel wide sprite) is interpreted as a row of pixels.
     pub rows: Vec<u8>,
+    /// The number of pixels in each row: 8 or 16.
+    pub width: u32,
+    /// How many screen pixels wide and tall each of the sprite's pixels is. The position gets scaled too.
+    pub scale: u32,
+    /// The mask of the planes this sprite gets drawn onto. Everything but XO-CHIP only has the first one.
+    pub planes: u8,
+    /// Are the parts of the sprite that hang off the edge of the screen cut off? If not, they wrap around.
+    pub clip: bool,
 }
 
 impl std::fmt::Display for Sprite {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for byte in self.rows.iter() {
-            for i in (0..8).rev() {
-                let c = if byte & (1 << i) != 0 { "#" } else { " " };
+        for row in 0..self.nrows() {
+            for col in 0..self.width {
+                let c = if self.bit(row, col) { "#" } else { " " };
                 write!(f, "{}", c)?;
             }
-            writeln!(f, "")?;
+            writeln!(f)?;
         }
         Ok(())
     }
 }
 
 impl Sprite {
-    pub fn new(r: &Vec<u8>, locationx: u32, locationy: u32) -> Self {
+    pub fn new(r: &[u8], locationx: u32, locationy: u32) -> Self {
         Sprite {
             x: locationx,
             y: locationy,
-            rows: r.clone(),
+            rows: r.to_vec(),
+            width: 8,
+            scale: 1,
+            planes: PLANE_1,
+            clip: false,
+        }
+    }
+
+    /// A sixteen pixel wide sprite, where every two bytes of `r` make up a row.
+    pub fn wide(r: &[u8], locationx: u32, locationy: u32) -> Self {
+        Sprite {
+            width: 16,
+            ..Sprite::new(r, locationx, locationy)
         }
     }
+
+    /// The same sprite, but with every pixel (and the position) blown up by `scale`.
+    pub fn scaled(self, scale: u32) -> Self {
+        Sprite {
+            scale,
+            ..self
+        }
+    }
+
+    /// The same sprite, but drawn onto the planes in the mask `planes` instead.
+    pub fn on_planes(self, planes: u8) -> Self {
+        Sprite {
+            planes,
+            ..self
+        }
+    }
+
+    /// The same sprite, but cut off at the edges of the screen if `clip` is true instead of wrapping around.
+    pub fn clipped(self, clip: bool) -> Self {
+        Sprite {
+            clip,
+            ..self
+        }
+    }
+
+    /// The number of rows of pixels in this sprite.
+    pub fn nrows(&self) -> u32 {
+        self.rows.len() as u32 / (self.width / 8)
+    }
+
+    /// Is the pixel at the given row and column (counting from the top left) on?
+    pub fn bit(&self, row: u32, col: u32) -> bool {
+        let bytes_per_row = self.width / 8;
+        let byte = self.rows[(row * bytes_per_row + col / 8) as usize];
+        byte & (0x80 >> (col % 8)) != 0
+    }
 }
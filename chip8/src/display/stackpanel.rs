@@ -34,7 +34,7 @@ impl panel::Panel for StackPanel {
         let sp = args.sp.expect("Stack Panel's draw() method requires a SP as part of its context, but none was provided.");
         let stack = args.stack.expect("Stack Panel's draw() method requires a stack as part of its context, but none was provided.");
 
-        if !self.args_already_cached(sp, &stack) || self.draw_ticks % DRAW_INTERVAL == 0 {
+        if !self.args_already_cached(sp, &stack) || self.draw_ticks.is_multiple_of(DRAW_INTERVAL) {
             // Do the drawing, since we haven't done this one yet; then update the cache.
             self.draw_no_cache_check(window, event, sp, &stack);
             self.update_cache(sp, &stack);
@@ -79,13 +79,13 @@ impl StackPanel {
     }
 
     /// Updates the cache with the given args.
-    fn update_cache(&mut self, sp: u8, stack: &Vec<u16>) {
+    fn update_cache(&mut self, sp: u8, stack: &[u16]) {
         self.cached_sp = Some(sp);
-        self.cached_stack = Some(stack.clone());
+        self.cached_stack = Some(stack.to_vec());
     }
 
     /// Don't check the cache, just draw the Panel.
-    fn draw_no_cache_check(&mut self, window: &mut pwindow::PistonWindow, event: &pwindow::Event, sp: u8, stack: &Vec<u16>) {
+    fn draw_no_cache_check(&mut self, window: &mut pwindow::PistonWindow, event: &pwindow::Event, sp: u8, stack: &[u16]) {
         // Construct the glyph cache
         let font_data: &[u8] = include_bytes!("../../assets/fonts/roboto/Roboto-Regular.ttf");
         let font: rusttype::Font<'static> = rusttype::Font::from_bytes(font_data).expect("Fatal error: Corrupt font binary?");
@@ -0,0 +1,164 @@
+//! This module contains the traits that separate the emulator core from whatever is presenting it to the user.
+//!
+//! The core only ever talks to a display, an input, and an audio backend through these traits, so it can run
+//! inside the piston GUI or completely in memory with no window at all.
+
+use super::display::chip8panel::PixelGrid;
+use super::display::gui;
+use super::display::headless::HeadlessDisplay;
+use super::display::sprite;
+use super::keyboard::Keypad;
+
+/// Things the user can ask the emulator to do through the display (with a hotkey, say), rather than through the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserRequest {
+    /// Save the whole machine to the save state file.
+    SaveState,
+    /// Load the whole machine back from the save state file.
+    LoadState,
+    /// Start running time backwards, a frame at a time, until told to stop.
+    StartRewind,
+    /// Stop running time backwards and carry on from wherever we got to.
+    StopRewind,
+}
+
+/// Something that can show the Chip-8 screen (and possibly some debug information) to the user.
+pub trait DisplayBackend {
+    /// Clear the planes in the mask `planes` of the Chip-8 screen. Clearing every plane sets every pixel back to the background color.
+    fn clear(&mut self, planes: u8) {
+        self.framebuffer_mut().clear_planes(planes);
+    }
+
+    /// XOR the given sprite onto the Chip-8 screen. Returns the number of rows of the sprite that turned off a pixel that was on.
+    fn draw_sprite(&mut self, spr: sprite::Sprite) -> usize {
+        self.framebuffer_mut().add_sprite(&spr)
+    }
+
+    /// Move everything in the planes in the mask `planes` of the Chip-8 screen right by `dx` pixels and down by `dy`
+    /// (left and up if they are negative).
+    fn scroll(&mut self, dx: i32, dy: i32, planes: u8) {
+        self.framebuffer_mut().scroll(dx, dy, planes);
+    }
+
+    /// The pixels currently on the Chip-8 screen.
+    fn framebuffer(&self) -> &PixelGrid;
+
+    /// Mutable access to the pixels currently on the Chip-8 screen.
+    fn framebuffer_mut(&mut self) -> &mut PixelGrid;
+
+    /// Called at the start of every 60 Hz frame with the parts of the machine the backend may want to show.
+    ///
+    /// Returns false if the user has closed the display and the emulator should stop.
+    fn update(&mut self, pc: u16, ram: &[u8], sp: u8, stack: &[u16]) -> bool;
+
+    /// Returns the next thing the user has asked for since the last call, if there is anything.
+    fn poll_request(&mut self) -> Option<UserRequest> {
+        None
+    }
+}
+
+/// Something that can tell the emulator which keys on the hex keypad are being pushed.
+pub trait InputBackend {
+    /// Returns true if the key with the given value (0x0 through 0xF) is currently held down.
+    fn is_key_pressed(&mut self, key: u8) -> bool;
+
+    /// Returns true if the key with the given value is currently held down on the CHIP-8X's second keypad.
+    /// Most inputs only have the one keypad, so by default nothing is ever pressed on the second.
+    fn is_second_key_pressed(&mut self, _key: u8) -> bool {
+        false
+    }
+
+    /// Returns the value of a key that has been pressed since the last time this was called, if there is one.
+    ///
+    /// This must not block: the emulator calls it once per cycle for as long as it is waiting on a key.
+    fn poll_keypress(&mut self) -> Option<u8>;
+}
+
+/// Something that can play the Chip-8's single tone.
+pub trait AudioBackend {
+    /// Called at the start of every 60 Hz frame. `on` is true whenever the sound timer is non-zero.
+    fn set_tone(&mut self, on: bool);
+
+    /// Starts playing the MEGA-CHIP's digitized sound: unsigned 8 bit `samples` at `rate_hz`, over and over if
+    /// `looping`. Anything already playing stops. By default, nothing is played.
+    fn play_samples(&mut self, _samples: &[u8], _rate_hz: u32, _looping: bool) {}
+
+    /// Stops the digitized sound, if it is playing.
+    fn stop_samples(&mut self) {}
+}
+
+/// An audio backend that plays nothing, but remembers what it was asked to do.
+#[derive(Debug, Default)]
+pub struct SilentAudio {
+    /// Is the tone currently supposed to be playing?
+    pub tone_on: bool,
+    /// The number of times the tone has been started.
+    pub ntones: usize,
+    /// The number of samples in the digitized sound that is supposed to be playing, if there is one.
+    pub samples_playing: Option<usize>,
+}
+
+impl AudioBackend for SilentAudio {
+    fn set_tone(&mut self, on: bool) {
+        if on && !self.tone_on {
+            self.ntones += 1;
+        }
+        self.tone_on = on;
+    }
+
+    fn play_samples(&mut self, samples: &[u8], _rate_hz: u32, _looping: bool) {
+        self.samples_playing = Some(samples.len());
+    }
+
+    fn stop_samples(&mut self) {
+        self.samples_playing = None;
+    }
+}
+
+/// An audio backend that rings the terminal bell every time the tone starts.
+#[derive(Debug, Default)]
+pub struct TerminalBell {
+    /// Is the tone currently supposed to be playing?
+    tone_on: bool,
+}
+
+impl AudioBackend for TerminalBell {
+    fn set_tone(&mut self, on: bool) {
+        if on && !self.tone_on {
+            print!("\x07");
+        }
+        self.tone_on = on;
+    }
+}
+
+/// The full set of backends a Chip8 needs.
+pub struct Backend {
+    /// Where the Chip-8 screen goes.
+    pub display: Box<dyn DisplayBackend>,
+    /// Where the keypad comes from.
+    pub input: Box<dyn InputBackend>,
+    /// Where the tone goes.
+    pub audio: Box<dyn AudioBackend>,
+}
+
+impl Backend {
+    /// The piston GUI, reading keys from the GUI window and ringing the terminal bell for sound.
+    pub fn gui() -> Self {
+        let user_interface = gui::Gui::new();
+        let keypad = user_interface.keypad();
+        Backend {
+            display: Box::new(user_interface),
+            input: Box::new(keypad),
+            audio: Box::new(TerminalBell::default()),
+        }
+    }
+
+    /// Everything in memory: no window, no keys pressed unless someone presses them on `keypad`, and no sound.
+    pub fn headless(keypad: Keypad) -> Self {
+        Backend {
+            display: Box::new(HeadlessDisplay::new()),
+            input: Box::new(keypad),
+            audio: Box::new(SilentAudio::default()),
+        }
+    }
+}
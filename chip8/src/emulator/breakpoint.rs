@@ -0,0 +1,302 @@
+//! This module contains the breakpoints and watchpoints that a debugger sets on the machine, as opposed to the BRKs
+//! that get assembled into the program.
+//!
+//! Breakpoints on an address or a kind of instruction stop the machine before the instruction runs, just like a BRK.
+//! Watchpoints on RAM, the V registers, I, or the screen stop it right after the instruction that touched what they
+//! watch. The screen isn't in RAM, so it gets watchpoints of its own.
+
+use super::display::chip8panel::PixelGrid;
+use super::opcode::{Opcode, MNEMONICS};
+use std::ops::Range;
+
+/// The number of V registers that watchpoints can watch.
+pub const NUM_WATCHED_REGISTERS: usize = 16;
+
+/// What a watchpoint watches for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// An instruction reads it. Fetching instructions doesn't count.
+    Read,
+    /// An instruction writes to it, even if it writes what was already there.
+    Write,
+    /// Its value changes.
+    Change,
+}
+
+/// What makes a breakpoint go off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// The PC reaches the address.
+    Address(u16),
+    /// An instruction with the mnemonic (like "DRW" or "SYS", see `Opcode::mnemonic`) is about to run.
+    Instruction(String),
+    /// Any of the `len` bytes of RAM starting at `start` are accessed.
+    Memory { start: usize, len: usize, access: Access },
+    /// Register Vx is accessed.
+    Register { x: u8, access: Access },
+    /// Register I is accessed.
+    Index(Access),
+    /// Any of the screen's pixels in the `width` by `height` rectangle with its top left corner at (`x`, `y`) are
+    /// turned on or off, or change planes. These are in the screen's own pixels, like `set_pixel` takes.
+    Pixels { x: u32, y: u32, width: u32, height: u32 },
+    /// A sprite collides with something already on the screen.
+    Collision,
+    /// A CLS is about to clear the screen.
+    Clear,
+}
+
+impl Condition {
+    /// Is this a watchpoint, which is checked after each instruction rather than before it?
+    pub fn is_watchpoint(&self) -> bool {
+        match self {
+            Condition::Address(_) | Condition::Instruction(_) | Condition::Clear => false,
+            Condition::Memory { .. } | Condition::Register { .. } | Condition::Index(_) | Condition::Pixels { .. }
+                | Condition::Collision => true,
+        }
+    }
+}
+
+/// A breakpoint or watchpoint that a debugger has set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// What the debugger calls it by. Ids are never reused.
+    pub id: usize,
+    /// What makes it go off.
+    pub condition: Condition,
+    /// Does it stop the machine? Disabled breakpoints are kept around, but never go off.
+    pub enabled: bool,
+    /// How many times it has gone off.
+    pub hits: u64,
+}
+
+/// What one instruction read and wrote, as far as the watchpoints are concerned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Footprint {
+    /// The RAM it read from.
+    pub memory_read: Vec<Range<usize>>,
+    /// The RAM it wrote to.
+    pub memory_written: Vec<Range<usize>>,
+    /// The registers it read, with bit x for Vx.
+    pub registers_read: u16,
+    /// The registers it wrote to, with bit x for Vx.
+    pub registers_written: u16,
+    /// Did it read I?
+    pub index_read: bool,
+    /// Did it write to I?
+    pub index_written: bool,
+}
+
+impl Footprint {
+    /// Counts register Vx as read.
+    pub fn read_register(&mut self, x: u8) {
+        self.registers_read |= 1 << (x & 0x0F);
+    }
+
+    /// Counts register Vx as written to.
+    pub fn write_register(&mut self, x: u8) {
+        self.registers_written |= 1 << (x & 0x0F);
+    }
+}
+
+/// The things watchpoints watch, as they are at some moment.
+pub struct Watched<'a> {
+    /// All of RAM.
+    pub memory: &'a [u8],
+    /// V0 through VF.
+    pub registers: [u8; NUM_WATCHED_REGISTERS],
+    /// Register I.
+    pub index: u32,
+    /// The screen.
+    pub framebuffer: &'a PixelGrid,
+    /// Did the instruction that just ran draw a sprite that collided with something? Before an instruction,
+    /// this doesn't matter.
+    pub collided: bool,
+}
+
+/// The breakpoints and watchpoints a debugger has set, in the order it set them.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    /// Every breakpoint, enabled or not.
+    list: Vec<Breakpoint>,
+    /// The id the next breakpoint gets.
+    next_id: usize,
+    /// What each memory or pixel watchpoint was watching before the current instruction, in the same order as
+    /// `list`. Pixels are kept as the mask of the planes they are on in.
+    before: Vec<Vec<u8>>,
+    /// The registers before the current instruction.
+    registers_before: [u8; NUM_WATCHED_REGISTERS],
+    /// Register I before the current instruction.
+    index_before: u32,
+}
+
+impl Breakpoints {
+    /// No breakpoints at all.
+    pub fn new() -> Self {
+        Breakpoints::default()
+    }
+
+    /// Adds an enabled breakpoint that goes off on `condition`, and returns its id.
+    pub fn add(&mut self, condition: Condition) -> Result<usize, String> {
+        let condition = match condition {
+            Condition::Instruction(ref mnemonic) => {
+                let upper = mnemonic.to_uppercase();
+                if !MNEMONICS.contains(&upper.as_str()) {
+                    return Err(format!("{} is not an instruction. Try one of {}.", mnemonic, MNEMONICS.join(", ")));
+                }
+                Condition::Instruction(upper)
+            },
+            Condition::Memory { len: 0, .. } => return Err("A memory watchpoint has to watch at least one byte.".to_string()),
+            Condition::Pixels { width: 0, .. } | Condition::Pixels { height: 0, .. } => {
+                return Err("A pixel watchpoint has to watch at least one pixel.".to_string());
+            },
+            Condition::Register { x, .. } if x as usize >= NUM_WATCHED_REGISTERS => {
+                return Err(format!("There is no register V{:X} to watch.", x));
+            },
+            c => c,
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.list.push(Breakpoint { id, condition, enabled: true, hits: 0 });
+        self.before.push(Vec::new());
+        Ok(id)
+    }
+
+    /// Removes the breakpoint with id `id`.
+    pub fn remove(&mut self, id: usize) -> Result<(), String> {
+        let n = self.position(id)?;
+        self.list.remove(n);
+        self.before.remove(n);
+        Ok(())
+    }
+
+    /// Enables or disables the breakpoint with id `id`.
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> Result<(), String> {
+        let n = self.position(id)?;
+        self.list[n].enabled = enabled;
+        Ok(())
+    }
+
+    /// Every breakpoint, enabled or not, in the order they were added.
+    pub fn list(&self) -> &[Breakpoint] {
+        &self.list
+    }
+
+    /// Are any watchpoints enabled? If not, there is no need to work out what each instruction touches.
+    pub fn watching(&self) -> bool {
+        self.list.iter().any(|bp| bp.enabled && bp.condition.is_watchpoint())
+    }
+
+    /// Checks the breakpoints that go off before the instruction `opcode` at `pc` runs, counting a hit on each one
+    /// that does. Returns their ids.
+    pub fn check_before(&mut self, pc: u16, opcode: Opcode) -> Vec<usize> {
+        let mut ids = Vec::new();
+        for bp in self.list.iter_mut().filter(|bp| bp.enabled) {
+            let hit = match bp.condition {
+                Condition::Address(addr) => addr == pc,
+                Condition::Instruction(ref mnemonic) => mnemonic == opcode.mnemonic(),
+                Condition::Clear => matches!(opcode, Opcode::CLS),
+                _ => false,
+            };
+            if hit {
+                bp.hits += 1;
+                ids.push(bp.id);
+            }
+        }
+        ids
+    }
+
+    /// Remembers what the watchpoints are watching, right before an instruction runs, so that `check_after` can
+    /// tell what it changed.
+    pub fn remember(&mut self, now: &Watched) {
+        for (bp, before) in self.list.iter().zip(self.before.iter_mut()) {
+            before.clear();
+            if !bp.enabled {
+                continue;
+            }
+            match bp.condition {
+                Condition::Memory { start, len, .. } => before.extend_from_slice(&now.memory[clamp(start, len, now.memory.len())]),
+                Condition::Pixels { x, y, width, height } => before.extend(pixels_in(now.framebuffer, x, y, width, height)),
+                _ => (),
+            }
+        }
+        self.registers_before = now.registers;
+        self.index_before = now.index;
+    }
+
+    /// Checks the watchpoints after an instruction that touched `footprint` has run, leaving the machine as `now`
+    /// is, counting a hit on each one that goes off. Returns their ids.
+    ///
+    /// Anything that changed counts as written, even if the footprint doesn't say so, which catches what the
+    /// machine code routines behind SYS do to RAM.
+    pub fn check_after(&mut self, footprint: &Footprint, now: &Watched) -> Vec<usize> {
+        let mut ids = Vec::new();
+        for (bp, before) in self.list.iter_mut().zip(self.before.iter()) {
+            if !bp.enabled {
+                continue;
+            }
+            let hit = match bp.condition {
+                Condition::Memory { start, len, access } => {
+                    let watched = start..start.saturating_add(len);
+                    let changed = now.memory[clamp(start, len, now.memory.len())] != before[..];
+                    match access {
+                        Access::Read => footprint.memory_read.iter().any(|r| overlaps(r, &watched)),
+                        Access::Write => changed || footprint.memory_written.iter().any(|r| overlaps(r, &watched)),
+                        Access::Change => changed,
+                    }
+                },
+                Condition::Register { x, access } => {
+                    let changed = now.registers[x as usize] != self.registers_before[x as usize];
+                    match access {
+                        Access::Read => footprint.registers_read & (1 << x) != 0,
+                        Access::Write => changed || footprint.registers_written & (1 << x) != 0,
+                        Access::Change => changed,
+                    }
+                },
+                Condition::Index(access) => {
+                    let changed = now.index != self.index_before;
+                    match access {
+                        Access::Read => footprint.index_read,
+                        Access::Write => changed || footprint.index_written,
+                        Access::Change => changed,
+                    }
+                },
+                Condition::Pixels { x, y, width, height } => !pixels_in(now.framebuffer, x, y, width, height).eq(before.iter().cloned()),
+                Condition::Collision => now.collided,
+                Condition::Address(_) | Condition::Instruction(_) | Condition::Clear => false,
+            };
+            if hit {
+                bp.hits += 1;
+                ids.push(bp.id);
+            }
+        }
+        ids
+    }
+
+    /// Where the breakpoint with id `id` is in the list.
+    fn position(&self, id: usize) -> Result<usize, String> {
+        match self.list.iter().position(|bp| bp.id == id) {
+            Some(n) => Ok(n),
+            None => Err(format!("There is no breakpoint {}.", id)),
+        }
+    }
+}
+
+/// The part of the `len` bytes starting at `start` that is inside RAM of `memory_len` bytes.
+fn clamp(start: usize, len: usize, memory_len: usize) -> Range<usize> {
+    let start = start.min(memory_len);
+    start..start.saturating_add(len).min(memory_len)
+}
+
+/// The plane masks of the pixels of `grid` in the `width` by `height` rectangle at (`x`, `y`), row by row. Whatever
+/// is off the edge of the screen is left out.
+fn pixels_in(grid: &PixelGrid, x: u32, y: u32, width: u32, height: u32) -> impl Iterator<Item = u8> + '_ {
+    let columns = x.min(grid.ncols)..x.saturating_add(width).min(grid.ncols);
+    let rows = y.min(grid.nrows)..y.saturating_add(height).min(grid.nrows);
+    rows.flat_map(move |r| columns.clone().map(move |c| grid.get_pixel_at(c as usize, r as usize).value.planes()))
+}
+
+/// Do the two ranges have any addresses in common?
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
@@ -0,0 +1,278 @@
+//! This module contains an RCA CDP1802, the CPU of the COSMAC VIP, for running the machine code routines that
+//! hybrid programs call with SYS.
+//!
+//! The original interpreter was itself 1802 machine code, and 0nnn jumped straight out of it into the routine at nnn,
+//! which came back with D4 (SEP R4, R4 being the interpreter's own program counter). Nothing else about the VIP is
+//! emulated here: there are no interrupts, the EF lines are never set, and the input bus always reads 0.
+
+use super::error::ErrorKind;
+
+/// The register the VIP interpreter runs from, so a routine returns to it with SEP R4 (D4).
+pub const INTERPRETER_P: u8 = 4;
+
+/// The registers and flags of the CPU.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cdp1802 {
+    /// The sixteen 16 bit scratchpad registers.
+    pub r: [u16; 16],
+    /// The accumulator.
+    pub d: u8,
+    /// The carry flag. After a subtraction, it is set if there was no borrow.
+    pub df: bool,
+    /// Which scratchpad register is the program counter.
+    pub p: u8,
+    /// Which scratchpad register points at the operand of the memory instructions.
+    pub x: u8,
+    /// Where MARK and interrupts keep the X and P they are replacing.
+    pub t: u8,
+    /// Are interrupts enabled?
+    pub ie: bool,
+    /// The Q output, which drives the VIP's speaker.
+    pub q: bool,
+}
+
+impl Cdp1802 {
+    /// A CPU with every register and flag cleared.
+    pub fn new() -> Self {
+        Cdp1802::default()
+    }
+
+    /// Executes instructions out of `memory` until P becomes `return_p`, or until `max_instructions` have gone by
+    /// without that happening. Addresses past the end of `memory` wrap around to the start, like the VIP's
+    /// partially decoded address lines.
+    ///
+    /// Returns the number of machine cycles the instructions took.
+    pub fn run_until_p(&mut self, memory: &mut [u8], return_p: u8, max_instructions: usize) -> Result<u64, ErrorKind> {
+        let mut ncycles = 0;
+        for _ in 0..max_instructions {
+            ncycles += self.step(memory);
+            if self.p == return_p {
+                return Ok(ncycles);
+            }
+        }
+        Err(ErrorKind::MachineCodeRunaway)
+    }
+
+    /// Executes one instruction, and returns the number of machine cycles it took.
+    ///
+    /// IDL waits for an interrupt or DMA request, and since the VIP's display is always about to make one, it
+    /// doesn't wait here at all.
+    pub fn step(&mut self, memory: &mut [u8]) -> u64 {
+        let instruction = self.fetch(memory);
+        let n = (instruction & 0x0F) as usize;
+        let x = self.x as usize;
+
+        match instruction >> 4 {
+            0x0 => {
+                if n != 0 {
+                    self.d = read(memory, self.r[n]);
+                }
+            },
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => {
+                let condition = match n & 0x7 {
+                    0x0 => true,
+                    0x1 => self.q,
+                    0x2 => self.d == 0,
+                    0x3 => self.df,
+                    _ => false, // EF1 through EF4
+                };
+                // 0x38 is SKP, the opposite of an unconditional branch
+                self.short_branch(memory, condition != (n & 0x8 != 0));
+            },
+            0x4 => {
+                self.d = read(memory, self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            },
+            0x5 => write(memory, self.r[n], self.d),
+            0x6 => match n {
+                // IRX
+                0x0 => self.r[x] = self.r[x].wrapping_add(1),
+                // OUT 1 through 7: the byte goes nowhere
+                0x1..=0x7 => self.r[x] = self.r[x].wrapping_add(1),
+                // INP 1 through 7: nothing is ever on the bus
+                0x9..=0xF => {
+                    self.d = 0;
+                    write(memory, self.r[x], 0);
+                },
+                _ => (),
+            },
+            0x7 => self.execute_7n(memory, n),
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xA => self.r[n] = (self.r[n] & 0xFF00) | self.d as u16,
+            0xB => self.r[n] = (self.r[n] & 0x00FF) | ((self.d as u16) << 8),
+            0xC => {
+                self.execute_cn(memory, n);
+                return 3;
+            },
+            0xD => self.p = n as u8,
+            0xE => self.x = n as u8,
+            _ => self.execute_fn(memory, n),
+        }
+        2
+    }
+
+    /// Executes one of the 7N instructions: returns from interrupts, arithmetic with carry on M(R(X)) or the
+    /// immediate byte, shifts with carry, and the Q flag.
+    fn execute_7n(&mut self, memory: &mut [u8], n: usize) {
+        let x = self.x as usize;
+        match n {
+            // RET and DIS
+            0x0 | 0x1 => {
+                let xp = read(memory, self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = xp >> 4;
+                self.p = xp & 0x0F;
+                self.ie = n == 0x0;
+            },
+            // LDXA
+            0x2 => {
+                self.d = read(memory, self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            },
+            // STXD
+            0x3 => {
+                write(memory, self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            },
+            // SHRC
+            0x6 => {
+                let carry = self.d & 0x01 != 0;
+                self.d = (self.d >> 1) | ((self.df as u8) << 7);
+                self.df = carry;
+            },
+            // SAV
+            0x8 => write(memory, self.r[x], self.t),
+            // MARK
+            0x9 => {
+                self.t = (self.x << 4) | self.p;
+                write(memory, self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            },
+            // REQ and SEQ
+            0xA | 0xB => self.q = n == 0xB,
+            // SHLC
+            0xE => {
+                let carry = self.d & 0x80 != 0;
+                self.d = (self.d << 1) | self.df as u8;
+                self.df = carry;
+            },
+            // ADC, SDB, SMB, and their immediate versions
+            _ => {
+                let operand = if n >= 0xC { self.fetch(memory) } else { read(memory, self.r[x]) };
+                self.arithmetic(n & 0x3, operand, self.df as u16);
+            },
+        }
+    }
+
+    /// Executes one of the CN instructions: the long branches and skips, and NOP.
+    fn execute_cn(&mut self, memory: &mut [u8], n: usize) {
+        let condition = match n & 0x3 {
+            0x0 => true,
+            0x1 => self.q,
+            0x2 => self.d == 0,
+            _ => self.df,
+        };
+        let p = self.p as usize;
+        match n {
+            // LBR, LBQ, LBZ, LBDF, and LBNQ, LBNZ, LBNF
+            0x0..=0x3 | 0x9..=0xB => {
+                if condition != (n & 0x8 != 0) {
+                    let high = read(memory, self.r[p]) as u16;
+                    let low = read(memory, self.r[p].wrapping_add(1)) as u16;
+                    self.r[p] = (high << 8) | low;
+                } else {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            },
+            // NOP
+            0x4 => (),
+            // LSKP, and the skips that only skip if the flag is set (LSIE, LSQ, LSZ, LSDF) or clear (LSNQ, LSNZ, LSNF)
+            _ => {
+                let skip = match n {
+                    0x8 => true,
+                    0xC => self.ie,
+                    0x5..=0x7 => !condition,
+                    _ => condition,
+                };
+                if skip {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            },
+        }
+    }
+
+    /// Executes one of the FN instructions: logic and arithmetic on M(R(X)) or the immediate byte, and shifts.
+    fn execute_fn(&mut self, memory: &mut [u8], n: usize) {
+        match n {
+            // SHR
+            0x6 => {
+                self.df = self.d & 0x01 != 0;
+                self.d >>= 1;
+            },
+            // SHL
+            0xE => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            },
+            _ => {
+                let operand = if n >= 0x8 { self.fetch(memory) } else { read(memory, self.r[self.x as usize]) };
+                match n & 0x7 {
+                    // LDX and LDI
+                    0x0 => self.d = operand,
+                    0x1 => self.d |= operand,
+                    0x2 => self.d &= operand,
+                    0x3 => self.d ^= operand,
+                    // ADD, SD, SM, and their immediate versions. Subtracting is adding the complement plus one.
+                    op => self.arithmetic(op & 0x3, operand, (op != 0x4) as u16),
+                }
+            },
+        }
+    }
+
+    /// Adds (`op` 0), subtracts D from (`op` 1), or subtracts from D (`op` 3) the byte `operand`, with `carry` as
+    /// the carry in (for subtraction, 1 means no borrow), setting DF to the carry out.
+    fn arithmetic(&mut self, op: usize, operand: u8, carry: u16) {
+        let (a, b) = match op {
+            0x0 => (self.d as u16, operand as u16),
+            0x1 => (operand as u16, !self.d as u16 & 0xFF),
+            _ => (self.d as u16, !operand as u16 & 0xFF),
+        };
+        let sum = a + b + carry;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    /// Reads the byte at R(P) and moves R(P) past it.
+    fn fetch(&mut self, memory: &[u8]) -> u8 {
+        let p = self.p as usize;
+        let byte = read(memory, self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        byte
+    }
+
+    /// Moves R(P) to the byte after it if `condition` holds, otherwise past it. For the short branches,
+    /// which stay in the same 256 byte page.
+    fn short_branch(&mut self, memory: &[u8], condition: bool) {
+        let p = self.p as usize;
+        if condition {
+            self.r[p] = (self.r[p] & 0xFF00) | read(memory, self.r[p]) as u16;
+        } else {
+            self.r[p] = self.r[p].wrapping_add(1);
+        }
+    }
+}
+
+/// The byte at `addr`, wrapping around the end of `memory`.
+fn read(memory: &[u8], addr: u16) -> u8 {
+    memory[addr as usize % memory.len()]
+}
+
+/// Sets the byte at `addr` to `byte`, wrapping around the end of `memory`.
+fn write(memory: &mut [u8], addr: u16, byte: u8) {
+    let len = memory.len();
+    memory[addr as usize % len] = byte;
+}
@@ -1,31 +1,61 @@
 use super::Address;
+use super::backend::{AudioBackend, Backend, DisplayBackend, InputBackend, UserRequest};
+use super::breakpoint::{Breakpoint, Breakpoints, Condition, Footprint, Watched, NUM_WATCHED_REGISTERS};
+use super::cdp1802::{Cdp1802, INTERPRETER_P};
+use super::crashdump::CrashDump;
 use super::opcode::Opcode;
-use super::debugiface::{EmulatorCommand, EmulatorResponse};
-use super::display::{gui, sprite};
-use super::keyboard;
-use super::rand::prelude::*;
+use super::debugiface::{BreakPolicy, EmulatorCommand, EmulatorEvent, EmulatorResponse};
+use super::error::{EmulatorError, ErrorKind};
+use super::keyboard::Keypad;
+use super::font::{self, FontSet};
+use super::movie::{Movie, MovieInput};
+use super::display::argb::{ArgbSprite, BlendMode, PALETTE_SIZE};
+use super::display::chip8panel::{PixelGrid, Pxcolor, ALL_PLANES, PLANE_1, PLANE_2};
+use super::display::sprite;
+use super::platform::Platform;
+use super::quirks::Quirks;
 use super::register::{Register, RegisterArray};
+use super::rewind::{RewindBuffer, REWIND_MAX_BYTES};
+use super::rng::{Rng, RngMode};
+use super::savestate::SaveState;
+use super::scheduler::{Scheduler, FRAME_RATE_HZ};
+use super::timing::{self, Timing, VIP_INTERPRETER_CYCLES_PER_FRAME, VIP_SKIP_CYCLES};
+use std::collections::VecDeque;
 use std::fmt::{self, Write};
+use std::fs;
+use std::io;
+use std::path;
 use std::sync::mpsc;
 
-/// The length of our RAM in bytes
-const MEMORY_LENGTH_NBYTES: usize = 4096;
-/// The start address for a program binary
+/// The start address for a program binary, unless it is set to something else
 const PROGRAM_START_BYTE_ADDR: u16 = 0x0200;
-/// The largest a program can be in bytes
-const MAX_PROGRAM_SIZE_NBYTES: usize = MEMORY_LENGTH_NBYTES - (PROGRAM_START_BYTE_ADDR as usize);
+/// The width of the screen in low resolution mode, in the program's pixels.
+const LORES_WIDTH: u32 = 64;
 /// There are this many addresses in the special stack array at most.
 const STACK_SIZE_N_ADDRS: usize = 16;
-/// The rate at which the delay timer counts down in Hz.
-const DELAY_TIMER_CLOCK_RATE_HZ: u64 = 60;
-/// The rate at which the sound timer counts down in Hz.
-const SOUND_TIMER_CLOCK_RATE_HZ: u64 = 60;
+/// Where the COSMAC VIP kept the stack in RAM, two bytes to an address with the high byte first.
+const VIP_STACK_ADDR: usize = 0x0EA0;
+/// Where the COSMAC VIP kept its 64x32 screen in RAM, one bit per pixel.
+const VIP_DISPLAY_ADDR: usize = 0x0F00;
+/// Where the COSMAC VIP interpreter kept V0 through VF in RAM, for machine code routines to get at.
+const VIP_REGISTERS_ADDR: usize = 0x0EF0;
+/// Where the COSMAC VIP interpreter's 1802 stack (R2) started, growing down.
+const VIP_CDP1802_STACK_ADDR: u16 = 0x0ECF;
+/// The most 1802 instructions a machine code routine can run before we decide it is never coming back.
+const MAX_MACHINE_CODE_INSTRUCTIONS: usize = 1_000_000;
+/// The COSMAC VIP's RAM, all of which its memory layout and machine code need.
+const VIP_MEMORY_LEN: usize = 0x1000;
+/// The platforms that ran on a 4 KiB COSMAC VIP with a 64x32 screen, and so can use its memory layout and run its machine code.
+const VIP_LAYOUT_PLATFORMS: [Platform; 2] = [Platform::Chip8, Platform::Chip8X];
 /// The default clock rate of the emulated CPU in Hz.
 const DEFAULT_CPU_CLOCK_RATE_HZ: u64 = 1000;
+/// The number of recently executed instructions we remember for crash dumps.
+const HISTORY_LENGTH: usize = 32;
 /// The number of bytes in each of the hexadecimal sprites.
 const BYTES_PER_HEX_SPRITE: u16 = 5;
-pub const HEX_SPRITE_ZERO_ADDR: u16 = BYTES_PER_HEX_SPRITE * 0;
-pub const HEX_SPRITE_ONE_ADDR: u16 = BYTES_PER_HEX_SPRITE * 1;
+/// Where the hex sprites are while the font is in its default place, at the start of RAM.
+pub const HEX_SPRITE_ZERO_ADDR: u16 = 0;
+pub const HEX_SPRITE_ONE_ADDR: u16 = BYTES_PER_HEX_SPRITE;
 pub const HEX_SPRITE_TWO_ADDR: u16 = BYTES_PER_HEX_SPRITE * 2;
 pub const HEX_SPRITE_THREE_ADDR: u16 = BYTES_PER_HEX_SPRITE * 3;
 pub const HEX_SPRITE_FOUR_ADDR: u16 = BYTES_PER_HEX_SPRITE * 4;
@@ -40,323 +70,1769 @@ pub const HEX_SPRITE_C_ADDR: u16 = BYTES_PER_HEX_SPRITE * 12;
 pub const HEX_SPRITE_D_ADDR: u16 = BYTES_PER_HEX_SPRITE * 13;
 pub const HEX_SPRITE_E_ADDR: u16 = BYTES_PER_HEX_SPRITE * 14;
 pub const HEX_SPRITE_F_ADDR: u16 = BYTES_PER_HEX_SPRITE * 15;
+/// The number of bytes in each of the big (SCHIP) hexadecimal sprites.
+const BYTES_PER_BIG_HEX_SPRITE: u16 = 10;
+/// Where the big 8x10 hex sprites start by default, right after the small ones. They always follow the small ones
+/// around. Each digit's sprite follows the one before it.
+pub const BIG_HEX_SPRITE_ZERO_ADDR: u16 = BYTES_PER_HEX_SPRITE * 16;
+/// The big 8x10 sprites for "0" through "F", one after another.
+const BIG_HEX_SPRITES: [u8; 16 * BYTES_PER_BIG_HEX_SPRITE as usize] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+/// The number of RPL user flags that Fx75 and Fx85 save and load.
+const NUM_RPL_FLAGS: usize = 16;
+
+/// In this module, most functions return an EmuResult, which returns either what went wrong or the number the PC should be incremented by.
+type EmuResult = Result<usize, ErrorKind>;
+
+/// Why a call to `step`, `run_for`, or `run_until` returned control to the caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// The PC is sitting on a BRK instruction. Running again continues past it.
+    Breakpoint,
+    /// The debugger's breakpoints with the ids `ids` went off because of the instruction at `pc`, which ran (or is
+    /// about to run) with I at `index`. For a DRW, that's where the sprite is. Breakpoints on an address, an
+    /// instruction, or CLS leave the PC on the instruction, and running again runs it without stopping on it again.
+    /// Watchpoints leave the PC after it.
+    BreakpointHit { ids: Vec<usize>, pc: u16, index: u32 },
+    /// We ran everything we were asked to run.
+    BudgetExhausted,
+    /// The condition given to `run_until` became true.
+    ConditionMet,
+    /// The display was closed, or a debugger told us to exit.
+    Exited,
+    /// The instruction at the PC could not be decoded or executed. The PC is left pointing at it,
+    /// and the machine stays halted on it: running again just reports the same fault.
+    Fault(EmulatorError),
+    /// A debugger asked us to stop with `Pause`. Running again carries on.
+    Paused,
+    /// The movie we were playing has reached its end. `matched` is true if the machine ended up in exactly the
+    /// state it was in at the end of the recording. Running again carries on with the normal input.
+    MovieFinished { matched: bool },
+    /// An LDVxK instruction is waiting for a key. Running again checks the input for one.
+    WaitingForKey,
+}
+
+/// A movie being recorded or played.
+enum MovieSession {
+    /// Recording, since the machine was in the `start` state.
+    Recording { start: SaveState, input: MovieInput },
+    /// Playing `movie`. The instruction count started out at `start_count`.
+    Playing { movie: Movie, start_count: usize, input: MovieInput },
+}
+
+/// How much `run_for` should run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    /// This many instructions.
+    Cycles(usize),
+    /// Until this many 60 Hz frames have gone by, stopping right before the first instruction of the next one.
+    Frames(usize),
+}
+
+/// The Chip 8 emulator
+pub struct Chip8 {
+    /// Are we stopped on a BRK or a breakpoint? If so, the next cycle executes the instruction instead of stopping on it again.
+    at_breakpoint: bool,
+    /// Where the tone goes
+    audio: Box<dyn AudioBackend>,
+    /// Is an LDVxK instruction waiting for a key press?
+    awaiting_key: bool,
+    /// What to do about a BRK when no debugger is attached
+    break_policy: BreakPolicy,
+    /// The breakpoints and watchpoints the debugger has set
+    breakpoints: Breakpoints,
+    /// Does SYS run machine code routines on a CDP1802, like the COSMAC VIP? If not, SYS does nothing.
+    cdp1802: bool,
+    /// The clock rate of the CPU we are emulating. There aren't really any limits on this.
+    clock_rate_hz: u64,
+    /// Where to write a crash dump when we fault. If this is None, we don't write one.
+    crash_dump_path: Option<path::PathBuf>,
+    /// With COSMAC VIP timing, the machine cycles the interpreter has spent so far
+    cycle_count: u64,
+    /// Did the last DRW draw a sprite over something? Only kept up to date while something is being watched.
+    sprite_collided: bool,
+    /// Flag used in debugging to deterimine if the thread should exit. The program can also set it with EXIT.
+    debug_should_exit: bool,
+    /// Where we tell the attached debugger whenever we stop. If no debugger is attached, BRKs follow the break policy.
+    debugger: Option<mpsc::Sender<EmulatorEvent>>,
+    /// Debug pipe receiving end
+    debugrx: mpsc::Receiver<EmulatorCommand>,
+    /// Debug pipe sending end
+    debugtx: mpsc::Sender<EmulatorResponse>,
+    /// How MEGA-CHIP sprites are blended onto the screen
+    blend_mode: BlendMode,
+    /// Drawing a MEGA-CHIP sprite over pixels in this palette entry counts as a collision
+    collision_index: u8,
+    /// Current value of the delay timer
+    delay_timer_value: u8,
+    /// Is a DRW sitting idle until the next frame because of the display wait quirk? If so, it has already been
+    /// checked against the breakpoints.
+    display_waiting: bool,
+    /// Where the hex sprites' font starts in RAM. The big SCHIP sprites come right after it.
+    font_address: u16,
+    /// Where the Chip-8 screen goes
+    display: Box<dyn DisplayBackend>,
+    /// Are we in the SCHIP's 128x64 high resolution mode? If not, we are in the 64x32 low resolution mode,
+    /// and everything gets drawn at double size to fill the screen.
+    hires: bool,
+    /// If we have hit a fault, this is what it was. We won't execute anything else while it is set.
+    fault: Option<EmulatorError>,
+    /// Has the fault we are halted on been reported yet?
+    fault_reported: bool,
+    /// The last few instructions we executed as (PC, instruction) pairs, oldest first. Used for crash dumps.
+    history: VecDeque<(u16, u16)>,
+    /// Special index register - generally used to store memory addresses
+    index: u32,
+    /// The keys a debugger is holding down, if it has taken over the input
+    injected_keys: Option<Keypad>,
+    /// The input the user will use to play the games
+    input: Box<dyn InputBackend>,
+    /// Monotonically increasing (until wraparound) count of clock cycles
+    instruction_count: usize,
+    /// With COSMAC VIP timing, the machine cycles the interpreter had spent when the last instruction started
+    last_cycle_count: u64,
+    /// Where programs get loaded
+    load_address: u16,
+    /// The machine cycles the CDP1802 spent on the routine the current SYS called
+    machine_code_cycles: u64,
+    /// The RAM, which is as big as the platform says unless it was given some other size:
+    /// 0x0000 to 0x01FF is reserved for the interpreter
+    /// 0x0200 (or wherever the load address is) to the end (0x0FFF, or 0xFFFF on XO-CHIP) is where the ROM will be loaded
+    memory: Vec<u8>,
+    /// The movie we are recording or playing, if any. While there is one, the program reads keys from it instead of from `input`.
+    movie: Option<MovieSession>,
+    /// The MEGA-CHIP's palette of ARGB colors, which its sprites are indexes into
+    palette: Vec<u32>,
+    /// Has a debugger asked us to pause? If so, we stop before the next instruction.
+    pause_requested: bool,
+    /// Program counter
+    pc: u16,
+    /// The mask of the XO-CHIP planes that drawing, scrolling, and clearing work on
+    planes: u8,
+    /// Which machine we are pretending to be
+    platform: Platform,
+    /// The byte waiting on the CHIP-8X input port, if there is one. FxFB takes it.
+    port_in: Option<u8>,
+    /// The last byte the program sent out of the CHIP-8X output port with FxF8
+    port_out: u8,
+    /// The choices about what instructions do that differ between platforms
+    quirks: Quirks,
+    /// The SCHIP's RPL user flags, which are kept outside of RAM (on the HP-48 they were in the calculator's registers)
+    rpl_flags: [u8; NUM_RPL_FLAGS],
+    /// Where the RPL user flags are kept between runs. If this is None, they only last as long as we do.
+    rpl_path: Option<path::PathBuf>,
+    /// A snapshot of every recent frame, if rewinding is turned on.
+    rewind: Option<RewindBuffer>,
+    /// Where RND gets its random numbers
+    rng: Rng,
+    /// Is the user holding down the rewind key?
+    rewinding: bool,
+    /// The Chip-8 has 15 1-byte general purpose registers and one that is used as a carry flag.
+    registers:  RegisterArray,
+    /// Decides when frames start, and keeps them from going faster than real time if we are throttled
+    scheduler: Scheduler,
+    /// Stack pointer - simply an index into the stack, which is up to 16 addresses
+    sp: u8,
+    /// The height of MEGA-CHIP sprites in pixels, where 0 means 256
+    sprite_height: u8,
+    /// The width of MEGA-CHIP sprites in pixels, where 0 means 256
+    sprite_width: u8,
+    /// Current value of the sound timer
+    sound_timer_value: u8,
+    /// While we are stopped and serving the debugger, why we stopped
+    stopped: Option<StopReason>,
+    /// Where the display's save and load hotkeys put the save state. If this is None, they don't do anything.
+    state_path: Option<path::PathBuf>,
+    /// How emulated time goes by
+    timing: Timing,
+    /// The stack is implemented as its own array of 16 16-bit values, rather than just a section of RAM.
+    /// In the VIP memory layout, the stack lives in RAM instead, and this is just a copy of it for showing.
+    stack: [u16; STACK_SIZE_N_ADDRS],
+    /// The screen as we last copied it into RAM in the VIP memory layout, to tell whether the program has written over it since
+    vip_display: Vec<u8>,
+    /// Are the screen and stack kept in RAM where the COSMAC VIP kept them?
+    vip_layout: bool,
+}
+
+impl fmt::Debug for Chip8 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Registers: {:?}", self.registers)?;
+        writeln!(f, "PC: 0x{:2x}", self.pc)?;
+        writeln!(f, "I: 0x{:2x}", self.index)?;
+        writeln!(f, "SP: 0x{:2x}", self.sp)?;
+        writeln!(f, "Stack:")?;
+        for (idx, item) in self.stack.iter().enumerate() {
+            writeln!(f, "  {}: {:x}", idx, item)?;
+        }
+
+        // Examine the memory around the PC
+        let low: usize = (self.pc as usize).saturating_sub(10);
+        let high: usize = std::cmp::min(self.memory.len() - 1, (self.pc as usize) + 10);
+        writeln!(f, "Sample of memory around PC: (0x{:2x} to 0x{:2x}):", low, high)?;
+        for i in low..=high {
+            writeln!(f, "  0x{:2x}: 0x{:x}", i, self.memory[i])?;
+        }
+        Ok(())
+    }
+}
+
+impl Chip8 {
+    /// Create a new instance of the emulator that draws, reads keys, and plays sound through `backend`.
+    pub fn new(tx: mpsc::Sender<EmulatorResponse>, rx: mpsc::Receiver<EmulatorCommand>, backend: Backend) -> Self {
+        let platform = Platform::default();
+        let mut mem = vec![0u8; platform.memory_len()];
+        Chip8::write_font(&mut mem, FontSet::default().glyphs(), HEX_SPRITE_ZERO_ADDR);
+
+        Chip8 {
+            at_breakpoint: false,
+            audio: backend.audio,
+            awaiting_key: false,
+            blend_mode: BlendMode::Normal,
+            break_policy: BreakPolicy::default(),
+            breakpoints: Breakpoints::new(),
+            cdp1802: false,
+            collision_index: 0,
+            clock_rate_hz: DEFAULT_CPU_CLOCK_RATE_HZ,
+            crash_dump_path: None,
+            cycle_count: 0,
+            debug_should_exit: false,
+            debugger: None,
+            debugrx: rx,
+            debugtx: tx,
+            delay_timer_value: 0,
+            display: backend.display,
+            display_waiting: false,
+            fault: None,
+            fault_reported: false,
+            font_address: HEX_SPRITE_ZERO_ADDR,
+            hires: false,
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+            memory: mem,
+            movie: None,
+            registers: RegisterArray::new(),
+            palette: vec![0; PALETTE_SIZE],
+            pause_requested: false,
+            pc: platform.start_address(),
+            planes: PLANE_1,
+            platform,
+            port_in: None,
+            port_out: 0,
+            quirks: Quirks::default(),
+            rpl_flags: [0u8; NUM_RPL_FLAGS],
+            rpl_path: None,
+            rewind: None,
+            rng: Rng::from_entropy(RngMode::Modern),
+            rewinding: false,
+            index: 0,
+            injected_keys: None,
+            input: backend.input,
+            instruction_count: 0,
+            last_cycle_count: 0,
+            load_address: PROGRAM_START_BYTE_ADDR,
+            machine_code_cycles: 0,
+            scheduler: Scheduler::new(false),
+            sp: 0,
+            sprite_collided: false,
+            sprite_height: 0,
+            sprite_width: 0,
+            sound_timer_value: 0,
+            state_path: None,
+            stopped: None,
+            timing: Timing::Flat,
+            stack: [0u16; 16],
+            vip_display: Vec::new(),
+            vip_layout: false,
+        }
+    }
+
+    /// Create a new instance of the emulator that is driven entirely through `step`, `run_for`, and `run_until`,
+    /// with nobody on the other end of the debug pipes.
+    pub fn without_debugger(backend: Backend) -> Self {
+        let (tx, _) = mpsc::channel();
+        let (_, rx) = mpsc::channel();
+        Chip8::new(tx, rx, backend)
+    }
+
+    /// Copies the glyphs of a font into `memory` at `addr`, with the big hex sprites right after them.
+    fn write_font(memory: &mut [u8], glyphs: &[u8], addr: u16) {
+        let start = addr as usize;
+        let bigstart = start + glyphs.len();
+        memory[start..bigstart].copy_from_slice(glyphs);
+        memory[bigstart..bigstart + BIG_HEX_SPRITES.len()].copy_from_slice(&BIG_HEX_SPRITES);
+    }
+
+    /// Attempts to load the given binary into RAM and run it.
+    ///
+    /// How big a binary fits depends on the platform, the memory size, and the load address, so set those first.
+    pub fn load(&mut self, binary: &[u8]) -> Result<(), String> {
+        let start = self.load_address as usize;
+        let max_program_size_nbytes = self.memory.len().saturating_sub(start);
+        if binary.len() < max_program_size_nbytes {
+            self.memory[start..start + binary.len()].copy_from_slice(binary);
+            self.mark_dirty(start, binary.len());
+            Ok(())
+        } else {
+            let mut msg = String::new();
+            write!(msg,
+                    "Binary is too large. Maximum size is {} bytes, but this binary is {} bytes.",
+                    max_program_size_nbytes,
+                    binary.len()
+                  ).unwrap();
+            Err(msg)
+        }
+    }
+
+    /// Changes the choices about what instructions do that differ between platforms.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Switches to pretending to be `platform`. RAM grows or shrinks to the platform's size, keeping whatever
+    /// still fits, so this should be done before loading a program. The screen takes on the platform's size (clearing
+    /// it if that changes), the CHIP-8X color board comes and goes with it, the load address goes back to 0x200, drawing
+    /// goes back to the first XO-CHIP plane, and the PC goes to where the platform's programs start. Platforms that didn't run on the COSMAC VIP turn its memory layout and
+    /// machine code off.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.vip_layout &= VIP_LAYOUT_PLATFORMS.contains(&platform);
+        self.cdp1802 &= VIP_LAYOUT_PLATFORMS.contains(&platform);
+        self.memory.resize(platform.memory_len(), 0);
+        self.mark_all_dirty();
+        self.load_address = PROGRAM_START_BYTE_ADDR;
+        self.pc = platform.start_address();
+        self.planes = PLANE_1;
+
+        let (width, height) = platform.screen_sizes()[0];
+        let framebuffer = self.display.framebuffer_mut();
+        if framebuffer.ncols != width || framebuffer.nrows != height {
+            framebuffer.resize(height, width);
+        }
+        framebuffer.enable_colors(platform == Platform::Chip8X);
+    }
+
+    /// Gives the machine `nbytes` of RAM instead of however much the platform had, up to as much as its programs can
+    /// address. Whatever still fits is kept. The font and the load address have to still fit, and the COSMAC VIP's
+    /// memory layout and machine code need all 4 KiB of the VIP's RAM.
+    ///
+    /// Setting the platform puts RAM back to the platform's size, so set that first.
+    pub fn set_memory_len(&mut self, nbytes: usize) -> Result<(), String> {
+        let font_end = self.font_address as usize + font::FONT_NBYTES + BIG_HEX_SPRITES.len();
+        if nbytes > self.platform.max_memory_len() {
+            return Err(format!("{} programs can only address {} bytes of RAM, not {}.", self.platform, self.platform.max_memory_len(), nbytes));
+        } else if nbytes < font_end || nbytes <= self.load_address as usize {
+            return Err(format!("{} bytes of RAM can't hold both the font (up to 0x{:X}) and a program at 0x{:X}.", nbytes, font_end, self.load_address));
+        } else if (self.vip_layout || self.cdp1802) && nbytes < VIP_MEMORY_LEN {
+            return Err(format!("The COSMAC VIP memory layout and machine code need {} bytes of RAM, not {}.", VIP_MEMORY_LEN, nbytes));
+        }
+
+        self.memory.resize(nbytes, 0);
+        self.mark_all_dirty();
+        Ok(())
+    }
+
+    /// Loads programs at `addr` instead of 0x200, like the ETI-660's 0x600, and moves the PC there. Platforms whose
+    /// programs start running past 0x200 (like HIRES) start the same distance past `addr`.
+    ///
+    /// Setting the platform puts this back to 0x200, so set that first, and the memory size too.
+    pub fn set_load_address(&mut self, addr: u16) -> Result<(), String> {
+        if addr as usize >= self.memory.len() {
+            return Err(format!("Programs can't be loaded at 0x{:X}, since RAM ends at 0x{:X}.", addr, self.memory.len()));
+        }
+
+        self.load_address = addr;
+        self.pc = addr.wrapping_add(self.platform.start_address() - PROGRAM_START_BYTE_ADDR);
+        Ok(())
+    }
+
+    /// Replaces the hex sprites' font with `glyphs` (sixteen 5-byte glyphs for "0" through "F", like the `FontSet`s
+    /// have) and moves it to `addr`, with the big SCHIP sprites right after it. Where the font used to be is cleared,
+    /// and LDFVx and LDHFVx point I at the new one.
+    ///
+    /// The font has to fit in RAM, so set the platform and memory size first.
+    pub fn set_font(&mut self, glyphs: &[u8], addr: u16) -> Result<(), String> {
+        let nbytes = font::FONT_NBYTES + BIG_HEX_SPRITES.len();
+        if glyphs.len() != font::FONT_NBYTES {
+            return Err(format!("A font is {} bytes, but this one is {}.", font::FONT_NBYTES, glyphs.len()));
+        } else if addr as usize + nbytes > self.memory.len() {
+            return Err(format!("A font at 0x{:X} would run past the end of RAM at 0x{:X}.", addr, self.memory.len()));
+        }
+
+        let old = (self.font_address as usize).min(self.memory.len());
+        let oldend = (old + nbytes).min(self.memory.len());
+        for byte in &mut self.memory[old..oldend] {
+            *byte = 0;
+        }
+        Chip8::write_font(&mut self.memory, glyphs, addr);
+        self.mark_dirty(old, oldend - old);
+        self.mark_dirty(addr as usize, nbytes);
+        self.font_address = addr;
+        Ok(())
+    }
+
+    /// Keeps the screen and the stack in RAM where the COSMAC VIP did (the screen at 0xF00 and the stack at 0xEA0),
+    /// for programs that read or write them there. Whatever is on the screen and the stack right now is copied in.
+    ///
+    /// Only the platforms that ran on a 4 KiB VIP with a 64x32 screen can do this, so set the platform first.
+    pub fn set_vip_layout(&mut self, on: bool) -> Result<(), String> {
+        if on && !VIP_LAYOUT_PLATFORMS.contains(&self.platform) {
+            return Err(format!("{} programs did not keep the screen and stack in RAM like the COSMAC VIP.", self.platform));
+        } else if on && self.memory.len() < VIP_MEMORY_LEN {
+            return Err(format!("The COSMAC VIP memory layout needs {} bytes of RAM, but there are only {}.", VIP_MEMORY_LEN, self.memory.len()));
+        }
+
+        self.vip_layout = on;
+        if on {
+            for (n, addr) in self.stack.iter().enumerate() {
+                self.memory[VIP_STACK_ADDR + 2 * n] = (*addr >> 8) as u8;
+                self.memory[VIP_STACK_ADDR + 2 * n + 1] = *addr as u8;
+            }
+            self.mark_dirty(VIP_STACK_ADDR, 2 * self.stack.len());
+            self.store_vip_display();
+        }
+        Ok(())
+    }
+
+    /// Makes SYS run the machine code routine it calls on a CDP1802, the way the COSMAC VIP did, for hybrid programs.
+    /// Routines that draw straight onto the screen also need the VIP memory layout.
+    ///
+    /// Only the platforms that ran on a 4 KiB VIP can do this, so set the platform first.
+    pub fn set_cdp1802(&mut self, on: bool) -> Result<(), String> {
+        if on && !VIP_LAYOUT_PLATFORMS.contains(&self.platform) {
+            return Err(format!("{} programs did not call COSMAC VIP machine code.", self.platform));
+        } else if on && self.memory.len() < VIP_MEMORY_LEN {
+            return Err(format!("COSMAC VIP machine code needs {} bytes of RAM, but there are only {}.", VIP_MEMORY_LEN, self.memory.len()));
+        }
+
+        self.cdp1802 = on;
+        Ok(())
+    }
+
+    /// In the VIP memory layout, brings the screen and stack in RAM and the ones we draw and show up to date with
+    /// each other after an instruction. If the program wrote over the screen in RAM, that's what the screen shows
+    /// now; otherwise, whatever was drawn is copied into RAM.
+    fn sync_vip_layout(&mut self) {
+        for (n, slot) in self.stack.iter_mut().enumerate() {
+            let addr = VIP_STACK_ADDR + 2 * n;
+            *slot = ((self.memory[addr] as u16) << 8) | self.memory[addr + 1] as u16;
+        }
+
+        if !self.hires {
+            let len = self.vip_display.len();
+            if self.memory[VIP_DISPLAY_ADDR..VIP_DISPLAY_ADDR + len] != self.vip_display[..] {
+                let scale = self.scale();
+                self.display.framebuffer_mut().load_bitmap(&self.memory[VIP_DISPLAY_ADDR..], scale);
+            }
+        }
+        self.store_vip_display();
+    }
+
+    /// Copies the screen into RAM for the VIP memory layout. The VIP had no high resolution mode, so there is
+    /// nowhere for that screen to go, and RAM is left alone while it's on.
+    fn store_vip_display(&mut self) {
+        if self.hires {
+            return;
+        }
+        let bitmap = self.display.framebuffer().to_bitmap(self.scale());
+        self.memory[VIP_DISPLAY_ADDR..VIP_DISPLAY_ADDR + bitmap.len()].copy_from_slice(&bitmap);
+        self.mark_dirty(VIP_DISPLAY_ADDR, bitmap.len());
+        self.vip_display = bitmap;
+    }
+
+    /// Puts `byte` on the CHIP-8X input port for the program to read with FxFB, replacing whatever was waiting there.
+    pub fn set_port_input(&mut self, byte: u8) {
+        self.port_in = Some(byte);
+    }
+
+    /// Runs the emulator forever (or until a user debugs it and issues the exit command, or closes the display).
+    ///
+    /// The debugger gets its turn at the start of every frame, and whenever we stop on a BRK, a fault, or a Pause.
+    /// If no debugger is attached, BRKs do whatever the break policy says.
+    ///
+    /// Returns the fault we halted on, if there was no debugger attached to look at it (or it went away).
+    pub fn run(&mut self) -> Result<(), EmulatorError> {
+        loop {
+            match self.cycle() {
+                None | Some(StopReason::WaitingForKey) => (),
+                Some(StopReason::MovieFinished { matched }) => {
+                    if matched {
+                        println!("The movie is over, and everything matches the recording.");
+                    } else {
+                        println!("The movie is over, but we did not end up where the recording did.");
+                    }
+                },
+                Some(StopReason::Breakpoint) if self.debugger.is_none() && self.break_policy != BreakPolicy::Halt => {
+                    if self.break_policy == BreakPolicy::Log {
+                        println!("Hit a BRK at 0x{:03X}. Carrying on, since no debugger is attached.", self.pc);
+                    }
+                },
+                Some(StopReason::BreakpointHit { ids, pc, index }) if self.debugger.is_none() && self.break_policy != BreakPolicy::Halt => {
+                    if self.break_policy == BreakPolicy::Log {
+                        println!("Hit breakpoints {:?} at 0x{:03X} with I at 0x{:03X}. Carrying on, since no debugger is attached.", ids, pc, index);
+                    }
+                },
+                Some(reason @ StopReason::Breakpoint) | Some(reason @ StopReason::BreakpointHit { .. }) | Some(reason @ StopReason::Paused) => {
+                    // If the other end of the pipe is gone, nobody can ever tell us to carry on
+                    if !self.serve_debugger(reason) {
+                        println!("Halted at 0x{:03X}, and there is no debugger to carry on from there.", self.pc);
+                        break;
+                    }
+                },
+                Some(StopReason::Exited) => break,
+                Some(StopReason::Fault(err)) => {
+                    // Stay halted, but let an attached debugger look around. If nobody is there to look, we're done.
+                    // The fault only gets reported once, when it happens, however many times the debugger tries to
+                    // carry on from it. Everything else about the machine is in the crash dump, if we wrote one.
+                    let reported = std::mem::replace(&mut self.fault_reported, true);
+                    if self.debugger.is_none() {
+                        match self.crash_dump_path {
+                            Some(ref p) => println!("{}. Crash dump is at {}", err, p.display()),
+                            None => println!("{}", err),
+                        }
+                        return Err(err);
+                    }
+                    if !reported {
+                        self.notify_stopped(&StopReason::Fault(err));
+                    }
+                    if !self.wait_for_debugger(StopReason::Fault(err)) {
+                        return Err(err);
+                    }
+                },
+                Some(reason) => panic!("A single cycle should never stop because of {:?}", reason),
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes exactly one instruction, unless something stops us first.
+    ///
+    /// Returns `BudgetExhausted` if the instruction ran normally.
+    pub fn step(&mut self) -> StopReason {
+        self.run_for(Budget::Cycles(1))
+    }
+
+    /// Executes instructions until we have used up `budget` or something stops us first.
+    pub fn run_for(&mut self, budget: Budget) -> StopReason {
+        match budget {
+            Budget::Cycles(n) => {
+                for _ in 0..n {
+                    if let Some(reason) = self.cycle() {
+                        return reason;
+                    }
+                }
+            },
+            Budget::Frames(n) => {
+                let mut nframes = 0;
+                loop {
+                    let due = self.frames_due();
+                    if due > 0 {
+                        if nframes >= n as u64 {
+                            break;
+                        }
+                        nframes += due;
+                    }
+                    if let Some(reason) = self.cycle() {
+                        return reason;
+                    }
+                }
+            },
+        }
+        StopReason::BudgetExhausted
+    }
+
+    /// Executes instructions until `condition` is true of the machine after an instruction,
+    /// or until something else stops us first.
+    pub fn run_until<F>(&mut self, mut condition: F) -> StopReason where F: FnMut(&Chip8) -> bool {
+        loop {
+            if let Some(reason) = self.cycle() {
+                return reason;
+            }
+            if condition(self) {
+                return StopReason::ConditionMet;
+            }
+        }
+    }
+
+    /// Does one cycle of the machine: fetches, decodes, and executes one instruction. If the instruction is the first
+    /// one of a frame, first waits for the frame to come around, updates the display, and ticks the timers.
+    ///
+    /// Returns None if the instruction ran normally, otherwise the reason we stopped.
+    fn cycle(&mut self) -> Option<StopReason> {
+        // First check if we are being debugged and the user wants to exit
+        if self.debug_should_exit {
+            return Some(StopReason::Exited);
+        }
+
+        // If we have played the whole movie, check that we ended up where the recording did
+        if let Some(MovieSession::Playing { ref movie, start_count, .. }) = self.movie {
+            if self.instruction_count.wrapping_sub(start_count) >= movie.ninstructions {
+                let matched = self.save_state().digest() == movie.final_digest;
+                self.movie = None;
+                return Some(StopReason::MovieFinished { matched });
+            }
+        }
+
+        // At the start of each frame, wait for it to come around in real time, then let the display draw everything and handle its events
+        let nframes = self.frames_due();
+        if nframes > 0 || self.rewinding {
+            self.scheduler.wait_for_frame();
+            if !self.display.update(self.pc, &self.memory, self.sp, &self.stack) {
+                return Some(StopReason::Exited);
+            }
+
+            // Do whatever the user asked for through the display
+            while let Some(request) = self.display.poll_request() {
+                self.handle_request(request);
+            }
+
+            // And whatever the debugger asked for, which might be to stop
+            self.poll_debugger();
+            if self.debug_should_exit {
+                return Some(StopReason::Exited);
+            }
+            if self.pause_requested {
+                self.pause_requested = false;
+                return Some(StopReason::Paused);
+            }
+        }
+
+        // While the user holds the rewind key, run time backwards one frame at a time instead of executing anything
+        if self.rewinding {
+            self.rewind();
+            self.audio.set_tone(false);
+            return None;
+        }
+
+        // If we have faulted, we are halted on the bad instruction until someone does something about it
+        if let Some(err) = self.fault {
+            return Some(StopReason::Fault(err));
+        }
+
+        if nframes > 0 {
+            // Take a snapshot to rewind back to, and update the keys if there is a movie going
+            if let Some(mut rewind) = self.rewind.take() {
+                rewind.push(self.save_state_with(Vec::new(), PixelGrid::new(0, 0)), &self.memory, self.display.framebuffer());
+                self.rewind = Some(rewind);
+            }
+            match self.movie {
+                Some(MovieSession::Recording { ref mut input, .. }) => input.record_frame(&mut *self.input),
+                Some(MovieSession::Playing { ref mut input, .. }) => input.play_frame(),
+                None => (),
+            }
+
+            // The timers count down once for every frame that has gone by
+            let ticks = std::cmp::min(nframes, u8::MAX as u64) as u8;
+            self.delay_timer_value = self.delay_timer_value.saturating_sub(ticks);
+            self.sound_timer_value = self.sound_timer_value.saturating_sub(ticks);
+            self.audio.set_tone(self.sound_timer_value > 0);
+        }
+
+        // Fetch an instruction with pc
+        if self.pc as usize + 1 >= self.memory.len() {
+            return Some(self.halt(ErrorKind::AddressOutOfRange(self.pc as usize), 0));
+        }
+        let msb = self.memory[self.pc as usize];
+        let lsb = self.memory[(self.pc + 1) as usize];
+        let instruction: u16 = ((msb as u16) << 8) | (lsb as u16);
+
+        // Decode opcode
+        let opcode = match Opcode::for_platform(instruction, self.platform) {
+            Ok(o) => o,
+            Err(kind) => return Some(self.halt(kind, instruction)),
+        };
+
+        // Stop on a BRK or a breakpoint the first time we see it. The next cycle will execute the instruction,
+        // unless the PC was moved off it.
+        if !self.at_breakpoint {
+            if let Opcode::BRK = opcode {
+                self.at_breakpoint = true;
+                return Some(StopReason::Breakpoint);
+            }
+            let hits = if self.display_waiting { Vec::new() } else { self.breakpoints.check_before(self.pc, opcode) };
+            if !hits.is_empty() {
+                self.at_breakpoint = true;
+                return Some(StopReason::BreakpointHit { ids: hits, pc: self.pc, index: self.index });
+            }
+        }
+        self.at_breakpoint = false;
+
+        // With the display wait quirk, a DRW sits idle until the next frame starts, then goes first in that frame.
+        // With COSMAC VIP timing, that's the rest of this frame's machine cycles gone.
+        if let Opcode::DRWVxVyNibble(..) = opcode {
+            if self.quirks.display_wait && nframes == 0 {
+                if self.timing == Timing::CosmacVip {
+                    self.last_cycle_count = self.cycle_count;
+                    self.cycle_count = (self.cycle_count / VIP_INTERPRETER_CYCLES_PER_FRAME + 1) * VIP_INTERPRETER_CYCLES_PER_FRAME;
+                }
+                self.instruction_count = self.instruction_count.wrapping_add(1);
+                self.display_waiting = true;
+                return None;
+            }
+        }
+        self.display_waiting = false;
+
+        // If anything is being watched, work out what the instruction is going to touch, and remember what it was before
+        let footprint = if self.breakpoints.watching() {
+            self.sprite_collided = false;
+            let mut breakpoints = std::mem::take(&mut self.breakpoints);
+            breakpoints.remember(&self.watched());
+            self.breakpoints = breakpoints;
+            Some(self.footprint(opcode))
+        } else {
+            None
+        };
+
+        // Execute instruction and increment the PC, working out how long it takes from the registers it started with
+        let pc = self.pc;
+        let index = self.index;
+        let vip_timing = self.timing == Timing::CosmacVip;
+        let cycles = if vip_timing { timing::vip_cycles(opcode, |x| self.registers.get(x).unwrap_or(0)) } else { 0 };
+        match self.execute(opcode) {
+            Ok(pcincr) => {
+                self.pc = self.pc.wrapping_add(pcincr as u16);
+                if vip_timing {
+                    let skipped = if pcincr > 2 { VIP_SKIP_CYCLES } else { 0 };
+                    self.last_cycle_count = self.cycle_count;
+                    self.cycle_count += cycles + skipped + self.machine_code_cycles;
+                }
+                self.machine_code_cycles = 0;
+            },
+            Err(kind) => return Some(self.halt(kind, instruction)),
+        }
+        if self.vip_layout {
+            self.sync_vip_layout();
+        }
+
+        // Remember what we just did in case we crash later
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back((pc, instruction));
+
+        // Increment the instruction count
+        self.instruction_count = self.instruction_count.wrapping_add(1);
+
+        // Stop if the instruction touched anything being watched
+        if let Some(footprint) = footprint {
+            let mut breakpoints = std::mem::take(&mut self.breakpoints);
+            let hits = breakpoints.check_after(&footprint, &self.watched());
+            self.breakpoints = breakpoints;
+            if !hits.is_empty() {
+                return Some(StopReason::BreakpointHit { ids: hits, pc, index });
+            }
+        }
+
+        if self.awaiting_key {
+            Some(StopReason::WaitingForKey)
+        } else {
+            None
+        }
+    }
+
+    /// Puts us into the halted fault state on the instruction at the PC, and writes a crash dump if we have been told where to.
+    fn halt(&mut self, kind: ErrorKind, instruction: u16) -> StopReason {
+        let err = EmulatorError { kind, pc: self.pc, instruction };
+        self.fault = Some(err);
+        self.fault_reported = false;
+
+        if let Some(ref p) = self.crash_dump_path {
+            if let Err(msg) = self.crash_dump().save(p) {
+                println!("{}", msg);
+            }
+        }
+
+        StopReason::Fault(err)
+    }
+
+    /// Does something the user asked for through the display.
+    fn handle_request(&mut self, request: UserRequest) {
+        match request {
+            UserRequest::StartRewind => self.rewinding = self.rewind.is_some(),
+            UserRequest::StopRewind => self.rewinding = false,
+            UserRequest::SaveState | UserRequest::LoadState => self.handle_state_request(request),
+        }
+    }
+
+    /// Saves or loads the state file, as the user asked for through the display.
+    fn handle_state_request(&mut self, request: UserRequest) {
+        let path = match self.state_path {
+            Some(ref p) => p.clone(),
+            None => {
+                println!("Can't do {:?}: there is no save state file.", request);
+                return;
+            },
+        };
+
+        let result = match request {
+            UserRequest::LoadState => SaveState::load(&path).and_then(|state| self.load_state(&state)),
+            _ => self.save_state().save(&path),
+        };
+        match result {
+            Ok(()) => println!("{:?}: {}", request, path.display()),
+            Err(msg) => println!("{}", msg),
+        }
+    }
+
+    /// Tells us where to keep the RPL user flags between runs, and picks up whatever flags are already there.
+    /// Pass None to stop saving them.
+    pub fn set_rpl_path(&mut self, path: Option<path::PathBuf>) {
+        if let Some(ref p) = path {
+            match fs::read(p) {
+                Ok(bytes) => {
+                    for (flag, byte) in self.rpl_flags.iter_mut().zip(bytes) {
+                        *flag = byte;
+                    }
+                },
+                // Nobody has saved any flags yet
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => println!("Could not read the RPL user flags at {}: {:?}", p.display(), e),
+            }
+        }
+        self.rpl_path = path;
+    }
+
+    /// Tells us where the display's save and load hotkeys should put the save state. Pass None to turn them off.
+    pub fn set_state_path(&mut self, path: Option<path::PathBuf>) {
+        self.state_path = path;
+    }
+
+    /// Replaces the random number generator behind RND, for instance with one that has a known seed.
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    /// Turns on rewinding, keeping a snapshot of each of the last `nframes` frames. Pass 0 to turn it back off.
+    ///
+    /// The snapshots never take up more than `REWIND_MAX_BYTES`, so a program that changes a lot of RAM every frame
+    /// can be rewound fewer frames than this.
+    pub fn set_rewind_frames(&mut self, nframes: usize) {
+        self.rewind = if nframes > 0 { Some(RewindBuffer::new(nframes, REWIND_MAX_BYTES)) } else { None };
+        self.rewinding = false;
+    }
+
+    /// Goes back to the start of the most recent frame we have a snapshot of, forgetting the snapshot
+    /// so that the next call goes back another frame.
+    ///
+    /// Returns false if there is nothing left to rewind to (or rewinding is turned off).
+    pub fn rewind(&mut self) -> bool {
+        // The buffer only writes over the pages of RAM that are different, so hand it ours rather than a copy
+        let mut memory = std::mem::take(&mut self.memory);
+        let mut state = match self.rewind.as_mut().and_then(|r| r.pop(&mut memory)) {
+            Some(s) => s,
+            None => {
+                self.memory = memory;
+                return false;
+            },
+        };
+
+        state.memory = memory;
+        match self.check_state(&state) {
+            Ok(()) => {
+                self.restore_state(state);
+                true
+            },
+            Err(msg) => {
+                self.memory = state.memory;
+                println!("Could not rewind: {}", msg);
+                false
+            },
+        }
+    }
+
+    /// Tells the rewind buffer (if there is one) that `len` bytes of RAM at `addr` were just written.
+    fn mark_dirty(&mut self, addr: usize, len: usize) {
+        if let Some(ref mut rewind) = self.rewind {
+            rewind.mark_dirty(addr, len);
+        }
+    }
+
+    /// Tells the rewind buffer (if there is one) that RAM may have been written anywhere.
+    fn mark_all_dirty(&mut self) {
+        if let Some(ref mut rewind) = self.rewind {
+            rewind.mark_all_dirty();
+        }
+    }
+
+    /// The number of frames we can still rewind.
+    pub fn rewind_len(&self) -> usize {
+        self.rewind.as_ref().map(|r| r.len()).unwrap_or(0)
+    }
+
+    /// Starts recording a movie of everything that happens on the keypad from here on, replacing any movie
+    /// that was already recording or playing.
+    ///
+    /// The keys are only looked at once a frame, so a tap shorter than a frame may not make it into the movie.
+    /// Loading a state or rewinding while recording will keep the movie from playing back the same way.
+    pub fn start_recording(&mut self) {
+        self.movie = Some(MovieSession::Recording { start: self.save_state(), input: MovieInput::recorder() });
+    }
+
+    /// Stops recording and returns the movie, ending right here. Returns None if we weren't recording.
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        match self.movie.take() {
+            Some(MovieSession::Recording { start, input }) => {
+                let end = self.save_state();
+                Some(Movie {
+                    ninstructions: end.instruction_count.wrapping_sub(start.instruction_count),
+                    final_digest: end.digest(),
+                    start,
+                    events: input.into_events(),
+                })
+            },
+            other => {
+                self.movie = other;
+                None
+            },
+        }
+    }
+
+    /// Puts the machine into the state `movie` started from and starts playing it back. The program reads keys from
+    /// the movie until it runs out, at which point we stop with `MovieFinished`.
+    pub fn play_movie(&mut self, movie: &Movie) -> Result<(), String> {
+        self.load_state(&movie.start)?;
+        self.movie = Some(MovieSession::Playing {
+            movie: movie.clone(),
+            start_count: self.instruction_count,
+            input: MovieInput::player(movie.events.clone()),
+        });
+        Ok(())
+    }
+
+    /// Where the program should read the keys from: the movie, if there is one, otherwise the keys a debugger is
+    /// holding down, if it has taken over, otherwise the input backend.
+    fn keys(&mut self) -> &mut dyn InputBackend {
+        match self.movie {
+            Some(MovieSession::Recording { ref mut input, .. }) | Some(MovieSession::Playing { ref mut input, .. }) => input,
+            None => match self.injected_keys {
+                Some(ref mut keypad) => keypad,
+                None => &mut *self.input,
+            },
+        }
+    }
+
+    /// The number of frames that start before the next instruction. If we are coming back from a BRK,
+    /// we already started them when we stopped on it.
+    fn frames_due(&self) -> u64 {
+        if self.at_breakpoint {
+            return 0;
+        }
+        match self.timing {
+            Timing::Flat => Scheduler::frames_starting_at(self.instruction_count, self.clock_rate_hz),
+            Timing::CosmacVip if self.instruction_count == 0 => 1,
+            Timing::CosmacVip => {
+                self.cycle_count / VIP_INTERPRETER_CYCLES_PER_FRAME - self.last_cycle_count / VIP_INTERPRETER_CYCLES_PER_FRAME
+            },
+        }
+    }
+
+    /// Picks how emulated time goes by. With COSMAC VIP timing, the clock rate doesn't matter.
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    /// Sets the clock rate of the CPU in Hz. This can be anything, even below the 60 Hz frame rate.
+    pub fn set_clock_rate(&mut self, hz: u64) {
+        self.clock_rate_hz = hz;
+    }
+
+    /// Sets how many instructions we run in each 60 Hz frame, by setting the clock rate to that many times 60 Hz.
+    pub fn set_instructions_per_frame(&mut self, ninstructions: u64) {
+        self.set_clock_rate(ninstructions * FRAME_RATE_HZ);
+    }
+
+    /// Picks what a BRK does when no debugger is attached. Only `run` pays attention to this; `step`, `run_for`, and
+    /// `run_until` always stop on BRKs.
+    pub fn set_break_policy(&mut self, policy: BreakPolicy) {
+        self.break_policy = policy;
+    }
+
+    /// Attaches a debugger that hears about every stop over `events`, just like the Attach command does, but
+    /// before anything has had a chance to run.
+    pub fn attach_debugger(&mut self, events: mpsc::Sender<EmulatorEvent>) {
+        self.debugger = Some(events);
+    }
+
+    /// Turns waiting for real time at the start of each frame on or off. Without it, we run as fast as we can.
+    pub fn set_throttle(&mut self, throttle: bool) {
+        self.scheduler.set_throttle(throttle);
+    }
+
+    /// Takes a snapshot of the whole machine.
+    pub fn save_state(&self) -> SaveState {
+        self.save_state_with(self.memory.to_vec(), self.display.framebuffer().clone())
+    }
+
+    /// Takes a snapshot of the machine, but with the given RAM and screen instead of copies of ours.
+    fn save_state_with(&self, memory: Vec<u8>, framebuffer: PixelGrid) -> SaveState {
+        SaveState {
+            pc: self.pc,
+            index: self.index,
+            sp: self.sp,
+            registers: (0..self.registers.len() as Register).filter_map(|x| self.registers.get(x)).collect(),
+            stack: self.stack.to_vec(),
+            delay_timer: self.delay_timer_value,
+            sound_timer: self.sound_timer_value,
+            instruction_count: self.instruction_count,
+            clock_rate_hz: self.clock_rate_hz,
+            awaiting_key: self.awaiting_key,
+            at_breakpoint: self.at_breakpoint,
+            memory,
+            framebuffer,
+            rng: self.rng,
+            hires: self.hires,
+            rpl_flags: self.rpl_flags.to_vec(),
+            platform: self.platform,
+            planes: self.planes,
+            quirks: self.quirks,
+            port_out: self.port_out,
+            palette: self.palette.clone(),
+            sprite_width: self.sprite_width,
+            sprite_height: self.sprite_height,
+            blend_mode: self.blend_mode,
+            collision_index: self.collision_index,
+            vip_layout: self.vip_layout,
+            cdp1802: self.cdp1802,
+            timing: self.timing,
+            cycle_count: self.cycle_count,
+            last_cycle_count: self.last_cycle_count,
+            font_address: self.font_address,
+            load_address: self.load_address,
+            display_waiting: self.display_waiting,
+        }
+    }
+
+    /// Puts the machine back into the state it was in when `state` was taken.
+    ///
+    /// This also clears any fault we were halted on, since we are now somewhere else entirely, and lets go of any
+    /// keys a debugger was holding down and of the rewind key.
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), String> {
+        self.check_state(state)?;
+        self.restore_state(state.clone());
+        self.mark_all_dirty();
+        self.injected_keys = None;
+        self.rewinding = false;
+        Ok(())
+    }
+
+    /// Makes sure that `state` is one this machine can be put into.
+    fn check_state(&self, state: &SaveState) -> Result<(), String> {
+        if state.memory.len() > state.platform.max_memory_len() {
+            return Err(format!("The snapshot has {} bytes of RAM, but {} can only address {}.", state.memory.len(), state.platform, state.platform.max_memory_len()));
+        } else if (state.vip_layout || state.cdp1802) && state.memory.len() < VIP_MEMORY_LEN {
+            return Err(format!("The snapshot uses the COSMAC VIP's RAM, but only has {} bytes of it.", state.memory.len()));
+        }
+        self.check_shape(state.registers.len(), state.stack.len(), &state.framebuffer, state.platform)?;
+        self.check_pointers(state.memory.len(), state.pc, state.index, state.sp, state.platform)?;
+        if state.rpl_flags.len() != NUM_RPL_FLAGS {
+            return Err(format!("The snapshot has {} RPL user flags, but we have {}.", state.rpl_flags.len(), NUM_RPL_FLAGS));
+        }
+        let font_end = state.font_address as usize + font::FONT_NBYTES + BIG_HEX_SPRITES.len();
+        if font_end > state.memory.len() {
+            return Err(format!("The snapshot's font runs up to 0x{:X}, past the end of its RAM at 0x{:X}.", font_end, state.memory.len()));
+        } else if state.load_address as usize >= state.memory.len() {
+            return Err(format!("The snapshot's program was loaded at 0x{:X}, past the end of its RAM at 0x{:X}.", state.load_address, state.memory.len()));
+        } else if state.planes & !ALL_PLANES != 0 || (state.platform != Platform::XoChip && state.planes != PLANE_1) {
+            return Err(format!("{} can't draw on planes 0x{:X}.", state.platform, state.planes));
+        }
+        Ok(())
+    }
+
+    /// Puts the machine into `state`, which `check_state` has already said is fine.
+    fn restore_state(&mut self, state: SaveState) {
+        self.restore_framebuffer(&state.framebuffer);
+        self.rpl_flags.copy_from_slice(&state.rpl_flags);
+        self.restore_registers(&state.registers);
+        self.platform = state.platform;
+        self.memory = state.memory;
+        self.stack.copy_from_slice(&state.stack);
+        self.fault = None;
+        self.pc = state.pc;
+        self.index = state.index;
+        self.sp = state.sp;
+        self.delay_timer_value = state.delay_timer;
+        self.sound_timer_value = state.sound_timer;
+        self.instruction_count = state.instruction_count;
+        self.clock_rate_hz = state.clock_rate_hz;
+        self.awaiting_key = state.awaiting_key;
+        self.at_breakpoint = state.at_breakpoint;
+        self.rng = state.rng;
+        self.hires = state.hires;
+        self.planes = state.planes;
+        self.quirks = state.quirks;
+        self.port_out = state.port_out;
+        self.palette = state.palette;
+        self.sprite_width = state.sprite_width;
+        self.sprite_height = state.sprite_height;
+        self.blend_mode = state.blend_mode;
+        self.collision_index = state.collision_index;
+        self.vip_layout = state.vip_layout;
+        self.cdp1802 = state.cdp1802;
+        self.timing = state.timing;
+        self.cycle_count = state.cycle_count;
+        self.last_cycle_count = state.last_cycle_count;
+        self.font_address = state.font_address;
+        self.load_address = state.load_address;
+        self.display_waiting = state.display_waiting;
+        self.vip_display.clear();
+        if self.vip_layout && !self.hires {
+            let len = self.display.framebuffer().to_bitmap(self.scale()).len();
+            self.vip_display = self.memory[VIP_DISPLAY_ADDR..VIP_DISPLAY_ADDR + len].to_vec();
+        }
+        self.port_in = None;
+        self.history.clear();
+    }
+
+    /// Makes sure that a snapshot of a machine with the given number of registers, stack size, and screen
+    /// would fit into this one, pretending to be `platform`.
+    fn check_shape(&self, nregisters: usize, nstack: usize, framebuffer: &PixelGrid, platform: Platform) -> Result<(), String> {
+        let size = (framebuffer.ncols, framebuffer.nrows);
+        if nregisters != self.registers.len() {
+            Err(format!("The snapshot has {} registers, but we have {}.", nregisters, self.registers.len()))
+        } else if nstack != self.stack.len() {
+            Err(format!("The snapshot has a stack of {} addresses, but ours holds {}.", nstack, self.stack.len()))
+        } else if !platform.screen_sizes().contains(&size) {
+            Err(format!("The snapshot's screen is {}x{}, which {} doesn't have.", size.0, size.1, platform))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Makes sure that a snapshot's RAM of `memory_len` bytes is big enough for `platform` to run anything, and that
+    /// its PC, I, and SP point somewhere we could have left them.
+    ///
+    /// The PC may sit right at the end of RAM, where a program that runs off the end faults. I only has to fit in
+    /// the platform's address space, since ADD I, Vx can leave it past the end of RAM without anything going wrong.
+    fn check_pointers(&self, memory_len: usize, pc: u16, index: u32, sp: u8, platform: Platform) -> Result<(), String> {
+        if memory_len <= platform.start_address() as usize {
+            Err(format!("The snapshot has only {} bytes of RAM, which stops short of 0x{:X}, where {} programs start.", memory_len, platform.start_address(), platform))
+        } else if pc as usize > memory_len {
+            Err(format!("The snapshot's PC is 0x{:X}, past the end of its RAM at 0x{:X}.", pc, memory_len))
+        } else if index as usize >= platform.max_memory_len() {
+            Err(format!("The snapshot's I is 0x{:X}, which {} can't address.", index, platform))
+        } else if sp as usize > self.stack.len() {
+            Err(format!("The snapshot's SP is {}, but the stack only holds {} addresses.", sp, self.stack.len()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Copies the given pixels onto the screen. They must already be the right shape.
+    fn restore_framebuffer(&mut self, pixels: &PixelGrid) {
+        let framebuffer = self.display.framebuffer_mut();
+        *framebuffer = pixels.clone();
+        framebuffer.has_changed = true;
+    }
+
+    /// Copies the given values into V0 onwards. There must already be the right number of them.
+    fn restore_registers(&mut self, values: &[u8]) {
+        for (x, value) in values.iter().enumerate() {
+            *self.get_register(x as Register).expect("We already checked the number of registers") = *value;
+        }
+    }
+
+    /// Tells us where to write a crash dump if we fault. Pass None to stop writing them.
+    pub fn set_crash_dump_path(&mut self, path: Option<path::PathBuf>) {
+        self.crash_dump_path = path;
+    }
+
+    /// Takes a snapshot of everything a person would want to know after a crash.
+    pub fn crash_dump(&self) -> CrashDump {
+        CrashDump {
+            fault: self.fault,
+            platform: Some(self.platform),
+            quirks: Some(self.quirks),
+            hires: Some(self.hires),
+            load_address: Some(self.load_address),
+            pc: self.pc,
+            index: self.index,
+            sp: self.sp,
+            registers: (0..self.registers.len() as Register).filter_map(|x| self.registers.get(x)).collect(),
+            stack: self.stack.to_vec(),
+            delay_timer: self.delay_timer_value,
+            sound_timer: self.sound_timer_value,
+            instruction_count: self.instruction_count,
+            history: self.history.iter().cloned().collect(),
+            memory: self.memory.to_vec(),
+            framebuffer: self.display.framebuffer().clone(),
+        }
+    }
+
+    /// Puts the machine back into the state it was in when `dump` was taken, including halting on its fault,
+    /// so it can be poked at for post-mortem inspection.
+    ///
+    /// Old crash dumps don't say which platform they came from, and several platforms share a screen size and
+    /// amount of RAM, so those can't be loaded. Whatever else a dump doesn't say stays the way it is here.
+    pub fn load_crash_dump(&mut self, dump: &CrashDump) -> Result<(), String> {
+        let platform = match dump.platform {
+            Some(p) => p,
+            None => return Err("The crash dump doesn't say which platform it came from, so it can't be loaded.".to_string()),
+        };
+        if dump.memory.len() > platform.max_memory_len() {
+            return Err(format!("The crash dump has {} bytes of RAM, but {} can only address {}.", dump.memory.len(), platform, platform.max_memory_len()));
+        }
+        self.check_shape(dump.registers.len(), dump.stack.len(), &dump.framebuffer, platform)?;
+        self.check_pointers(dump.memory.len(), dump.pc, dump.index, dump.sp, platform)?;
+        if let Some(addr) = dump.load_address {
+            if addr as usize >= dump.memory.len() {
+                return Err(format!("The crash dump's program was loaded at 0x{:X}, past the end of its RAM at 0x{:X}.", addr, dump.memory.len()));
+            }
+        }
+
+        self.restore_framebuffer(&dump.framebuffer);
+        self.restore_registers(&dump.registers);
+        self.platform = platform;
+        self.memory = dump.memory.clone();
+        self.mark_all_dirty();
+        self.stack.copy_from_slice(&dump.stack);
+        self.fault = dump.fault;
+        self.fault_reported = false;
+        self.pc = dump.pc;
+        self.index = dump.index;
+        self.sp = dump.sp;
+        self.delay_timer_value = dump.delay_timer;
+        self.sound_timer_value = dump.sound_timer;
+        self.instruction_count = dump.instruction_count;
+        self.history = dump.history.iter().cloned().collect();
+        self.awaiting_key = false;
+        self.at_breakpoint = false;
+        if let Some(quirks) = dump.quirks {
+            self.quirks = quirks;
+        }
+        if let Some(hires) = dump.hires {
+            self.hires = hires;
+        }
+        if let Some(addr) = dump.load_address {
+            self.load_address = addr;
+        }
+
+        Ok(())
+    }
+
+    /// Stop executing code because of `reason`, and instead wait around on self.debugrx, executing debug commands we
+    /// receive over the pipeline until one of them lets us go again.
+    ///
+    /// Returns false if the other end of the pipeline hung up.
+    fn serve_debugger(&mut self, reason: StopReason) -> bool {
+        self.notify_stopped(&reason);
+        self.wait_for_debugger(reason)
+    }
+
+    /// Like `serve_debugger`, but without telling the debugger why we stopped, since it already knows.
+    fn wait_for_debugger(&mut self, reason: StopReason) -> bool {
+        self.stopped = Some(reason);
+
+        // Sit around waiting for debug commands
+        let mut resumed = false;
+        while let Ok(cmd) = self.debugrx.recv() {
+            if self.handle_command(cmd) {
+                resumed = true;
+                break;
+            }
+        }
+
+        self.stopped = None;
+        resumed
+    }
+
+    /// Tells the attached debugger, if there is one, that we stopped because of `reason`. If it has gone away, it
+    /// isn't attached anymore.
+    fn notify_stopped(&mut self, reason: &StopReason) {
+        let gone = match self.debugger {
+            Some(ref events) => events.send(EmulatorEvent::Stopped(reason.clone())).is_err(),
+            None => false,
+        };
+        if gone {
+            self.debugger = None;
+        }
+    }
+
+    /// Executes whatever debug commands have come in over self.debugrx while we were running, without waiting for more.
+    fn poll_debugger(&mut self) {
+        while let Ok(cmd) = self.debugrx.try_recv() {
+            self.handle_command(cmd);
+        }
+    }
+
+    /// Sends `response` back to the debugger. If nobody is there to get it anymore, that's as good as detaching.
+    ///
+    /// Returns true if the debugger has gone away, in which case a stopped machine should carry on.
+    fn reply(&mut self, response: EmulatorResponse) -> bool {
+        if self.debugtx.send(response).is_ok() {
+            return false;
+        }
+        self.debugger = None;
+        self.pause_requested = false;
+        true
+    }
+
+    /// Executes one debug command, sending back whatever it asks for.
+    ///
+    /// Returns true if the command lets a stopped machine go again. While we are running, that doesn't mean anything.
+    fn handle_command(&mut self, cmd: EmulatorCommand) -> bool {
+        // Check the received command
+        match cmd {
+            // Get the I register and return it
+            EmulatorCommand::PeekI => {
+                return self.reply(EmulatorResponse::I(self.index));
+            },
+
+            // Get the PC and return it
+            EmulatorCommand::PeekPC => {
+                return self.reply(EmulatorResponse::PC(self.pc));
+            },
+
+            // Get some bytes and return them
+            EmulatorCommand::PeekAddr(addr, nbytes) => {
+                let (start, memlen) = (addr as usize, self.memory.len());
+                let response = match start.checked_add(nbytes) {
+                    Some(end) if end <= memlen => EmulatorResponse::MemorySlice(self.memory[start..end].to_vec()),
+                    _ => EmulatorResponse::Ack(Err(format!("{} bytes at 0x{:X} would run past the end of RAM at 0x{:X}.", nbytes, start, memlen))),
+                };
+                return self.reply(response);
+            },
+
+            // Get the contents of a register
+            EmulatorCommand::PeekReg(regidx) => {
+                let response = match self.get_register(regidx) {
+                    Ok(r) => EmulatorResponse::Reg(*r),
+                    Err(kind) => EmulatorResponse::Ack(Err(kind.to_string())),
+                };
+                return self.reply(response);
+            },
+
+            // Send back the SP
+            EmulatorCommand::PeekSP => {
+                return self.reply(EmulatorResponse::SP(self.sp));
+            },
+
+            // Peek at the whole stack
+            EmulatorCommand::PeekStack => {
+                return self.reply(EmulatorResponse::Stack(self.stack.to_vec()));
+            },
+
+            // Peek at the sound timer
+            EmulatorCommand::PeekSoundTimer => {
+                return self.reply(EmulatorResponse::SoundTimer(self.sound_timer_value));
+            },
+
+            // Send back whatever fault we are halted on, if any
+            EmulatorCommand::PeekFault => {
+                return self.reply(EmulatorResponse::Fault(self.fault));
+            },
+
+            // Save or load the whole machine
+            EmulatorCommand::SaveState(p) => {
+                let result = self.save_state().save(&p);
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+            EmulatorCommand::LoadState(p) => {
+                let result = SaveState::load(&p).and_then(|state| self.load_state(&state));
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+
+            // Say why we are stopped, if we are
+            EmulatorCommand::PeekStopped => {
+                return self.reply(EmulatorResponse::Stopped(self.stopped.clone()));
+            },
+
+            // Peek at the rest of the machine
+            EmulatorCommand::PeekDelayTimer => {
+                return self.reply(EmulatorResponse::DelayTimer(self.delay_timer_value));
+            },
+            EmulatorCommand::PeekFramebuffer => {
+                return self.reply(EmulatorResponse::Framebuffer(self.display.framebuffer().clone()));
+            },
+
+            // Change the machine, and say whether that worked
+            EmulatorCommand::PokeAddr(addr, bytes) => {
+                let result = self.poke(addr as usize, &bytes);
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+            EmulatorCommand::PokePixel(x, y, color) => {
+                let result = self.set_pixel(x, y, color);
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+            EmulatorCommand::PokeReg(x, value) => {
+                let result = self.set_register(x, value);
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+            EmulatorCommand::PokeStack(n, addr) => {
+                let result = self.set_stack_entry(n, addr);
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+            EmulatorCommand::SetDelayTimer(value) => {
+                self.set_delay_timer(value);
+                return self.reply(EmulatorResponse::Ack(Ok(())));
+            },
+            EmulatorCommand::SetFramebuffer(pixels) => {
+                let result = self.set_framebuffer(&pixels);
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+            EmulatorCommand::SetI(value) => {
+                self.set_index(value);
+                return self.reply(EmulatorResponse::Ack(Ok(())));
+            },
+            EmulatorCommand::SetKeys(mask) => {
+                self.set_keys(mask);
+                return self.reply(EmulatorResponse::Ack(Ok(())));
+            },
+            EmulatorCommand::SetPC(pc) => {
+                self.set_pc(pc);
+                return self.reply(EmulatorResponse::Ack(Ok(())));
+            },
+            EmulatorCommand::SetSP(sp) => {
+                let result = self.set_sp(sp);
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+            EmulatorCommand::SetSoundTimer(value) => {
+                self.set_sound_timer(value);
+                return self.reply(EmulatorResponse::Ack(Ok(())));
+            },
+
+            // Set breakpoints and watchpoints, and look at them
+            EmulatorCommand::AddBreakpoint(condition) => {
+                let result = self.add_breakpoint(condition);
+                return self.reply(EmulatorResponse::BreakpointAdded(result));
+            },
+            EmulatorCommand::EnableBreakpoint(id, enabled) => {
+                let result = self.enable_breakpoint(id, enabled);
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+            EmulatorCommand::RemoveBreakpoint(id) => {
+                let result = self.remove_breakpoint(id);
+                return self.reply(EmulatorResponse::Ack(result));
+            },
+            EmulatorCommand::PeekBreakpoints => {
+                return self.reply(EmulatorResponse::Breakpoints(self.breakpoints().to_vec()));
+            },
+
+            // Start or stop debugging. Detaching lets us go.
+            EmulatorCommand::Attach(events) => {
+                self.debugger = Some(events);
+                if self.reply(EmulatorResponse::Ack(Ok(()))) {
+                    return true;
+                }
+                if let Some(reason) = self.stopped.clone() {
+                    self.notify_stopped(&reason);
+                }
+            },
+            EmulatorCommand::Detach => {
+                self.debugger = None;
+                self.pause_requested = false;
+                self.reply(EmulatorResponse::Ack(Ok(())));
+                return true;
+            },
+
+            // Stop before the next instruction, unless we already have
+            EmulatorCommand::Pause => {
+                self.pause_requested = self.stopped.is_none();
+                return self.reply(EmulatorResponse::Ack(Ok(())));
+            },
+
+            // Go again, if we are stopped
+            EmulatorCommand::ResumeExecution => {
+                self.pause_requested = false;
+                return true;
+            },
+
+            // Exit the emulator thread
+            EmulatorCommand::Exit => { self.debug_should_exit = true; return true },
+
+            EmulatorCommand::SetClockRate(new_rate) => self.set_clock_rate(new_rate),
+        }
+
+        false
+    }
 
-/// In this module, most functions return an EmuResult, which returns either an error message or the number the PC should be incremented by.
-type EmuResult = Result<usize, String>;
+    /// Executes a BRK instruction.
+    ///
+    /// By the time a BRK gets executed, we have already stopped on it and let the debugger
+    /// (or whoever called `step`) have its turn, so all that is left is to move past it.
+    fn execute_brk(&mut self) -> EmuResult {
+        Ok(2)
+    }
 
-/// The Chip 8 emulator
-pub struct Chip8 {
-    /// The clock rate of the CPU we are emulating. There aren't really any limits on this.
-    clock_rate_hz: u64,
-    /// Flag used in debugging to deterimine if the thread should exit
-    debug_should_exit: bool,
-    /// Debug pipe receiving end
-    debugrx: mpsc::Receiver<EmulatorCommand>,
-    /// Debug pipe sending end
-    debugtx: mpsc::Sender<EmulatorResponse>,
-    /// Current value of the delay timer
-    delay_timer_value: u8,
-    /// Special index register - generally used to store memory addresses
-    index: u16,
-    /// The input the user will use to play the games
-    input: keyboard::Keyboard,
-    /// Monotonically increasing (until wraparound) count of clock cycles
-    instruction_count: usize,
-    /// The RAM:
-    /// 0x0000 to 0x01FF is reserved for the interpreter
-    /// 0x0200 to 0x0FFF is where the ROM will be loaded
-    memory: [u8; MEMORY_LENGTH_NBYTES],
-    /// Program counter
-    pc: u16,
-    /// The Chip-8 has 15 1-byte general purpose registers and one that is used as a carry flag.
-    registers:  RegisterArray,
-    /// Should we clear the display?
-    should_clear_chip8_display: bool,
-    /// Stack pointer - simply an index into the stack, which is up to 16 addresses
-    sp: u8,
-    /// Current value of the sound timer
-    sound_timer_value: u8,
-    /// The stack is implemented as its own array of 16 16-bit values, rather than just a section of RAM
-    stack: [u16; STACK_SIZE_N_ADDRS],
-    /// The emulator GUI
-    user_interface: gui::Gui,
-}
+    /// Executes a SYS instruction.
+    ///
+    /// The SYS instruction jumps to a machine code routine at the given address.
+    /// This instruction is only used on the old computers on which Chip-8 was originally
+    /// implemented. It is ignored by modern interpreters, and by us unless the CDP1802 is turned on.
+    fn execute_sys(&mut self, addr: Address) -> EmuResult {
+        if self.cdp1802 {
+            self.call_machine_code(addr)
+        } else {
+            // Does nothing - NOP
+            Ok(2)
+        }
+    }
 
-impl fmt::Debug for Chip8 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "Registers: {:?}", self.registers)?;
-        writeln!(f, "PC: 0x{:2x}", self.pc)?;
-        writeln!(f, "I: 0x{:2x}", self.index)?;
-        writeln!(f, "SP: 0x{:2x}", self.sp)?;
-        writeln!(f, "Stack:")?;
-        for (idx, item) in self.stack.iter().enumerate() {
-            writeln!(f, "  {}: {:x}", idx, item)?;
+    /// Runs the CDP1802 machine code routine at `addr` until it returns with D4.
+    ///
+    /// The routine finds everything where the VIP interpreter kept it: V0 through VF in RAM at 0xEF0, I in RA,
+    /// the Chip-8 PC (already past the SYS) in R5, and the stack in R2 with X pointing at it. The routine runs
+    /// from R3. Whatever it leaves in those places is what the program sees afterwards.
+    fn call_machine_code(&mut self, addr: Address) -> EmuResult {
+        for x in 0..16 {
+            self.memory[VIP_REGISTERS_ADDR + x as usize] = *self.get_register(x)?;
         }
 
-        // Examine the memory around the PC
-        let low: usize = std::cmp::max(0, ((self.pc as isize) - 10) as usize);
-        let high: usize = std::cmp::min(MEMORY_LENGTH_NBYTES - 1, (self.pc as usize) + 10);
-        writeln!(f, "Sample of memory around PC: (0x{:2x} to 0x{:2x}):", low, high)?;
-        for i in low..=high {
-            writeln!(f, "  0x{:2x}: 0x{:x}", i, self.memory[i])?;
+        let mut cpu = Cdp1802::new();
+        cpu.r[2] = VIP_CDP1802_STACK_ADDR;
+        cpu.r[3] = addr;
+        cpu.r[5] = self.pc.wrapping_add(2);
+        cpu.r[0xA] = self.index as u16;
+        cpu.x = 2;
+        cpu.p = 3;
+        self.machine_code_cycles = cpu.run_until_p(&mut self.memory, INTERPRETER_P, MAX_MACHINE_CODE_INSTRUCTIONS)?;
+        self.mark_all_dirty();
+
+        for x in 0..16 {
+            *self.get_register(x)? = self.memory[VIP_REGISTERS_ADDR + x as usize];
         }
-        Ok(())
+        // RAM can be all 64 KiB of what the registers can address, which doesn't fit in a u16 itself
+        self.index = (cpu.r[0xA] as usize % self.memory.len()) as u32;
+        self.pc = (cpu.r[5] as usize % self.memory.len()) as u16;
+
+        Ok(0)
     }
-}
 
-impl Chip8 {
-    /// Create a new instance of the emulator.
-    pub fn new(tx: mpsc::Sender<EmulatorResponse>, rx: mpsc::Receiver<EmulatorCommand>, mock_input: Option<mpsc::Receiver<String>>) -> Self {
-        let mut mem = [0u8; MEMORY_LENGTH_NBYTES];
-        Chip8::load_hex_sprites_into_memory(&mut mem);
+    /// Executes a MEGAON or MEGAOFF instruction.
+    ///
+    /// Switches MEGA-CHIP mode on, with a blank 256x192 true color screen, or off, going back to the SCHIP's
+    /// high resolution screen.
+    fn execute_mega(&mut self, on: bool) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        let (width, height) = self.platform.screen_sizes()[on as usize];
+        let framebuffer = self.display.framebuffer_mut();
+        framebuffer.enable_argb(false);
+        framebuffer.resize(height, width);
+        framebuffer.enable_argb(on);
+        self.hires = true;
 
-        Chip8 {
-            clock_rate_hz: DEFAULT_CPU_CLOCK_RATE_HZ,
-            debug_should_exit: false,
-            debugrx: rx,
-            debugtx: tx,
-            delay_timer_value: 0,
-            memory: mem,
-            registers: RegisterArray::new(),
-            pc: PROGRAM_START_BYTE_ADDR,
-            index: 0,
-            input: keyboard::Keyboard::new(mock_input),
-            instruction_count: 0,
-            should_clear_chip8_display: true,
-            sp: 0,
-            sound_timer_value: 0,
-            stack: [0u16; 16],
-            user_interface: gui::Gui::new(),
-        }
-    }
-
-    /// Loads sprites to represent the hex values "0" through "F" into memory at predefined locations.
-    fn load_hex_sprites_into_memory(memory: &mut [u8]) {
-        // These are the raw sprite values
-        let zero  = vec![0xF0, 0x90, 0x90, 0x90, 0xF0];
-        let one   = vec![0x20, 0x60, 0x20, 0x20, 0x70];
-        let two   = vec![0xF0, 0x10, 0xF0, 0x80, 0xF0];
-        let three = vec![0xF0, 0x10, 0xF0, 0x10, 0xF0];
-        let four  = vec![0x90, 0x90, 0xF0, 0x10, 0x10];
-        let five  = vec![0xF0, 0x80, 0xF0, 0x10, 0xF0];
-        let six   = vec![0xF0, 0x80, 0xF0, 0x90, 0xF0];
-        let seven = vec![0xF0, 0x10, 0x20, 0x40, 0x40];
-        let eight = vec![0xF0, 0x90, 0xF0, 0x90, 0xF0];
-        let nine  = vec![0xF0, 0x90, 0xF0, 0x10, 0xF0];
-        let hexa  = vec![0xF0, 0x90, 0xF0, 0x90, 0x90];
-        let hexb  = vec![0xE0, 0x90, 0xE0, 0x90, 0xE0];
-        let hexc  = vec![0xF0, 0x80, 0x80, 0x80, 0xF0];
-        let hexd  = vec![0xE0, 0x90, 0x90, 0x90, 0xE0];
-        let hexe  = vec![0xF0, 0x80, 0xF0, 0x80, 0xF0];
-        let hexf  = vec![0xF0, 0x80, 0xF0, 0x80, 0x80];
-
-        // These are where the sprites start and end
-        let zerostart  = HEX_SPRITE_ZERO_ADDR as usize;
-        let zeroend    = zerostart + zero.len();
-        let onestart   = HEX_SPRITE_ONE_ADDR as usize;
-        let oneend     = onestart + one.len();
-        let twostart   = HEX_SPRITE_TWO_ADDR as usize;
-        let twoend     = twostart + two.len();
-        let threestart = HEX_SPRITE_THREE_ADDR as usize;
-        let threeend   = threestart + three.len();
-        let fourstart  = HEX_SPRITE_FOUR_ADDR as usize;
-        let fourend    = fourstart + four.len();
-        let fivestart  = HEX_SPRITE_FIVE_ADDR as usize;
-        let fiveend    = fivestart + five.len();
-        let sixstart   = HEX_SPRITE_SIX_ADDR as usize;
-        let sixend     = sixstart + six.len();
-        let sevenstart = HEX_SPRITE_SEVEN_ADDR as usize;
-        let sevenend   = sevenstart + seven.len();
-        let eightstart = HEX_SPRITE_EIGHT_ADDR as usize;
-        let eightend   = eightstart + eight.len();
-        let ninestart  = HEX_SPRITE_NINE_ADDR as usize;
-        let nineend    = ninestart + nine.len();
-        let astart     = HEX_SPRITE_A_ADDR as usize;
-        let aend       = astart + hexa.len();
-        let bstart     = HEX_SPRITE_B_ADDR as usize;
-        let bend       = bstart + hexb.len();
-        let cstart     = HEX_SPRITE_C_ADDR as usize;
-        let cend       = cstart + hexc.len();
-        let dstart     = HEX_SPRITE_D_ADDR as usize;
-        let dend       = dstart + hexd.len();
-        let estart     = HEX_SPRITE_E_ADDR as usize;
-        let eend       = estart + hexe.len();
-        let fstart     = HEX_SPRITE_F_ADDR as usize;
-        let fend       = fstart + hexf.len();
-
-        // Now copy all the sprites into memory at their respective locations
-        memory[zerostart..zeroend].copy_from_slice(&zero);
-        memory[onestart..oneend].copy_from_slice(&one);
-        memory[twostart..twoend].copy_from_slice(&two);
-        memory[threestart..threeend].copy_from_slice(&three);
-        memory[fourstart..fourend].copy_from_slice(&four);
-        memory[fivestart..fiveend].copy_from_slice(&five);
-        memory[sixstart..sixend].copy_from_slice(&six);
-        memory[sevenstart..sevenend].copy_from_slice(&seven);
-        memory[eightstart..eightend].copy_from_slice(&eight);
-        memory[ninestart..nineend].copy_from_slice(&nine);
-        memory[astart..aend].copy_from_slice(&hexa);
-        memory[bstart..bend].copy_from_slice(&hexb);
-        memory[cstart..cend].copy_from_slice(&hexc);
-        memory[dstart..dend].copy_from_slice(&hexd);
-        memory[estart..eend].copy_from_slice(&hexe);
-        memory[fstart..fend].copy_from_slice(&hexf);
+        Ok(2)
     }
 
-    /// Attempts to load the given binary into RAM and run it.
-    pub fn load(&mut self, binary: &Vec<u8>) -> Result<(), String> {
-        if binary.len() < MAX_PROGRAM_SIZE_NBYTES {
-            let mut index = PROGRAM_START_BYTE_ADDR as usize;
-            for byte in binary {
-                self.memory[index] = *byte;
-                index += 1;
-            }
-            Ok(())
-        } else {
-            let mut msg = String::new();
-            write!(msg,
-                    "Binary is too large. Maximum size is {} bytes, but this binary is {} bytes.",
-                    MAX_PROGRAM_SIZE_NBYTES,
-                    binary.len()
-                  ).unwrap();
-            Err(msg)
-        }
+    /// Executes a LDHI instruction.
+    ///
+    /// The value of register I is set to the 24 bit address made of `nn` and the two bytes after the instruction,
+    /// and the PC moves past all four bytes.
+    fn execute_ldhi(&mut self, nn: u8) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        let addr = self.pc.wrapping_add(2);
+        self.check_range(addr as usize, 2)?;
+
+        self.index = ((nn as u32) << 16) | ((self.memory[addr as usize] as u32) << 8) | (self.memory[addr as usize + 1] as u32);
+
+        Ok(4)
     }
 
-    /// Runs the emulator forever (or until a user debugs it and issues the exit command).
-    pub fn run(&mut self) {
-        while let Some(pistonevent) = self.user_interface.next() {
-            // First check if we are being debugged and the user wants to exit
-            if self.debug_should_exit {
-                break;
-            }
+    /// Executes a LDPAL instruction.
+    ///
+    /// Palette entries 1 through `nn` are loaded with the 4 byte ARGB colors starting at I.
+    fn execute_ldpal(&mut self, nn: u8) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        self.check_range(self.index as usize, nn as usize * 4)?;
 
-            // Next check if we should decrement timers
-            let decrement_delay_timer: usize = (self.clock_rate_hz / DELAY_TIMER_CLOCK_RATE_HZ) as usize;
-            let decrement_sound_timer: usize = (self.clock_rate_hz / SOUND_TIMER_CLOCK_RATE_HZ) as usize;
-            if self.instruction_count % decrement_delay_timer == 0 {
-                self.delay_timer_value = if self.delay_timer_value > 0 { self.delay_timer_value - 1 } else { self.delay_timer_value };
-            }
-            if self.instruction_count % decrement_sound_timer == 0 {
-                self.sound_timer_value = if self.sound_timer_value > 0 { self.sound_timer_value - 1 } else { self.sound_timer_value };
-            }
+        for (i, color) in self.memory[self.index as usize..].chunks(4).take(nn as usize).enumerate() {
+            self.palette[i + 1] = color.iter().fold(0, |argb, byte| (argb << 8) | *byte as u32);
+        }
 
-            // Possibly clear the display
-            if self.should_clear_chip8_display {
-                self.user_interface.clear_chip8(&pistonevent);
-                self.should_clear_chip8_display = false;
-            }
+        Ok(2)
+    }
 
-            // Draw everything
-            self.user_interface.draw_chip8(&pistonevent);
-            self.user_interface.draw_ram(&pistonevent, self.pc, &self.memory);
-            self.user_interface.draw_stack(&pistonevent, self.sp, &self.stack);
-            self.user_interface.draw_paneling(&pistonevent);
-
-            // Fetch an instruction with pc
-            let msb = self.memory[self.pc as usize];
-            let lsb = self.memory[(self.pc + 1) as usize];
-            let instruction: u16 = ((msb as u16) << 8) | (lsb as u16);
-
-            // Decode opcode
-            let opcode = match Opcode::new(instruction) {
-                Ok(o) => o,
-                Err(msg) => {
-                    panic!("Problem with instruction {:x}: {}. State of us:\n{:?}", instruction, msg, self)
-                },
-            };
+    /// Executes a SPRW instruction.
+    ///
+    /// Sets the width of MEGA-CHIP sprites to `nn`, where 0 means 256.
+    fn execute_sprw(&mut self, nn: u8) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        self.sprite_width = nn;
 
+        Ok(2)
+    }
 
-            // Execute instruction and increment the PC
-            match self.execute(opcode) {
-                Ok(pcincr) => self.pc += pcincr as u16,
-                Err(msg) => {
-                    panic!("Problem executing instruction {:?}: {}. State of us:\n{:?}", opcode, msg, self)
-                },
-            }
+    /// Executes a SPRH instruction.
+    ///
+    /// Sets the height of MEGA-CHIP sprites to `nn`, where 0 means 256.
+    fn execute_sprh(&mut self, nn: u8) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        self.sprite_height = nn;
 
-            // Increment the instruction count
-            self.instruction_count = self.instruction_count.wrapping_add(1);
+        Ok(2)
+    }
+
+    /// Executes an ALPHA instruction.
+    ///
+    /// Sets how opaque the true color screen is. Outside of MEGA-CHIP mode, there is no such screen, so this does nothing.
+    fn execute_alpha(&mut self, nn: u8) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        if let Some(ref mut argb) = self.display.framebuffer_mut().argb {
+            argb.alpha = nn;
         }
+
+        Ok(2)
     }
 
-    /// Stop executing code and instead wait around on self.debugrx, executing debug commands we receive over the pipeline.
-    fn execute_brk(&mut self) -> EmuResult {
-        // Sit around waiting for debug commands
-        while let Ok(cmd) = self.debugrx.recv() {
+    /// Executes a DIGISND instruction.
+    ///
+    /// Starts playing the digitized sound at I, over and over if `n` is 0. The sound has a 6 byte header:
+    /// the sample rate in Hz in 2 bytes, the number of samples in 3, and a byte we ignore. The samples come right after.
+    fn execute_digisnd(&mut self, n: u8) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        let start = self.index as usize;
+        self.check_range(start, 6)?;
+        let header = &self.memory[start..start + 6];
+        let rate_hz = ((header[0] as u32) << 8) | header[1] as u32;
+        let nsamples = ((header[2] as usize) << 16) | ((header[3] as usize) << 8) | header[4] as usize;
+        self.check_range(start + 6, nsamples)?;
+
+        self.audio.play_samples(&self.memory[start + 6..start + 6 + nsamples], rate_hz, n == 0);
 
-            // Check the received command
-            match cmd {
-                // Get the I register and return it
-                EmulatorCommand::PeekI => {
-                    self.debugtx.send(EmulatorResponse::I(self.index)).unwrap();
-                },
+        Ok(2)
+    }
 
-                // Get the PC and return it
-                EmulatorCommand::PeekPC => {
-                    self.debugtx.send(EmulatorResponse::PC(self.pc)).unwrap();
-                },
+    /// Executes a STOPSND instruction.
+    ///
+    /// Stops the digitized sound.
+    fn execute_stopsnd(&mut self) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        self.audio.stop_samples();
 
-                // Get some bytes and return them
-                EmulatorCommand::PeekAddr(addr, nbytes) => {
-                    let mut bytes = Vec::<u8>::new();
-                    for i in 0..nbytes {
-                        bytes.push(self.memory[(addr as usize + i) as usize]);
-                    }
-                    self.debugtx.send(EmulatorResponse::MemorySlice(bytes)).unwrap();
-                },
+        Ok(2)
+    }
 
-                // Get the contents of a register
-                EmulatorCommand::PeekReg(regidx) => {
-                    let regval = match self.get_register(regidx) {
-                        Ok(r) => *r,
-                        Err(e) => panic!("Could not get requested register {}: {}", regidx, e),
-                    };
-                    self.debugtx.send(EmulatorResponse::Reg(regval)).unwrap();
-                },
+    /// Executes a BMODE instruction.
+    ///
+    /// Picks how MEGA-CHIP sprites are blended onto the screen.
+    fn execute_bmode(&mut self, n: u8) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        self.blend_mode = BlendMode::from_number(n).ok_or(ErrorKind::InvalidOpcode)?;
 
-                // Send back the SP
-                EmulatorCommand::PeekSP => {
-                    self.debugtx.send(EmulatorResponse::SP(self.sp)).unwrap();
-                },
+        Ok(2)
+    }
 
-                // Peek at the whole stack
-                EmulatorCommand::PeekStack => {
-                    self.debugtx.send(EmulatorResponse::Stack(self.stack.clone().to_vec())).unwrap();
-                },
+    /// Executes a CCOL instruction.
+    ///
+    /// Makes drawing a MEGA-CHIP sprite over pixels in palette entry `nn` count as a collision.
+    fn execute_ccol(&mut self, nn: u8) -> EmuResult {
+        self.only_on(&[Platform::MegaChip])?;
+        self.collision_index = nn;
 
-                // Peek at the sound timer
-                EmulatorCommand::PeekSoundTimer => {
-                    self.debugtx.send(EmulatorResponse::SoundTimer(self.sound_timer_value)).unwrap();
-                },
+        Ok(2)
+    }
 
-                // Break from the BRK loop
-                EmulatorCommand::ResumeExecution => break,
+    /// Is MEGA-CHIP mode on?
+    fn mega_mode(&self) -> bool {
+        self.display.framebuffer().argb.is_some()
+    }
 
-                // Exit the emulator thread
-                EmulatorCommand::Exit => { self.debug_should_exit = true; break },
+    /// Executes a BGCOL instruction.
+    ///
+    /// Steps the CHIP-8X background color on to the next one.
+    fn execute_bgcol(&mut self) -> EmuResult {
+        self.display.framebuffer_mut().cycle_background();
 
-                EmulatorCommand::SetClockRate(new_rate) => self.clock_rate_hz = new_rate,
-            }
-        }
         Ok(2)
     }
 
-    /// Executes a SYS instruction.
+    /// Executes a SCD instruction.
     ///
-    /// The SYS instruction jumps to a machine code routine at the given address.
-    /// This instruction is only used on the old computers on which Chip-8 was originally
-    /// implemented. It is ignored by modern interpreters.
-    fn execute_sys(&mut self, _addr: Address) -> EmuResult {
-        // Does nothing - NOP
+    /// Scrolls the display down by `n` pixels. Like on the SCHIP 1.1, these are always high resolution pixels,
+    /// so in low resolution mode this only goes half as far.
+    fn execute_scd(&mut self, n: u8) -> EmuResult {
+        self.scroll(0, n as i32);
+
+        Ok(2)
+    }
+
+    /// Executes a SCU instruction.
+    ///
+    /// Scrolls the selected planes up by `n` pixels.
+    fn execute_scu(&mut self, n: u8) -> EmuResult {
+        self.only_on(&[Platform::XoChip, Platform::MegaChip])?;
+        self.scroll(0, -(n as i32));
+
+        Ok(2)
+    }
+
+    /// Executes a SCR instruction.
+    ///
+    /// Scrolls the display right by 4 (high resolution) pixels.
+    fn execute_scr(&mut self) -> EmuResult {
+        self.scroll(4, 0);
+
+        Ok(2)
+    }
+
+    /// Executes a SCL instruction.
+    ///
+    /// Scrolls the display left by 4 (high resolution) pixels.
+    fn execute_scl(&mut self) -> EmuResult {
+        self.scroll(-4, 0);
+
+        Ok(2)
+    }
+
+    /// Scrolls the screen by (`dx`, `dy`) for one of the scroll instructions.
+    ///
+    /// On XO-CHIP, only the selected planes move, and the distance is in pixels of the current resolution,
+    /// so it goes twice as far in low resolution mode as it does on the SCHIP.
+    fn scroll(&mut self, dx: i32, dy: i32) {
+        if self.platform == Platform::XoChip {
+            let scale = self.scale() as i32;
+            self.display.scroll(dx * scale, dy * scale, self.planes);
+        } else {
+            self.display.scroll(dx, dy, ALL_PLANES);
+        }
+    }
+
+    /// Executes an EXIT instruction.
+    ///
+    /// Stops the interpreter. The PC stays on the EXIT.
+    fn execute_exit(&mut self) -> EmuResult {
+        self.debug_should_exit = true;
+
+        Ok(0)
+    }
+
+    /// Executes a LOW or HIGH instruction.
+    ///
+    /// Switches between the low and high resolution modes. Whatever is on the screen stays there,
+    /// except on XO-CHIP, which clears every plane.
+    fn execute_resolution(&mut self, hires: bool) -> EmuResult {
+        self.hires = hires;
+        if self.platform == Platform::XoChip {
+            self.display.clear(ALL_PLANES);
+        }
+
         Ok(2)
     }
 
     /// Executes a CLS instruction.
     ///
-    /// Clears the display.
+    /// Clears the display. On XO-CHIP, only the selected planes are cleared. In MEGA-CHIP mode, this shows everything
+    /// that has been drawn since the last CLS, and starts drawing on a blank screen.
     fn execute_cls(&mut self) -> EmuResult {
-        self.should_clear_chip8_display = true;
+        if self.mega_mode() {
+            self.display.framebuffer_mut().flip();
+            return Ok(2);
+        }
+        let planes = if self.platform == Platform::XoChip { self.planes } else { ALL_PLANES };
+        self.display.clear(planes);
 
         Ok(2)
     }
@@ -364,15 +1840,18 @@ impl Chip8 {
     /// Executes a RET instruction.
     ///
     /// Sets the program counter to the address at the top of the stack,
-    /// then subtracts one from the stack pointer.
+    /// then subtracts one from the stack pointer. In the VIP memory layout, the address comes out of RAM.
     fn execute_ret(&mut self) -> EmuResult {
-        if self.sp as usize >= self.stack.len() {
-            let mut errmsg = String::new();
-            write!(errmsg, "Stack pointer ({}) is too big.", self.sp).unwrap();
-            Err(errmsg)
+        if self.sp == 0 {
+            Err(ErrorKind::StackUnderflow)
         } else {
             self.sp -= 1;
-            self.pc = self.stack[self.sp as usize];
+            self.pc = if self.vip_layout {
+                let addr = VIP_STACK_ADDR + 2 * self.sp as usize;
+                ((self.memory[addr] as u16) << 8) | self.memory[addr + 1] as u16
+            } else {
+                self.stack[self.sp as usize]
+            };
             Ok(2)
         }
     }
@@ -382,9 +1861,7 @@ impl Chip8 {
     /// Sets the program counter to the given address.
     fn execute_jp(&mut self, addr: Address) -> EmuResult {
         if addr as usize >= self.memory.len() {
-            let mut errmsg = String::new();
-            write!(errmsg, "Address {} is larger than the memory.", addr).unwrap();
-            Err(errmsg)
+            Err(ErrorKind::AddressOutOfRange(addr as usize))
         } else {
             self.pc = addr;
             Ok(0)
@@ -394,14 +1871,19 @@ impl Chip8 {
     /// Executes a CALL instruction.
     ///
     /// Increments the stack pointer, puts the current program counter on top of the stack,
-    /// then sets the program counter to the given address.
+    /// then sets the program counter to the given address. The stack only holds as many addresses as the quirks say.
+    /// In the VIP memory layout, the address goes into RAM.
     fn execute_call(&mut self, addr: Address) -> EmuResult {
-        if (self.sp + 1) as usize >= self.stack.len() {
-            let mut errmsg = String::new();
-            write!(errmsg, "Stack pointer ({}) is greater than the length of the stack.", self.sp).unwrap();
-            Err(errmsg)
+        if self.sp as usize >= std::cmp::min(self.quirks.stack_depth, self.stack.len()) {
+            Err(ErrorKind::StackOverflow)
         } else {
             self.stack[self.sp as usize] = self.pc;
+            if self.vip_layout {
+                let addr = VIP_STACK_ADDR + 2 * self.sp as usize;
+                self.memory[addr] = (self.pc >> 8) as u8;
+                self.memory[addr + 1] = self.pc as u8;
+                self.mark_dirty(addr, 2);
+            }
             self.sp += 1;
             self.pc = addr;
             Ok(0)
@@ -413,13 +1895,10 @@ impl Chip8 {
     /// If the contents of register Vx equal `byte`, the program counter is incremented
     /// by 2 (in other words, we skip the next instruction).
     fn execute_sevxbyte(&mut self, x: Register, byte: u8) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
 
         if vx == byte {
-            Ok(4)
+            Ok(self.skip())
         } else {
             Ok(2)
         }
@@ -430,13 +1909,10 @@ impl Chip8 {
     /// If the contents of register Vx do NOT equal `byte`, the program counter is incremented
     /// by 2 (in other words, we skip the next instruction).
     fn execute_snevxbyte(&mut self, x: Register, byte: u8) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
 
         if vx != byte {
-            Ok(4)
+            Ok(self.skip())
         } else {
             Ok(2)
         }
@@ -447,18 +1923,12 @@ impl Chip8 {
     /// The values of Vx and Vy are compared and if they are equal, the program counter is
     /// incremented by 2.
     fn execute_sevxvy(&mut self, x: Register, y: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
 
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vy = *self.get_register(y)?;
 
         if vx == vy {
-            Ok(4)
+            Ok(self.skip())
         } else {
             Ok(2)
         }
@@ -468,10 +1938,7 @@ impl Chip8 {
     ///
     /// Stores the given byte in register `x`.
     fn execute_ldvxbyte(&mut self, x: Register, byte: u8) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
 
         *vx = byte;
 
@@ -482,12 +1949,9 @@ impl Chip8 {
     ///
     /// Adds the value `byte` to the contents of register Vx, then stores the result in Vx.
     fn execute_addvxbyte(&mut self, x: Register, byte: u8) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
 
-        *vx += byte;
+        *vx = vx.wrapping_add(byte);
 
         Ok(2)
     }
@@ -496,15 +1960,9 @@ impl Chip8 {
     ///
     /// Stores the value of register Vy in register Vx.
     fn execute_ldvxvy(&mut self, x: Register, y: Register) -> EmuResult {
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vy = *self.get_register(y)?;
 
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
 
         *vx = vy;
 
@@ -514,18 +1972,15 @@ impl Chip8 {
     /// Executes an OR instruction on registers `x` and `y`.
     ///
     /// Performs a bitwise OR on the values of Vx and Vy, then stores the result in Vx.
+    /// With the VF reset quirk, VF is then set to 0.
     fn execute_orvxvy(&mut self, x: Register, y: Register) -> EmuResult {
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vy = *self.get_register(y)?;
 
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
 
-        *vx = *vx | vy;
+        *vx |= vy;
+
+        self.reset_vf_after_logic();
 
         Ok(2)
     }
@@ -533,18 +1988,15 @@ impl Chip8 {
     /// Executes an AND instruction on registers `x` and `y`.
     ///
     /// Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx.
+    /// With the VF reset quirk, VF is then set to 0.
     fn execute_andvxvy(&mut self, x: Register, y: Register) -> EmuResult {
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vy = *self.get_register(y)?;
 
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
+
+        *vx &= vy;
 
-        *vx = *vx & vy;
+        self.reset_vf_after_logic();
 
         Ok(2)
     }
@@ -552,37 +2004,35 @@ impl Chip8 {
     /// Executes an XOR instruction on registers `x` and `y`.
     ///
     /// Performs a bitwise XOR on the values of Vx and Vy, then stores the result in Vx.
+    /// With the VF reset quirk, VF is then set to 0.
     fn execute_xorvxvy(&mut self, x: Register, y: Register) -> EmuResult {
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vy = *self.get_register(y)?;
 
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
 
-        *vx = *vx ^ vy;
+        *vx ^= vy;
+
+        self.reset_vf_after_logic();
 
         Ok(2)
     }
 
+    /// The VIP did its logic instructions with a routine that clobbered VF, so with the VF reset quirk, they all set VF to 0.
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers.vf = 0;
+        }
+    }
+
     /// Executes an ADD instruction on registers `x` and `y`.
     ///
     /// Adds the value in the register `y` to the value of register `x`, then stores the result in register `x`.
     /// If the result is greater than 255, VF is set to 1, otherwise it is set to 0.
     /// Only the lowest 8 bits are stored in Vx.
     fn execute_addvxvy(&mut self, x: Register, y: Register) -> EmuResult {
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vy = *self.get_register(y)?;
 
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
 
         let tmp: u16 = (*vx as u16) + (vy as u16);
 
@@ -602,20 +2052,14 @@ impl Chip8 {
     /// If Vx > Vy, then VF is set to 1, otherwise set it to 0. Then Vy is subtracted from Vx
     /// and the result is stored in Vx.
     fn execute_subvxvy(&mut self, x: Register, y: Register) -> EmuResult {
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vy = *self.get_register(y)?;
 
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
 
         let gt: bool = *vx > vy;
 
         if gt {
-            *vx = *vx - vy;
+            *vx -= vy;
             self.registers.vf = 1;
         } else {
             let tmp: i16 = (*vx).wrapping_sub(vy) as i16;  // Wrap around
@@ -630,14 +2074,16 @@ impl Chip8 {
     ///
     /// If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is
     /// bit shifted right by one (in other words, Vx is divided by 2).
-    fn execute_shrvx(&mut self, x: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+    ///
+    /// With the shift quirk, it is Vy that gets looked at and shifted, and the result goes in Vx.
+    fn execute_shrvx(&mut self, x: Register, y: Register) -> EmuResult {
+        let source = if self.quirks.shift_reads_vy { y } else { x };
+        let value = *self.get_register(source)?;
+
+        let vx = self.get_register(x)?;
 
-        let lsb_is_one: bool = (*vx & 0x01) == 1;
-        *vx = *vx >> 1;
+        let lsb_is_one: bool = (value & 0x01) == 1;
+        *vx = value >> 1;
         if lsb_is_one {
             self.registers.vf = 1;
         } else {
@@ -652,15 +2098,9 @@ impl Chip8 {
     /// If Vy > Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy
     /// and the result is stored in Vx.
     fn execute_subnvxvy(&mut self, x: Register, y: Register) -> EmuResult {
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vy = *self.get_register(y)?;
 
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
 
         let lt: bool = *vx < vy;
 
@@ -680,14 +2120,16 @@ impl Chip8 {
     ///
     /// If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is shifted
     /// left by one bit (in other words, Vx is multiplied by 2).
-    fn execute_shlvx(&mut self, x: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+    ///
+    /// With the shift quirk, it is Vy that gets looked at and shifted, and the result goes in Vx.
+    fn execute_shlvx(&mut self, x: Register, y: Register) -> EmuResult {
+        let source = if self.quirks.shift_reads_vy { y } else { x };
+        let value = *self.get_register(source)?;
 
-        let msb_is_one: bool = (*vx & 0x80) == 0x80;
-        *vx = *vx << 1;
+        let vx = self.get_register(x)?;
+
+        let msb_is_one: bool = (value & 0x80) == 0x80;
+        *vx = value << 1;
         if msb_is_one {
             self.registers.vf = 1;
         } else {
@@ -702,108 +2144,226 @@ impl Chip8 {
     /// The values of Vx and Vy are compared and if they are NOT equal, the program counter is
     /// incremented by 2.
     fn execute_snevxvy(&mut self, x: Register, y: Register) -> EmuResult {
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vy = *self.get_register(y)?;
 
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
 
         if vx != vy {
-            self.pc += 2;
+            Ok(self.skip())
+        } else {
+            Ok(2)
+        }
+    }
+
+    /// Executes a SAVE instruction on registers `x` through `y`.
+    ///
+    /// Copies the values of registers Vx through Vy (in that order, even if y is smaller than x) into memory,
+    /// starting at the address in I. I is left where it is.
+    fn execute_savevxvy(&mut self, x: Register, y: Register) -> EmuResult {
+        self.only_on(&[Platform::XoChip])?;
+        let regs = Chip8::register_range(x, y);
+        let nregs = regs.len();
+        self.check_range(self.index as usize, nregs)?;
+
+        for (offset, reg) in regs.into_iter().enumerate() {
+            let value = *self.get_register(reg)?;
+            self.memory[self.index as usize + offset] = value;
+        }
+        self.mark_dirty(self.index as usize, nregs);
+
+        Ok(2)
+    }
+
+    /// Executes a LOAD instruction on registers `x` through `y`.
+    ///
+    /// Reads values from memory starting at location I into registers Vx through Vy (in that order, even if
+    /// y is smaller than x). I is left where it is.
+    fn execute_loadvxvy(&mut self, x: Register, y: Register) -> EmuResult {
+        self.only_on(&[Platform::XoChip])?;
+        let regs = Chip8::register_range(x, y);
+        self.check_range(self.index as usize, regs.len())?;
+
+        for (offset, reg) in regs.into_iter().enumerate() {
+            let value = self.memory[self.index as usize + offset];
+            *self.get_register(reg)? = value;
         }
 
         Ok(2)
     }
 
+    /// The registers from `x` to `y`, counting down if `y` is smaller.
+    fn register_range(x: Register, y: Register) -> Vec<Register> {
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+
     /// Executes a LD instruction on register I and `addr`.
     ///
     /// The value of regsiter I is set to the value `addr`.
     fn execute_ldiaddr(&mut self, addr: Address) -> EmuResult {
-        self.index = addr;
+        self.index = addr as u32;
         Ok(2)
     }
 
     /// Executes a JP instruction on V0 and `addr`.
     ///
-    /// The program counter is set to `addr` plus the value of V0.
+    /// The program counter is set to `addr` plus the value of V0. With the jump quirk, the top nibble
+    /// of `addr` picks the register to add instead of V0.
     fn execute_jpv0addr(&mut self, addr: Address) -> EmuResult {
-        let mut msg = String::new();
-        if addr as usize > MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} is out of range of the RAM.", addr).unwrap();
-            Err(msg)
-        } else if (addr + self.registers.v0 as u16) as usize > MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} plus {} (the contents of V0) is out of range of the RAM.", addr, self.registers.v0).unwrap();
-            Err(msg)
+        let offset_register = if self.quirks.jump_uses_vx { (addr >> 8) as Register } else { 0 };
+        let offset = *self.get_register(offset_register)?;
+
+        let target = addr as usize + offset as usize;
+        if target >= self.memory.len() {
+            Err(ErrorKind::AddressOutOfRange(target))
         } else {
-            self.pc = addr + self.registers.v0 as u16;
+            self.pc = target as u16;
             Ok(0)
         }
     }
 
+    /// Executes a COL instruction on registers `x` and `y` and `n`.
+    ///
+    /// Colors a zone of the screen with the CHIP-8X foreground color in the low 3 bits of V(x+1). Zones are
+    /// 8 pixels wide. The low nibble of Vx is the first column of zones, and the high nibble is how many more
+    /// columns to color. If `n` is 0, zones are 4 pixels high, and Vy picks the rows of zones the same way.
+    /// Otherwise, `n` rows starting at row Vy get colored.
+    fn execute_colvxvyn(&mut self, x: Register, y: Register, n: u8) -> EmuResult {
+        self.only_on(&[Platform::Chip8X])?;
+        let vx = *self.get_register(x)? as u32;
+        let vy = *self.get_register(y)? as u32;
+        let color = *self.get_register((x + 1) & 0x0F)? & 0x07;
+
+        let (top, height) = if n == 0 {
+            ((vy & 0x0F) * 4, ((vy >> 4) + 1) * 4)
+        } else {
+            (vy, n as u32)
+        };
+        let (left, width) = ((vx & 0x0F) * 8, ((vx >> 4) + 1) * 8);
+
+        // The zones are in pixels of the current resolution, but the screen is always in high resolution pixels
+        let scale = self.scale();
+        self.display.framebuffer_mut().set_foreground(left * scale, top * scale, width * scale, height * scale, color);
+
+        Ok(2)
+    }
+
     /// Executes a RND instruction on `x` and byte `byte`.
     ///
     /// Generate a random number in the interval [0, 255], which is then ANDed with the value
     /// `byte`. The results are stored in Vx.
     fn execute_rndvxbyte(&mut self, x: Register, byte: u8) -> EmuResult {
-        let mut rng = thread_rng();
-        let result = byte & rng.gen_range(0, 256) as u8;
+        let result = byte & self.rng.next_byte(&self.memory);
+
+        let vx = self.get_register(x)?;
+
+        *vx = result;
+
+        Ok(2)
+    }
+
+    /// Executes a DRW instruction on registers `x` and `y` and nibble `byte`.
+    ///
+    /// Read `byte` bytes from memory, starting at the address stored in I. These bytes are then
+    /// displayed as sprites on screen at coordinates (Vx, Vy). Sprites are XORed onto the existing
+    /// screen. If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
+    /// If the sprite is positioned so part of it is outside the coordinates of the display, it wraps
+    /// around to the opposite side of the screen, or with the clipping quirk, that part is cut off.
+    ///
+    /// As on the SCHIP, a `byte` of 0 draws a 16x16 sprite (32 bytes) in high resolution mode, or an
+    /// 8x16 sprite in low resolution mode, and in high resolution mode VF is set to the number of rows
+    /// of the sprite that erased something instead.
+    ///
+    /// On XO-CHIP, a `byte` of 0 always draws a 16x16 sprite and VF is always 0 or 1. The sprite is drawn onto
+    /// each selected plane in turn, with the data for the first plane right after I and the data for the
+    /// second plane right after that.
+    fn execute_drwvxvynibble(&mut self, x: Register, y: Register, byte: u8) -> EmuResult {
+        let vx = *self.get_register(x)?;
+
+        let vy = *self.get_register(y)?;
+
+        if self.mega_mode() {
+            return self.draw_mega_sprite(vx, vy);
+        }
+
+        let xochip = self.platform == Platform::XoChip;
+        let (nbytes, wide) = self.sprite_shape(byte);
+        let planes = self.sprite_planes();
+        self.check_range(self.index as usize, nbytes * planes.len())?;
+
+        // The screen is always at the platform's highest resolution, so low resolution pixels may need to be scaled up
+        let scale = self.scale();
+        let mut ncollisions = 0;
+        for (i, plane) in planes.into_iter().enumerate() {
+            let start = self.index as usize + i * nbytes;
+            let combined_sprite = &self.memory[start..start + nbytes];
+            let pixsprite = if wide {
+                sprite::Sprite::wide(combined_sprite, vx as u32, vy as u32)
+            } else {
+                sprite::Sprite::new(combined_sprite, vx as u32, vy as u32)
+            };
 
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+            ncollisions += self.display.draw_sprite(pixsprite.scaled(scale).on_planes(plane).clipped(self.quirks.clip_sprites));
+        }
 
-        *vx = result;
+        self.registers.vf = if self.hires && !xochip { ncollisions as u8 } else { (ncollisions > 0) as u8 };
+        self.sprite_collided = ncollisions > 0;
 
         Ok(2)
     }
 
-    /// Executes a DRW instruction on registers `x` and `y` and nibble `byte`.
+    /// Draws a MEGA-CHIP sprite from I at (`x`, `y`), for a DRW in MEGA-CHIP mode.
     ///
-    /// Read `byte` bytes from memory, starting at the address stored in I. These bytes are then
-    /// displayed as sprites on screen at coordinates (Vx, Vy). Sprites are XORed onto the existing
-    /// screen. If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
-    /// If the sprite is positioned so part of it is outside the coordinates of the display, it wraps
-    /// around to the opposite side of the screen.
-    fn execute_drwvxvynibble(&mut self, x: Register, y: Register, byte: u8) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
-
-        let vy = match self.get_register(y) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
+    /// The sprite is as wide and high as SPRW and SPRH say, with one palette index per pixel. Palette entry 0 is
+    /// transparent, and whatever hangs off the edge of the screen is cut off. VF is set to 1 if any of it went
+    /// over something in the collision color, or 0 if not.
+    fn draw_mega_sprite(&mut self, x: u8, y: u8) -> EmuResult {
+        let (width, height) = self.mega_sprite_size();
+        let start = self.index as usize;
+        self.check_range(start, width * height)?;
+
+        let sprite = ArgbSprite {
+            indexes: &self.memory[start..start + width * height],
+            width: width as u32,
+            x: x as u32,
+            y: y as u32,
         };
+        let collision = self.display.framebuffer_mut().draw_argb(&sprite, &self.palette, self.blend_mode, self.collision_index);
+        self.registers.vf = collision as u8;
+        self.sprite_collided = collision;
 
-        let mut msg = String::new();
-        if self.index as usize >= MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} is too large for RAM.", self.index).unwrap();
-            return Err(msg);
-        } else if (self.index + byte as u16) as usize >= MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} plus {} (the number of bytes we need to read) is too large for RAM.", self.index, byte).unwrap();
-            return Err(msg);
-        }
+        Ok(2)
+    }
 
-        let mut combined_sprite = Vec::<u8>::new();
-        for i in 0..byte {
-            let idx: usize = (self.index + i as u16) as usize;
-            let sprite = self.memory[idx];
-            combined_sprite.push(sprite);
+    /// How many bytes a DRW with nibble `n` reads for each plane it draws on, and whether they make a 16x16 sprite,
+    /// given the mode we are in. Without the SCHIP's instructions, Dxy0 draws nothing. Not for MEGA-CHIP sprites,
+    /// which go by `mega_sprite_size` instead.
+    fn sprite_shape(&self, n: u8) -> (usize, bool) {
+        match (n, self.hires || self.platform == Platform::XoChip) {
+            (0, _) if !self.platform.has_schip() => (0, false),
+            (0, true) => (32, true),
+            (0, false) => (16, false),
+            (n, _) => (n as usize, false),
         }
+    }
 
-        let pixsprite = sprite::Sprite::new(&combined_sprite, vx as u32, vy as u32);
-        let collision = self.user_interface.draw_sprite(pixsprite.clone());
-
-        if collision {
-            self.registers.vf = if collision { 0x01 } else { 0x00 };
+    /// The planes a DRW draws on, in the order their sprites follow each other in RAM.
+    fn sprite_planes(&self) -> Vec<u8> {
+        if self.platform == Platform::XoChip {
+            [PLANE_1, PLANE_2].iter().cloned().filter(|p| self.planes & p != 0).collect()
+        } else {
+            vec![PLANE_1]
         }
+    }
 
-        Ok(2)
+    /// The width and height of the MEGA-CHIP sprites the program asked for, where 0 means 256.
+    fn mega_sprite_size(&self) -> (usize, usize) {
+        let width = if self.sprite_width == 0 { 256 } else { self.sprite_width as usize };
+        let height = if self.sprite_height == 0 { 256 } else { self.sprite_height as usize };
+        (width, height)
     }
 
     /// Executes a SKP instruction on register `x`.
@@ -811,18 +2371,10 @@ impl Chip8 {
     /// Checks the keyboard, and if the key corresponding to the value of Vx is currently
     /// in the down position, the program counter is increased by 2.
     fn execute_skpvx(&mut self, x: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
-
-        let key = match keyboard::map(vx) {
-            Err(_) => return Ok(2),  // If the user presses a key that isn't part of the keyboard, we'll just ignore it.
-            Ok(k) => k,
-        };
+        let vx = *self.get_register(x)?;
 
-        if self.input.check_keyboard_for_key(key) {
-            Ok(4)
+        if self.keys().is_key_pressed(vx) {
+            Ok(self.skip())
         } else {
             Ok(2)
         }
@@ -833,20 +2385,40 @@ impl Chip8 {
     /// Checks the keyboard, and if the key corresponding to the value of Vx is currently
     /// in the up position, the program counter is increased by 2.
     fn execute_sknpvx(&mut self, x: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
 
-        let key = match keyboard::map(vx) {
-            Err(_) => return Ok(2), // If the user presses a key that isn't part of the keyboard, we'll just ignore it.
-            Ok(k) => k,
-        };
+        if self.keys().is_key_pressed(vx) {
+            Ok(2)
+        } else {
+            Ok(self.skip())
+        }
+    }
+
+    /// Executes a SKP2 instruction on register `x`.
+    ///
+    /// Like SKP, but for the key on the CHIP-8X's second keypad.
+    fn execute_skp2vx(&mut self, x: Register) -> EmuResult {
+        self.only_on(&[Platform::Chip8X])?;
+        let vx = *self.get_register(x)?;
+
+        if self.keys().is_second_key_pressed(vx) {
+            Ok(self.skip())
+        } else {
+            Ok(2)
+        }
+    }
 
-        if self.input.check_keyboard_for_key(key) {
+    /// Executes a SKNP2 instruction on register `x`.
+    ///
+    /// Like SKNP, but for the key on the CHIP-8X's second keypad.
+    fn execute_sknp2vx(&mut self, x: Register) -> EmuResult {
+        self.only_on(&[Platform::Chip8X])?;
+        let vx = *self.get_register(x)?;
+
+        if self.keys().is_second_key_pressed(vx) {
             Ok(2)
         } else {
-            Ok(4)
+            Ok(self.skip())
         }
     }
 
@@ -855,10 +2427,7 @@ impl Chip8 {
     /// The value of the delay timer is placed into Vx.
     fn execute_ldvxdt(&mut self, x: Register) -> EmuResult {
         let delay_timer_value = self.delay_timer_value;
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = self.get_register(x)?;
 
         *vx = delay_timer_value;
 
@@ -868,15 +2437,20 @@ impl Chip8 {
     /// Executes a LD instruction on register `x` from a key press.
     ///
     /// All execution stops until a key is pressed, then the value of that key
-    /// is stored in Vx.
+    /// is stored in Vx. While no key has been pressed, we leave the PC where it is so that
+    /// this instruction runs again next cycle (and the display and timers keep going).
     fn execute_ldvxk(&mut self, x: Register) -> EmuResult {
-        let byte = self.input.wait_for_keypress();
-        let vx = match self.get_register(x) {
-            Ok(r) => r,
-            Err(msg) => return Err(msg),
+        let byte = match self.keys().poll_keypress() {
+            Some(b) => b,
+            None => {
+                self.awaiting_key = true;
+                return Ok(0);
+            },
         };
 
+        let vx = self.get_register(x)?;
         *vx = byte;
+        self.awaiting_key = false;
 
         Ok(2)
     }
@@ -885,10 +2459,7 @@ impl Chip8 {
     ///
     /// The delay timer is set equal to the value of Vx.
     fn execute_lddtvx(&mut self, x: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
 
         self.delay_timer_value = vx;
 
@@ -899,10 +2470,7 @@ impl Chip8 {
     ///
     /// The sound timer is set to the value of Vx.
     fn execute_ldstvx(&mut self, x: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
 
         self.sound_timer_value = vx;
 
@@ -913,12 +2481,52 @@ impl Chip8 {
     ///
     /// The values of I and Vx are added, and the result is stored in I.
     fn execute_addivx(&mut self, x: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
+
+        self.index = self.wrap_index(self.index.wrapping_add(vx as u32));
+
+        Ok(2)
+    }
+
+    /// Executes a long LD instruction on register I.
+    ///
+    /// The value of register I is set to the 16 bit address in the two bytes after the instruction,
+    /// and the PC moves past all four bytes.
+    fn execute_ldilong(&mut self) -> EmuResult {
+        self.only_on(&[Platform::XoChip])?;
+        let addr = self.pc.wrapping_add(2);
+        self.check_range(addr as usize, 2)?;
+
+        self.index = ((self.memory[addr as usize] as u32) << 8) | (self.memory[addr as usize + 1] as u32);
+
+        Ok(4)
+    }
+
+    /// Executes a PLANE instruction.
+    ///
+    /// Selects the planes in the mask `n` for drawing, scrolling, and clearing.
+    fn execute_plane(&mut self, n: u8) -> EmuResult {
+        self.only_on(&[Platform::XoChip])?;
+        if n > ALL_PLANES {
+            return Err(ErrorKind::InvalidOpcode);
+        }
+        self.planes = n;
+
+        Ok(2)
+    }
+
+    /// Executes a big sprite LD instruction.
+    ///
+    /// The value of I is set to the location of the big (8x10) hexadecimal sprite
+    /// corresponding to the value of Vx.
+    fn execute_ldhfvx(&mut self, x: Register) -> EmuResult {
+        let vx = *self.get_register(x)?;
 
-        self.index += vx as u16;
+        if vx > 0x0F {
+            return Err(ErrorKind::NoSuchHexSprite(vx));
+        }
+        let bigstart = self.font_address as u32 + font::FONT_NBYTES as u32;
+        self.index = bigstart + vx as u32 * BYTES_PER_BIG_HEX_SPRITE as u32;
 
         Ok(2)
     }
@@ -926,32 +2534,14 @@ impl Chip8 {
     /// Executes a sprite LD instruction.
     ///
     /// The value of I is set to the location of the hexadecimal sprite
-    /// corresponding to the value of Vx.
+    /// corresponding to the value of Vx, in whatever font is loaded wherever it is.
     fn execute_ldfvx(&mut self, x: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
 
-        self.index = match vx {
-            0x00 => HEX_SPRITE_ZERO_ADDR,
-            0x01 => HEX_SPRITE_ONE_ADDR,
-            0x02 => HEX_SPRITE_TWO_ADDR,
-            0x03 => HEX_SPRITE_THREE_ADDR,
-            0x04 => HEX_SPRITE_FOUR_ADDR,
-            0x05 => HEX_SPRITE_FIVE_ADDR,
-            0x06 => HEX_SPRITE_SIX_ADDR,
-            0x07 => HEX_SPRITE_SEVEN_ADDR,
-            0x08 => HEX_SPRITE_EIGHT_ADDR,
-            0x09 => HEX_SPRITE_NINE_ADDR,
-            0x0A => HEX_SPRITE_A_ADDR,
-            0x0B => HEX_SPRITE_B_ADDR,
-            0x0C => HEX_SPRITE_C_ADDR,
-            0x0D => HEX_SPRITE_D_ADDR,
-            0x0E => HEX_SPRITE_E_ADDR,
-            0x0F => HEX_SPRITE_F_ADDR,
-            _ => return Err(format!("Cannot set I to the address of sprite {} because it does not exist. Valid sprites are 0x00 through 0x0F.", vx)),
-        };
+        if vx > 0x0F {
+            return Err(ErrorKind::NoSuchHexSprite(vx));
+        }
+        self.index = self.font_address as u32 + vx as u32 * BYTES_PER_HEX_SPRITE as u32;
 
         Ok(2)
     }
@@ -962,19 +2552,9 @@ impl Chip8 {
     /// memory at location I, the tens digit at location I+1, and the
     /// ones digit at location I+2.
     fn execute_ldbvx(&mut self, x: Register) -> EmuResult {
-        let vx = match self.get_register(x) {
-            Ok(r) => *r,
-            Err(msg) => return Err(msg),
-        };
+        let vx = *self.get_register(x)?;
 
-        let mut msg = String::new();
-        if self.index as usize >= MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} is too large for RAM.", self.index).unwrap();
-            return Err(msg);
-        } else if (self.index + 2u16) as usize >= MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} plus 2 (where we will store the BCD ones digit) is too large for RAM.", self.index).unwrap();
-            return Err(msg);
-        }
+        self.check_range(self.index as usize, 3)?;
 
         let hundreds = vx / 100;
         let vx = vx % 100;
@@ -987,6 +2567,7 @@ impl Chip8 {
         self.memory[self.index as usize] = hundreds;
         self.memory[(self.index + 1) as usize] = tens;
         self.memory[(self.index + 2) as usize] = ones;
+        self.mark_dirty(self.index as usize, 3);
 
         Ok(2)
     }
@@ -994,30 +2575,19 @@ impl Chip8 {
     /// Executes an array LD instruction for writing.
     ///
     /// Copies the values of registers V0 through Vx into memory,
-    /// starting at the address in I.
+    /// starting at the address in I. With the load/store quirk, I ends up just past the last one.
     fn execute_ldivx(&mut self, regx_index: Register) -> EmuResult {
-        let mut msg = String::new();
-        if self.index as usize >= MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} is too large for RAM.", self.index).unwrap();
-            return Err(msg);
-        } else if (self.index + regx_index as u16) as usize >= MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} plus {} (where we will read to) is too large for RAM.", self.index, regx_index).unwrap();
-            return Err(msg);
-        } else if regx_index as usize >= self.registers.len() {
-            write!(msg, "Requested register index {} is too large. We have {} registers.", regx_index, self.registers.len()).unwrap();
-            return Err(msg);
+        if regx_index as usize >= self.registers.len() {
+            return Err(ErrorKind::BadRegister(regx_index));
         }
+        self.check_range(self.index as usize, regx_index as usize + 1)?;
 
         for idx in 0..=regx_index {
-            let reg = match self.get_register(idx) {
-                Ok(r) => r,
-                Err(e) => {
-                    write!(msg, "Could not get a register corresponding to index {}: {}", idx, e).unwrap();
-                    return Err(msg);
-                }
-            };
-            self.memory[(self.index + idx as u16) as usize] = *reg;
+            let reg = *self.get_register(idx)?;
+            self.memory[(self.index + idx as u32) as usize] = reg;
         }
+        self.mark_dirty(self.index as usize, regx_index as usize + 1);
+        self.increment_i_after_load_store(regx_index);
 
         Ok(2)
     }
@@ -1025,31 +2595,79 @@ impl Chip8 {
     /// Executes an array LD instruction for reading.
     ///
     /// Reads values from memory starting at location I into
-    /// registers V0 through Vx.
+    /// registers V0 through Vx. With the load/store quirk, I ends up just past the last one.
     fn execute_ldvxi(&mut self, regx_index: Register) -> EmuResult {
-        let mut msg = String::new();
-        if self.index as usize >= MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} is too large for RAM.", self.index).unwrap();
-            return Err(msg);
-        } else if (self.index + regx_index as u16) as usize >= MEMORY_LENGTH_NBYTES {
-            write!(msg, "Address {} plus {} (where we will read to) is too large for RAM.", self.index, regx_index).unwrap();
-            return Err(msg);
-        } else if regx_index as usize >= self.registers.len() {
-            write!(msg, "Requested register index {} is too large. We have {} registers.", regx_index, self.registers.len()).unwrap();
-            return Err(msg);
+        if regx_index as usize >= self.registers.len() {
+            return Err(ErrorKind::BadRegister(regx_index));
         }
+        self.check_range(self.index as usize, regx_index as usize + 1)?;
 
         for idx in 0..=regx_index {
-            let tmp = self.memory[(self.index + idx as u16) as usize];
-            let reg = match self.get_register(idx) {
-                Ok(r) => r,
-                Err(e) => {
-                    write!(msg, "Could not get a register corresponding to index {}: {}", idx, e).unwrap();
-                    return Err(msg);
-                }
-            };
+            let tmp = self.memory[(self.index + idx as u32) as usize];
+            let reg = self.get_register(idx)?;
             *reg = tmp;
         }
+        self.increment_i_after_load_store(regx_index);
+
+        Ok(2)
+    }
+
+    /// With the load/store quirk, moves I past the registers V0 through Vx that were just saved or loaded.
+    fn increment_i_after_load_store(&mut self, regx_index: Register) {
+        if self.quirks.load_store_increments_i {
+            self.index = self.wrap_index(self.index.wrapping_add(regx_index as u32 + 1));
+        }
+    }
+
+    /// Executes a LD instruction into the RPL user flags from registers V0 through Vx.
+    ///
+    /// The flags are written out to disk too, if we have been told where, so they are still there next time.
+    fn execute_ldrvx(&mut self, regx_index: Register) -> EmuResult {
+        for idx in 0..=regx_index {
+            self.rpl_flags[idx as usize] = *self.get_register(idx)?;
+        }
+
+        if let Some(ref p) = self.rpl_path {
+            if let Err(e) = fs::write(p, self.rpl_flags) {
+                println!("Could not save the RPL user flags to {}: {:?}", p.display(), e);
+            }
+        }
+
+        Ok(2)
+    }
+
+    /// Executes a LD instruction into registers V0 through Vx from the RPL user flags.
+    fn execute_ldvxr(&mut self, regx_index: Register) -> EmuResult {
+        for idx in 0..=regx_index {
+            let flag = self.rpl_flags[idx as usize];
+            *self.get_register(idx)? = flag;
+        }
+
+        Ok(2)
+    }
+
+    /// Executes an OUT instruction on register `x`.
+    ///
+    /// Sends Vx out of the CHIP-8X output port.
+    fn execute_outvx(&mut self, x: Register) -> EmuResult {
+        self.only_on(&[Platform::Chip8X])?;
+        self.port_out = *self.get_register(x)?;
+
+        Ok(2)
+    }
+
+    /// Executes an IN instruction on register `x`.
+    ///
+    /// Waits for a byte on the CHIP-8X input port, then stores it in Vx. Like LDVxK, while there is nothing there,
+    /// we leave the PC where it is so that this instruction runs again next cycle.
+    fn execute_invx(&mut self, x: Register) -> EmuResult {
+        self.only_on(&[Platform::Chip8X])?;
+        let byte = match self.port_in.take() {
+            Some(b) => b,
+            None => return Ok(0),
+        };
+
+        *self.get_register(x)? = byte;
 
         Ok(2)
     }
@@ -1059,13 +2677,34 @@ impl Chip8 {
         match op {
             Opcode::BRK => self.execute_brk(),
             Opcode::SYS(addr) => self.execute_sys(addr),
+            Opcode::MEGAOFF => self.execute_mega(false),
+            Opcode::MEGAON => self.execute_mega(true),
+            Opcode::LDHI(nn) => self.execute_ldhi(nn),
+            Opcode::LDPAL(nn) => self.execute_ldpal(nn),
+            Opcode::SPRW(nn) => self.execute_sprw(nn),
+            Opcode::SPRH(nn) => self.execute_sprh(nn),
+            Opcode::ALPHA(nn) => self.execute_alpha(nn),
+            Opcode::DIGISND(n) => self.execute_digisnd(n),
+            Opcode::STOPSND => self.execute_stopsnd(),
+            Opcode::BMODE(n) => self.execute_bmode(n),
+            Opcode::CCOL(nn) => self.execute_ccol(nn),
+            Opcode::BGCOL => self.execute_bgcol(),
+            Opcode::SCD(n) => self.execute_scd(n),
+            Opcode::SCU(n) => self.execute_scu(n),
             Opcode::CLS => self.execute_cls(),
             Opcode::RET => self.execute_ret(),
+            Opcode::SCR => self.execute_scr(),
+            Opcode::SCL => self.execute_scl(),
+            Opcode::EXIT => self.execute_exit(),
+            Opcode::LOW => self.execute_resolution(false),
+            Opcode::HIGH => self.execute_resolution(true),
             Opcode::JP(addr) => self.execute_jp(addr),
             Opcode::CALL(addr) => self.execute_call(addr),
             Opcode::SEVxByte(x, kk) => self.execute_sevxbyte(x, kk),
             Opcode::SNEVxByte(x, kk) => self.execute_snevxbyte(x, kk),
             Opcode::SEVxVy(x, y) => self.execute_sevxvy(x, y),
+            Opcode::SAVEVxVy(x, y) => self.execute_savevxvy(x, y),
+            Opcode::LOADVxVy(x, y) => self.execute_loadvxvy(x, y),
             Opcode::LDVxByte(x, kk) => self.execute_ldvxbyte(x, kk),
             Opcode::ADDVxByte(x, kk) => self.execute_addvxbyte(x, kk),
             Opcode::LDVxVy(x, y) => self.execute_ldvxvy(x, y),
@@ -1074,30 +2713,91 @@ impl Chip8 {
             Opcode::XORVxVy(x, y) => self.execute_xorvxvy(x, y),
             Opcode::ADDVxVy(x, y) => self.execute_addvxvy(x, y),
             Opcode::SUBVxVy(x, y) => self.execute_subvxvy(x, y),
-            Opcode::SHRVx(x) => self.execute_shrvx(x),
+            Opcode::SHRVx(x, y) => self.execute_shrvx(x, y),
             Opcode::SUBNVxVy(x, y) => self.execute_subnvxvy(x, y),
-            Opcode::SHLVx(x) => self.execute_shlvx(x),
+            Opcode::SHLVx(x, y) => self.execute_shlvx(x, y),
             Opcode::SNEVxVy(x, y) => self.execute_snevxvy(x, y),
             Opcode::LDIAddr(addr) => self.execute_ldiaddr(addr),
             Opcode::JPV0Addr(addr) => self.execute_jpv0addr(addr),
+            Opcode::COLVxVyN(x, y, n) => self.execute_colvxvyn(x, y, n),
             Opcode::RNDVxByte(x, kk) => self.execute_rndvxbyte(x, kk),
             Opcode::DRWVxVyNibble(x, y, n) => self.execute_drwvxvynibble(x, y, n),
             Opcode::SKPVx(x) => self.execute_skpvx(x),
             Opcode::SKNPVx(x) => self.execute_sknpvx(x),
+            Opcode::SKP2Vx(x) => self.execute_skp2vx(x),
+            Opcode::SKNP2Vx(x) => self.execute_sknp2vx(x),
+            Opcode::LDILong => self.execute_ldilong(),
+            Opcode::PLANE(n) => self.execute_plane(n),
             Opcode::LDVxDT(x) => self.execute_ldvxdt(x),
             Opcode::LDVxK(x) => self.execute_ldvxk(x),
             Opcode::LDDTVx(x) => self.execute_lddtvx(x),
             Opcode::LDSTVx(x) => self.execute_ldstvx(x),
             Opcode::ADDIVx(x) => self.execute_addivx(x),
             Opcode::LDFVx(x) => self.execute_ldfvx(x),
+            Opcode::LDHFVx(x) => self.execute_ldhfvx(x),
             Opcode::LDBVx(x) => self.execute_ldbvx(x),
             Opcode::LDIVx(x) => self.execute_ldivx(x),
             Opcode::LDVxI(x) => self.execute_ldvxi(x),
+            Opcode::LDRVx(x) => self.execute_ldrvx(x),
+            Opcode::LDVxR(x) => self.execute_ldvxr(x),
+            Opcode::OUTVx(x) => self.execute_outvx(x),
+            Opcode::INVx(x) => self.execute_invx(x),
+        }
+    }
+
+    /// How far to move the PC to skip the next instruction. On XO-CHIP, the next instruction might be a long
+    /// F000 nnnn, in which case all four of its bytes get skipped.
+    fn skip(&self) -> usize {
+        let next = self.pc as usize + 2;
+        if self.platform == Platform::XoChip && self.memory.get(next..next + 2) == Some(&[0xF0, 0x00]) {
+            6
+        } else {
+            4
+        }
+    }
+
+    /// How many pixels wide on the screen each of the program's pixels is. The screen is always the platform's
+    /// highest resolution, so on a 128x64 screen, everything is drawn at double size in low resolution mode.
+    fn scale(&self) -> u32 {
+        if self.hires {
+            1
+        } else {
+            std::cmp::max(1, self.display.framebuffer().ncols / LORES_WIDTH)
+        }
+    }
+
+    /// Makes sure we are one of `platforms`, since everything else would see the current instruction as garbage.
+    fn only_on(&self, platforms: &[Platform]) -> Result<(), ErrorKind> {
+        if platforms.contains(&self.platform) {
+            Ok(())
+        } else {
+            Err(ErrorKind::InvalidOpcode)
+        }
+    }
+
+    /// Wraps a value for I around to fit in 16 bits, or 24 on MEGA-CHIP.
+    fn wrap_index(&self, index: u32) -> u32 {
+        if self.platform == Platform::MegaChip {
+            index & 0x00FF_FFFF
+        } else {
+            index & 0xFFFF
+        }
+    }
+
+    /// Makes sure that the `nbytes` bytes starting at `addr` are all inside RAM.
+    fn check_range(&self, addr: usize, nbytes: usize) -> Result<(), ErrorKind> {
+        let last = addr + nbytes.saturating_sub(1);
+        if addr >= self.memory.len() {
+            Err(ErrorKind::AddressOutOfRange(addr))
+        } else if last >= self.memory.len() {
+            Err(ErrorKind::AddressOutOfRange(last))
+        } else {
+            Ok(())
         }
     }
 
     /// Returns a mutable reference to the specified register if it exists.
-    fn get_register<'b>(&'b mut self, v: Register) -> Result<&'b mut Register, String> {
+    fn get_register(&mut self, v: Register) -> Result<&mut Register, ErrorKind> {
         match v {
             0 => Ok(&mut self.registers.v0),
             1 => Ok(&mut self.registers.v1),
@@ -1115,11 +2815,393 @@ impl Chip8 {
             13 => Ok(&mut self.registers.vd),
             14 => Ok(&mut self.registers.ve),
             15 => Ok(&mut self.registers.vf),
-            _ => {
-                let mut msg = String::new();
-                write!(msg, "Register {} does not exist.", v).unwrap();
-                Err(msg)
+            _ => Err(ErrorKind::BadRegister(v)),
+        }
+    }
+}
+
+/// Read-only views of the machine, for whoever is embedding us (and for `run_until` conditions).
+impl Chip8 {
+    /// The current program counter.
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// The current contents of register I.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// The current stack pointer.
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// The whole stack, including the slots above the stack pointer.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// The contents of register V`x`, if there is such a register.
+    pub fn register(&self, x: Register) -> Option<u8> {
+        self.registers.get(x)
+    }
+
+    /// All of RAM.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// The current value of the delay timer.
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer_value
+    }
+
+    /// The current value of the sound timer.
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer_value
+    }
+
+    /// The random number generator behind RND, as it stands right now.
+    pub fn rng(&self) -> Rng {
+        self.rng
+    }
+
+    /// The number of instructions executed so far (wrapping around).
+    pub fn instruction_count(&self) -> usize {
+        self.instruction_count
+    }
+
+    /// The clock rate of the CPU in Hz. Divide by 60 for the number of instructions in a frame.
+    pub fn clock_rate_hz(&self) -> u64 {
+        self.clock_rate_hz
+    }
+
+    /// Which machine we are pretending to be.
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// The choices about what instructions do that differ between platforms.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// The mask of the XO-CHIP planes that drawing, scrolling, and clearing work on.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// The last byte the program sent out of the CHIP-8X output port.
+    pub fn port_output(&self) -> u8 {
+        self.port_out
+    }
+
+    /// Are we in the SCHIP's high resolution mode?
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// How emulated time goes by.
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// With COSMAC VIP timing, the machine cycles the interpreter has spent so far.
+    pub fn cycle_count(&self) -> u64 {
+        self.cycle_count
+    }
+
+    /// Does SYS run machine code routines on the CDP1802?
+    pub fn is_cdp1802(&self) -> bool {
+        self.cdp1802
+    }
+
+    /// Are the screen and stack kept in RAM where the COSMAC VIP kept them?
+    pub fn is_vip_layout(&self) -> bool {
+        self.vip_layout
+    }
+
+    /// The SCHIP's RPL user flags.
+    pub fn rpl_flags(&self) -> &[u8] {
+        &self.rpl_flags
+    }
+
+    /// Is an LDVxK instruction waiting for a key press?
+    pub fn is_awaiting_key(&self) -> bool {
+        self.awaiting_key
+    }
+
+    /// The fault we are halted on, if we have hit one.
+    pub fn fault(&self) -> Option<&EmulatorError> {
+        self.fault.as_ref()
+    }
+
+    /// The pixels currently on the Chip-8 screen.
+    pub fn framebuffer(&self) -> &PixelGrid {
+        self.display.framebuffer()
+    }
+}
+
+/// Ways to reach in and change the machine, for whoever is embedding us (and for the debugger), to set up
+/// whatever situation they want to try out.
+impl Chip8 {
+    /// Writes `bytes` into RAM starting at `addr`.
+    pub fn poke(&mut self, addr: usize, bytes: &[u8]) -> Result<(), String> {
+        if addr + bytes.len() > self.memory.len() {
+            return Err(format!("{} bytes at 0x{:X} would run past the end of RAM at 0x{:X}.", bytes.len(), addr, self.memory.len()));
+        }
+        self.memory[addr..addr + bytes.len()].copy_from_slice(bytes);
+        self.mark_dirty(addr, bytes.len());
+        Ok(())
+    }
+
+    /// Sets register V`x` to `value`.
+    pub fn set_register(&mut self, x: Register, value: u8) -> Result<(), String> {
+        match self.get_register(x) {
+            Ok(v) => {
+                *v = value;
+                Ok(())
+            },
+            Err(kind) => Err(kind.to_string()),
+        }
+    }
+
+    /// Sets register I to `value`, wrapped around to fit in the platform's address space like ADD I, Vx would.
+    pub fn set_index(&mut self, value: u32) {
+        self.index = self.wrap_index(value);
+    }
+
+    /// Moves the PC to `pc`. This also gets us off any fault we were halted on, so we can carry on from there.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+        self.fault = None;
+        self.display_waiting = false;
+    }
+
+    /// Sets the stack pointer to `sp`, which can go as high as the number of slots in the stack.
+    pub fn set_sp(&mut self, sp: u8) -> Result<(), String> {
+        if sp as usize > self.stack.len() {
+            return Err(format!("The stack only has {} slots, so the SP can't be {}.", self.stack.len(), sp));
+        }
+        self.sp = sp;
+        Ok(())
+    }
+
+    /// Sets slot `n` of the stack to `addr`. In the VIP memory layout, that's in RAM too.
+    pub fn set_stack_entry(&mut self, n: usize, addr: u16) -> Result<(), String> {
+        if n >= self.stack.len() {
+            return Err(format!("The stack only has {} slots, so there is no slot {}.", self.stack.len(), n));
+        }
+        self.stack[n] = addr;
+        if self.vip_layout {
+            self.memory[VIP_STACK_ADDR + 2 * n] = (addr >> 8) as u8;
+            self.memory[VIP_STACK_ADDR + 2 * n + 1] = addr as u8;
+            self.mark_dirty(VIP_STACK_ADDR + 2 * n, 2);
+        }
+        Ok(())
+    }
+
+    /// Sets the delay timer to `value`.
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer_value = value;
+    }
+
+    /// Sets the sound timer to `value`. The tone comes on or goes off at the start of the next frame.
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer_value = value;
+    }
+
+    /// Sets the pixel at `x`, `y` (in the screen's own pixels) to `color`.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Pxcolor) -> Result<(), String> {
+        let framebuffer = self.display.framebuffer_mut();
+        if x >= framebuffer.ncols || y >= framebuffer.nrows {
+            return Err(format!("There is no pixel at {}, {} on a {}x{} screen.", x, y, framebuffer.ncols, framebuffer.nrows));
+        }
+        framebuffer.set_pixel_at(color, x as usize, y as usize);
+        framebuffer.has_changed = true;
+        Ok(())
+    }
+
+    /// Replaces the whole screen with `pixels`, which must be the same size as it is now.
+    pub fn set_framebuffer(&mut self, pixels: &PixelGrid) -> Result<(), String> {
+        let framebuffer = self.display.framebuffer();
+        if (pixels.ncols, pixels.nrows) != (framebuffer.ncols, framebuffer.nrows) || pixels.pixels.len() != framebuffer.pixels.len() {
+            return Err(format!("The screen is {}x{}, not {}x{}.", framebuffer.ncols, framebuffer.nrows, pixels.ncols, pixels.nrows));
+        }
+        self.restore_framebuffer(pixels);
+        Ok(())
+    }
+
+    /// Holds down exactly the keys in `mask` (bit n for key n) instead of reading the input backend, or with None,
+    /// goes back to reading it. Keys that go down count as presses for LDVxK. Movies go on using their own keys.
+    pub fn set_keys(&mut self, mask: Option<u16>) {
+        let mask = match mask {
+            Some(mask) => mask,
+            None => {
+                self.injected_keys = None;
+                return;
+            },
+        };
+
+        let keypad = self.injected_keys.get_or_insert_with(Keypad::new);
+        for key in 0..16 {
+            if mask & (1 << key) == 0 {
+                keypad.release(key);
+            } else if !keypad.is_key_pressed(key) {
+                keypad.press(key);
+            }
+        }
+    }
+}
+
+/// Breakpoints and watchpoints, which stop the machine without anything being written into the program.
+impl Chip8 {
+    /// Adds an enabled breakpoint or watchpoint that goes off on `condition`, and returns its id.
+    pub fn add_breakpoint(&mut self, condition: Condition) -> Result<usize, String> {
+        self.breakpoints.add(condition)
+    }
+
+    /// Removes the breakpoint with id `id`.
+    pub fn remove_breakpoint(&mut self, id: usize) -> Result<(), String> {
+        self.breakpoints.remove(id)
+    }
+
+    /// Enables or disables the breakpoint with id `id`, keeping its hit count.
+    pub fn enable_breakpoint(&mut self, id: usize, enabled: bool) -> Result<(), String> {
+        self.breakpoints.set_enabled(id, enabled)
+    }
+
+    /// Every breakpoint, enabled or not, with how many times each has gone off.
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        self.breakpoints.list()
+    }
+
+    /// Everything the watchpoints watch, as it is right now.
+    fn watched(&self) -> Watched<'_> {
+        let mut registers = [0u8; NUM_WATCHED_REGISTERS];
+        for (x, value) in registers.iter_mut().enumerate() {
+            *value = self.registers.get(x as Register).unwrap_or(0);
+        }
+        Watched {
+            memory: &self.memory,
+            registers,
+            index: self.index,
+            framebuffer: self.display.framebuffer(),
+            collided: self.sprite_collided,
+        }
+    }
+
+    /// What `opcode` is going to read and write, given the machine as it is right before it runs.
+    ///
+    /// Stack pushes and pops aren't counted, and neither is whatever machine code SYS runs. Watchpoints still
+    /// notice whatever those change.
+    fn footprint(&self, opcode: Opcode) -> Footprint {
+        let mut fp = Footprint::default();
+        let i = self.index as usize;
+        match opcode {
+            Opcode::SEVxByte(x, _) | Opcode::SNEVxByte(x, _) | Opcode::SKPVx(x) | Opcode::SKNPVx(x) | Opcode::SKP2Vx(x)
+                | Opcode::SKNP2Vx(x) | Opcode::LDDTVx(x) | Opcode::LDSTVx(x) | Opcode::OUTVx(x) => fp.read_register(x),
+            Opcode::SEVxVy(x, y) | Opcode::SNEVxVy(x, y) => {
+                fp.read_register(x);
+                fp.read_register(y);
+            },
+            Opcode::LDVxByte(x, _) | Opcode::RNDVxByte(x, _) | Opcode::LDVxDT(x) | Opcode::LDVxK(x) | Opcode::INVx(x) => {
+                fp.write_register(x);
+            },
+            Opcode::ADDVxByte(x, _) => {
+                fp.read_register(x);
+                fp.write_register(x);
+            },
+            Opcode::LDVxVy(x, y) => {
+                fp.read_register(y);
+                fp.write_register(x);
+            },
+            Opcode::ORVxVy(x, y) | Opcode::ANDVxVy(x, y) | Opcode::XORVxVy(x, y) => {
+                fp.read_register(x);
+                fp.read_register(y);
+                fp.write_register(x);
+                if self.quirks.logic_resets_vf {
+                    fp.write_register(0xF);
+                }
             },
+            Opcode::ADDVxVy(x, y) | Opcode::SUBVxVy(x, y) | Opcode::SUBNVxVy(x, y) => {
+                fp.read_register(x);
+                fp.read_register(y);
+                fp.write_register(x);
+                fp.write_register(0xF);
+            },
+            Opcode::SHRVx(x, y) | Opcode::SHLVx(x, y) => {
+                fp.read_register(if self.quirks.shift_reads_vy { y } else { x });
+                fp.write_register(x);
+                fp.write_register(0xF);
+            },
+            Opcode::LDIAddr(_) | Opcode::LDILong | Opcode::LDHI(_) => fp.index_written = true,
+            Opcode::JPV0Addr(addr) => fp.read_register(if self.quirks.jump_uses_vx { (addr >> 8) as u8 } else { 0 }),
+            Opcode::COLVxVyN(x, y, _) => {
+                fp.read_register(x);
+                fp.read_register(y);
+                fp.read_register(x + 1);
+            },
+            Opcode::DRWVxVyNibble(x, y, n) => {
+                fp.read_register(x);
+                fp.read_register(y);
+                fp.write_register(0xF);
+                fp.index_read = true;
+                fp.memory_read.push(i..i + self.sprite_len(n));
+            },
+            Opcode::ADDIVx(x) => {
+                fp.read_register(x);
+                fp.index_read = true;
+                fp.index_written = true;
+            },
+            Opcode::LDFVx(x) | Opcode::LDHFVx(x) => {
+                fp.read_register(x);
+                fp.index_written = true;
+            },
+            Opcode::LDBVx(x) => {
+                fp.read_register(x);
+                fp.index_read = true;
+                fp.memory_written.push(i..i + 3);
+            },
+            Opcode::LDIVx(x) | Opcode::LDVxI(x) => {
+                let store = matches!(opcode, Opcode::LDIVx(_));
+                for reg in 0..=x {
+                    if store { fp.read_register(reg) } else { fp.write_register(reg) }
+                }
+                fp.index_read = true;
+                fp.index_written = self.quirks.load_store_increments_i;
+                if store { fp.memory_written.push(i..i + x as usize + 1) } else { fp.memory_read.push(i..i + x as usize + 1) }
+            },
+            Opcode::SAVEVxVy(x, y) | Opcode::LOADVxVy(x, y) => {
+                let store = matches!(opcode, Opcode::SAVEVxVy(..));
+                let regs = Chip8::register_range(x, y);
+                let nbytes = regs.len();
+                for reg in regs {
+                    if store { fp.read_register(reg) } else { fp.write_register(reg) }
+                }
+                fp.index_read = true;
+                if store { fp.memory_written.push(i..i + nbytes) } else { fp.memory_read.push(i..i + nbytes) }
+            },
+            Opcode::LDRVx(x) => (0..=x).for_each(|reg| fp.read_register(reg)),
+            Opcode::LDVxR(x) => (0..=x).for_each(|reg| fp.write_register(reg)),
+            Opcode::LDPAL(nn) => {
+                fp.index_read = true;
+                fp.memory_read.push(i..i + 4 * nn as usize);
+            },
+            Opcode::DIGISND(_) => fp.index_read = true,
+            _ => (),
+        }
+        fp
+    }
+
+    /// The number of bytes a DRW with nibble `n` reads from I, given the mode we are in.
+    fn sprite_len(&self, n: u8) -> usize {
+        if self.mega_mode() {
+            let (width, height) = self.mega_sprite_size();
+            width * height
+        } else {
+            self.sprite_shape(n).0 * self.sprite_planes().len()
         }
     }
 }
@@ -0,0 +1,433 @@
+//! This module contains crash dumps: everything about the machine at the moment it faulted.
+//!
+//! A crash dump is a plain text file, so it can be read by a person, but it holds everything we need to
+//! put an emulator back into the exact same state for post-mortem inspection.
+
+use super::display::chip8panel::{PixelGrid, Pxcolor};
+use super::error::{EmulatorError, ErrorKind};
+use super::opcode::Opcode;
+use super::platform::Platform;
+use super::quirks::Quirks;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path;
+
+/// The first line of every crash dump, followed by the version of the layout.
+const HEADER: &str = "mychip8 crash dump v";
+/// The version of the layout we write. Bump this whenever the layout changes, and keep reading the old ones.
+/// Version 2 added the platform, quirks, hires mode, and load address, along with XO-CHIP's pixel planes and
+/// the CDP1802's faults. Version 3 leaves out the lines of RAM that are all zeros, and says how many lines there are.
+/// Version 4 split the SCHIP off from plain Chip-8, so a chip8 dump from before then is really an SCHIP one.
+const CRASH_DUMP_VERSION: u32 = 4;
+/// What each quirk that is on is called in a crash dump.
+const QUIRK_NAMES: [&str; 6] = ["shift_reads_vy", "load_store_increments_i", "logic_resets_vf", "jump_uses_vx", "clip_sprites", "display_wait"];
+/// How many bytes of RAM go on each line.
+const RAM_BYTES_PER_LINE: usize = 32;
+
+/// Everything about the machine at the moment it faulted.
+#[derive(Debug, Clone)]
+pub struct CrashDump {
+    /// The fault the machine was halted on, if there was one.
+    pub fault: Option<EmulatorError>,
+    /// Which machine it was pretending to be, or None if the dump is too old to say.
+    pub platform: Option<Platform>,
+    /// What its instructions did where platforms differ, or None if the dump is too old to say.
+    pub quirks: Option<Quirks>,
+    /// Was the screen in high resolution mode? None if the dump is too old to say.
+    pub hires: Option<bool>,
+    /// Where the program was loaded, or None if the dump is too old to say.
+    pub load_address: Option<u16>,
+    /// Program counter
+    pub pc: u16,
+    /// Register I
+    pub index: u32,
+    /// Stack pointer
+    pub sp: u8,
+    /// V0 through VF
+    pub registers: Vec<u8>,
+    /// The whole stack, including the slots above the stack pointer.
+    pub stack: Vec<u16>,
+    /// Value of the delay timer
+    pub delay_timer: u8,
+    /// Value of the sound timer
+    pub sound_timer: u8,
+    /// The number of instructions executed so far
+    pub instruction_count: usize,
+    /// The most recently executed instructions as (PC, instruction) pairs, oldest first.
+    pub history: Vec<(u16, u16)>,
+    /// All of RAM
+    pub memory: Vec<u8>,
+    /// The Chip-8 screen
+    pub framebuffer: PixelGrid,
+}
+
+impl CrashDump {
+    /// Writes this dump to the file at `path`, replacing whatever was there.
+    pub fn save(&self, path: &path::Path) -> Result<(), String> {
+        let mut f = match fs::File::create(path) {
+            Ok(f) => io::BufWriter::new(f),
+            Err(e) => return Err(format!("Could not create crash dump at {}: {:?}", path.display(), e)),
+        };
+
+        match self.write(&mut f) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(format!("Could not write crash dump to {}: {:?}", path.display(), e)),
+        }
+    }
+
+    /// Reads a dump back out of the file at `path`.
+    pub fn load(path: &path::Path) -> Result<Self, String> {
+        match fs::File::open(path) {
+            Ok(f) => CrashDump::read(io::BufReader::new(f)),
+            Err(e) => Err(format!("Could not open crash dump at {}: {:?}", path.display(), e)),
+        }
+    }
+
+    /// Writes this dump out as text.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{}{}", HEADER, CRASH_DUMP_VERSION)?;
+        match self.fault {
+            Some(err) => {
+                writeln!(w, "# {}", err)?;
+                writeln!(w, "fault: 0x{:04x} 0x{:04x} {}", err.pc, err.instruction, kind_to_string(err.kind))?;
+            },
+            None => writeln!(w, "fault: none")?,
+        }
+
+        // What kind of machine it was, so it can be put back together without guessing
+        let platform = self.platform.unwrap_or_default();
+        let quirks = self.quirks.unwrap_or_else(|| Quirks::for_platform(platform));
+        writeln!(w, "platform: {}", platform)?;
+        writeln!(w, "quirks: {}", quirks_to_string(quirks))?;
+        writeln!(w, "stack depth: {}", quirks.stack_depth)?;
+        writeln!(w, "hires: {}", self.hires.unwrap_or(false))?;
+        writeln!(w, "load address: 0x{:04x}", self.load_address.unwrap_or(0x0200))?;
+        writeln!(w, "pc: 0x{:04x}", self.pc)?;
+        writeln!(w, "i: 0x{:04x}", self.index)?;
+        writeln!(w, "sp: {}", self.sp)?;
+        writeln!(w, "dt: {}", self.delay_timer)?;
+        writeln!(w, "st: {}", self.sound_timer)?;
+        writeln!(w, "instruction count: {}", self.instruction_count)?;
+        writeln!(w, "v: {}", hex_list(self.registers.iter().map(|b| format!("{:02x}", b))))?;
+        writeln!(w, "stack: {}", hex_list(self.stack.iter().map(|a| format!("{:04x}", a))))?;
+
+        // The most recent instructions, decoded so a person can follow along
+        writeln!(w, "history: {}", self.history.len())?;
+        for (pc, instruction) in self.history.iter() {
+            match Opcode::for_platform(*instruction, platform) {
+                Ok(op) => writeln!(w, "  0x{:04x}: {:04x}  {}", pc, instruction, op)?,
+                Err(kind) => writeln!(w, "  0x{:04x}: {:04x}  {}", pc, instruction, kind)?,
+            }
+        }
+
+        // Most of a big machine's RAM is usually zeros, so those lines are left out, and each line says where it goes
+        let lines: Vec<(usize, &[u8])> = self.memory.chunks(RAM_BYTES_PER_LINE).enumerate().filter(|(_, line)| line.iter().any(|b| *b != 0)).collect();
+        writeln!(w, "ram: {} {}", self.memory.len(), lines.len())?;
+        for (lineno, line) in lines {
+            writeln!(w, "  0x{:04x}: {}", lineno * RAM_BYTES_PER_LINE, hex_list(line.iter().map(|b| format!("{:02x}", b))))?;
+        }
+
+        // One line of text per row of pixels, with '#' for pixels that are on ('+' and '%' for XO-CHIP's second plane and both planes)
+        writeln!(w, "framebuffer: {} {}", self.framebuffer.nrows, self.framebuffer.ncols)?;
+        for y in 0..self.framebuffer.nrows as usize {
+            let row: String = (0..self.framebuffer.ncols as usize).map(|x| {
+                match self.framebuffer.get_pixel_at(x, y).value {
+                    Pxcolor::Black => '#',
+                    Pxcolor::White => '.',
+                    Pxcolor::Plane2 => '+',
+                    Pxcolor::BothPlanes => '%',
+                }
+            }).collect();
+            writeln!(w, "  {}", row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a dump that was written with `write`.
+    pub fn read<R: BufRead>(r: R) -> Result<Self, String> {
+        let mut lines = Lines { lines: r.lines(), lineno: 0 };
+
+        let header = lines.next_line()?;
+        if !header.starts_with(HEADER) {
+            return Err(format!("Not a crash dump: expected '{}{}' but found '{}'.", HEADER, CRASH_DUMP_VERSION, header));
+        }
+        let version = match header[HEADER.len()..].parse::<u32>() {
+            Ok(v) if (1..=CRASH_DUMP_VERSION).contains(&v) => v,
+            _ => return Err(format!("This crash dump is '{}', but we only understand versions 1 through {}.", header, CRASH_DUMP_VERSION)),
+        };
+
+        let fault = {
+            let value = lines.field("fault")?;
+            if value == "none" {
+                None
+            } else {
+                let parts: Vec<&str> = value.splitn(3, ' ').collect();
+                if parts.len() != 3 {
+                    return Err(format!("Line {}: could not understand fault '{}'.", lines.lineno, value));
+                }
+                Some(EmulatorError {
+                    pc: parse_hex(parts[0])? as u16,
+                    instruction: parse_hex(parts[1])? as u16,
+                    kind: string_to_kind(parts[2])?,
+                })
+            }
+        };
+        let (platform, quirks, hires, load_address) = if version >= 2 {
+            let platform = match lines.field("platform")?.parse::<Platform>()? {
+                Platform::Chip8 if version < 4 => Platform::SuperChip,
+                platform => platform,
+            };
+            let mut quirks = string_to_quirks(&lines.field("quirks")?)?;
+            quirks.stack_depth = parse_dec(&lines.field("stack depth")?)?;
+            let hires = match lines.field("hires")?.as_str() {
+                "true" => true,
+                "false" => false,
+                value => return Err(format!("Line {}: expected true or false, but found '{}'.", lines.lineno, value)),
+            };
+            let load_address = parse_hex(&lines.field("load address")?)? as u16;
+            (Some(platform), Some(quirks), Some(hires), Some(load_address))
+        } else {
+            (None, None, None, None)
+        };
+        let pc = parse_hex(&lines.field("pc")?)? as u16;
+        let index = parse_hex(&lines.field("i")?)? as u32;
+        let sp = parse_dec(&lines.field("sp")?)? as u8;
+        let delay_timer = parse_dec(&lines.field("dt")?)? as u8;
+        let sound_timer = parse_dec(&lines.field("st")?)? as u8;
+        let instruction_count = parse_dec(&lines.field("instruction count")?)?;
+        let registers = parse_hex_list(&lines.field("v")?)?.iter().map(|v| *v as u8).collect();
+        let stack = parse_hex_list(&lines.field("stack")?)?.iter().map(|v| *v as u16).collect();
+
+        let nhistory = parse_dec(&lines.field("history")?)?;
+        let mut history = Vec::<(u16, u16)>::new();
+        for _ in 0..nhistory {
+            let line = lines.next_line()?;
+            let mut words = line.split_whitespace();
+            let pc = words.next().map(|w| w.trim_end_matches(':')).unwrap_or("");
+            let instruction = words.next().unwrap_or("");
+            history.push((parse_hex(pc)? as u16, parse_hex(instruction)? as u16));
+        }
+
+        let memory = if version >= 3 {
+            let counts = parse_dec_list(&lines.field("ram")?)?;
+            if counts.len() != 2 {
+                return Err(format!("Line {}: expected the number of bytes and lines of RAM.", lines.lineno));
+            }
+            let max_memory_len = Platform::ALL.iter().map(|p| p.max_memory_len()).max().unwrap_or(0);
+            if counts[0] > max_memory_len {
+                return Err(format!("Line {}: {} bytes of RAM is more than any platform can address.", lines.lineno, counts[0]));
+            }
+
+            // Whatever lines aren't there were all zeros
+            let mut memory = vec![0; counts[0]];
+            for _ in 0..counts[1] {
+                let (addr, bytes) = lines.ram_line()?;
+                if addr + bytes.len() > memory.len() {
+                    return Err(format!("Line {}: RAM only goes up to 0x{:x}.", lines.lineno, memory.len()));
+                }
+                memory[addr..addr + bytes.len()].copy_from_slice(&bytes);
+            }
+            memory
+        } else {
+            let nbytes = parse_dec(&lines.field("ram")?)?;
+            let mut memory = Vec::<u8>::new();
+            while memory.len() < nbytes {
+                memory.extend(lines.ram_line()?.1);
+            }
+            if memory.len() != nbytes {
+                return Err(format!("Expected {} bytes of RAM, but found {}.", nbytes, memory.len()));
+            }
+            memory
+        };
+
+        let dims = parse_dec_list(&lines.field("framebuffer")?)?;
+        if dims.len() != 2 {
+            return Err(format!("Line {}: expected the framebuffer's rows and columns.", lines.lineno));
+        }
+        let (maxcols, maxrows) = Platform::largest_screen();
+        if dims[1] > maxcols as usize || dims[0] > maxrows as usize {
+            return Err(format!("Line {}: a {}x{} framebuffer is bigger than any platform's screen.", lines.lineno, dims[1], dims[0]));
+        }
+        let mut framebuffer = PixelGrid::new(dims[0] as u32, dims[1] as u32);
+        for y in 0..dims[0] {
+            let line = lines.next_line()?;
+            if line.len() != dims[1] {
+                return Err(format!("Line {}: expected {} pixels, but found {}.", lines.lineno, dims[1], line.len()));
+            }
+            for (x, c) in line.chars().enumerate() {
+                let value = match c {
+                    '#' => Pxcolor::Black,
+                    '.' => Pxcolor::White,
+                    '+' => Pxcolor::Plane2,
+                    '%' => Pxcolor::BothPlanes,
+                    _ => return Err(format!("Line {}: '{}' is not a pixel.", lines.lineno, c)),
+                };
+                framebuffer.set_pixel_at(value, x, y);
+            }
+        }
+
+        Ok(CrashDump {
+            fault,
+            platform,
+            quirks,
+            hires,
+            load_address,
+            pc,
+            index,
+            sp,
+            registers,
+            stack,
+            delay_timer,
+            sound_timer,
+            instruction_count,
+            history,
+            memory,
+            framebuffer,
+        })
+    }
+}
+
+/// The lines of a crash dump, skipping blank lines and comments, and keeping track of where we are for error messages.
+struct Lines<R: BufRead> {
+    lines: io::Lines<R>,
+    lineno: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+    /// Returns the next line that has anything in it, without any surrounding whitespace.
+    fn next_line(&mut self) -> Result<String, String> {
+        loop {
+            self.lineno += 1;
+            match self.lines.next() {
+                Some(Ok(line)) => {
+                    let line = line.trim();
+                    if !line.is_empty() && !line.starts_with('#') {
+                        return Ok(line.to_string());
+                    }
+                },
+                Some(Err(e)) => return Err(format!("Line {}: could not read: {:?}", self.lineno, e)),
+                None => return Err("The crash dump ends too early.".to_string()),
+            }
+        }
+    }
+
+    /// Returns the value of the next line, which must look like "`name`: value".
+    fn field(&mut self, name: &str) -> Result<String, String> {
+        let line = self.next_line()?;
+        let prefix = format!("{}:", name);
+        if line.starts_with(&prefix) {
+            Ok(line[prefix.len()..].trim().to_string())
+        } else {
+            Err(format!("Line {}: expected '{}' but found '{}'.", self.lineno, prefix, line))
+        }
+    }
+
+    /// Returns the address and bytes on the next line, which must be a line of RAM.
+    fn ram_line(&mut self) -> Result<(usize, Vec<u8>), String> {
+        let line = self.next_line()?;
+        match line.find(':') {
+            Some(colon) => Ok((parse_hex(&line[..colon])?, parse_hex_list(&line[colon + 1..])?.iter().map(|b| *b as u8).collect())),
+            None => Err(format!("Line {}: expected a line of RAM, but found '{}'.", self.lineno, line)),
+        }
+    }
+}
+
+/// Joins a bunch of already-formatted numbers with spaces.
+fn hex_list<I: Iterator<Item = String>>(items: I) -> String {
+    items.collect::<Vec<String>>().join(" ")
+}
+
+/// Parses a hexadecimal number, with or without a leading 0x.
+fn parse_hex(s: &str) -> Result<usize, String> {
+    let digits = s.trim_start_matches("0x");
+    match usize::from_str_radix(digits, 16) {
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("'{}' is not a hexadecimal number.", s)),
+    }
+}
+
+/// Parses a decimal number.
+fn parse_dec(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("'{}' is not a number.", s)),
+    }
+}
+
+/// Parses a bunch of hexadecimal numbers separated by whitespace.
+fn parse_hex_list(s: &str) -> Result<Vec<usize>, String> {
+    s.split_whitespace().map(parse_hex).collect()
+}
+
+/// Parses a bunch of decimal numbers separated by whitespace.
+fn parse_dec_list(s: &str) -> Result<Vec<usize>, String> {
+    s.split_whitespace().map(parse_dec).collect()
+}
+
+/// Lists the quirks that are on by name, the way `string_to_quirks` expects to read them. The stack depth isn't included.
+fn quirks_to_string(mut quirks: Quirks) -> String {
+    let on: Vec<&str> = QUIRK_NAMES.iter().zip(quirk_flags(&mut quirks).iter()).filter(|(_, on)| ***on).map(|(name, _)| *name).collect();
+    if on.is_empty() {
+        "none".to_string()
+    } else {
+        on.join(" ")
+    }
+}
+
+/// Reads the quirks that were written with `quirks_to_string`. Every quirk not named is off.
+fn string_to_quirks(s: &str) -> Result<Quirks, String> {
+    let mut quirks = Quirks::default();
+    if s == "none" {
+        return Ok(quirks);
+    }
+    for name in s.split_whitespace() {
+        match QUIRK_NAMES.iter().position(|n| *n == name) {
+            Some(n) => *quirk_flags(&mut quirks)[n] = true,
+            None => return Err(format!("'{}' is not a quirk.", name)),
+        }
+    }
+    Ok(quirks)
+}
+
+/// The quirks that are either on or off, in the same order as `QUIRK_NAMES`.
+fn quirk_flags(quirks: &mut Quirks) -> [&mut bool; 6] {
+    [
+        &mut quirks.shift_reads_vy,
+        &mut quirks.load_store_increments_i,
+        &mut quirks.logic_resets_vf,
+        &mut quirks.jump_uses_vx,
+        &mut quirks.clip_sprites,
+        &mut quirks.display_wait,
+    ]
+}
+
+/// Writes an ErrorKind the way `string_to_kind` expects to read it.
+fn kind_to_string(kind: ErrorKind) -> String {
+    match kind {
+        ErrorKind::InvalidOpcode => "InvalidOpcode".to_string(),
+        ErrorKind::StackOverflow => "StackOverflow".to_string(),
+        ErrorKind::StackUnderflow => "StackUnderflow".to_string(),
+        ErrorKind::AddressOutOfRange(addr) => format!("AddressOutOfRange 0x{:x}", addr),
+        ErrorKind::BadRegister(v) => format!("BadRegister 0x{:x}", v),
+        ErrorKind::NoSuchHexSprite(v) => format!("NoSuchHexSprite 0x{:x}", v),
+        ErrorKind::MachineCodeRunaway => "MachineCodeRunaway".to_string(),
+    }
+}
+
+/// Reads an ErrorKind that was written with `kind_to_string`.
+fn string_to_kind(s: &str) -> Result<ErrorKind, String> {
+    let mut words = s.split_whitespace();
+    let name = words.next().unwrap_or("");
+    let arg = words.next().map(parse_hex);
+    match (name, arg) {
+        ("InvalidOpcode", None) => Ok(ErrorKind::InvalidOpcode),
+        ("StackOverflow", None) => Ok(ErrorKind::StackOverflow),
+        ("StackUnderflow", None) => Ok(ErrorKind::StackUnderflow),
+        ("AddressOutOfRange", Some(addr)) => Ok(ErrorKind::AddressOutOfRange(addr?)),
+        ("BadRegister", Some(v)) => Ok(ErrorKind::BadRegister(v? as u8)),
+        ("NoSuchHexSprite", Some(v)) => Ok(ErrorKind::NoSuchHexSprite(v? as u8)),
+        ("MachineCodeRunaway", None) => Ok(ErrorKind::MachineCodeRunaway),
+        _ => Err(format!("'{}' is not a kind of fault.", s)),
+    }
+}
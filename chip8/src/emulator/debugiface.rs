@@ -1,13 +1,94 @@
 //! This module contains the debug commands and responses, mostly to refactor them out of the chip8 module.
 
 use super::Address;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc;
+use super::breakpoint::{Breakpoint, Condition};
+use super::chip8::StopReason;
+use super::display::chip8panel::{PixelGrid, Pxcolor};
+use super::error::EmulatorError;
+
+/// What the emulator thread does when it reaches a BRK with no debugger attached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BreakPolicy {
+    /// Carry on as if it weren't there.
+    Ignore,
+    /// Say where it was, then carry on.
+    Log,
+    /// Stop and wait for debug commands, as if a debugger were attached. If nobody can ever send any, the
+    /// emulator thread is done.
+    #[default]
+    Halt,
+}
+
+impl BreakPolicy {
+    /// Every policy, the quietest first.
+    pub const ALL: [BreakPolicy; 3] = [BreakPolicy::Ignore, BreakPolicy::Log, BreakPolicy::Halt];
+
+    /// What this policy is called on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            BreakPolicy::Ignore => "ignore",
+            BreakPolicy::Log => "log",
+            BreakPolicy::Halt => "halt",
+        }
+    }
+}
+
+impl fmt::Display for BreakPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for BreakPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match BreakPolicy::ALL.iter().find(|p| p.name() == s) {
+            Some(p) => Ok(*p),
+            None => {
+                let names: Vec<&str> = BreakPolicy::ALL.iter().map(|p| p.name()).collect();
+                Err(format!("{} is not a BRK policy. Try one of {}.", s, names.join(", ")))
+            },
+        }
+    }
+}
 
 /// The different commands the emulator understands. Used for debugging.
+///
+/// The emulator answers them at the start of every frame while it runs, and right away while it is stopped
+/// (at a BRK, on a fault, or after a Pause). A peek that can't be answered gets an `Ack` with the reason instead.
+/// If the other end of the response pipe is gone, the debugger counts as detached.
 #[derive(Debug)]
 pub enum EmulatorCommand {
+    /// Add a breakpoint or watchpoint that goes off on the condition. We answer with its id.
+    AddBreakpoint(Condition),
+    /// Start debugging: from now on, BRKs always stop the machine, and whenever it stops we say so over the given
+    /// pipe. If it is already stopped, we say so right away. If the other end of that pipe is gone, the debugger
+    /// counts as detached.
+    Attach(mpsc::Sender<EmulatorEvent>),
+    /// Stop debugging: BRKs go back to the `BreakPolicy`, and a stopped machine carries on.
+    Detach,
+    /// Enable or disable the breakpoint with the given id. Its hit count stays as it was.
+    EnableBreakpoint(usize, bool),
     /// Exit the emulator thread.
     Exit,
-    /// Peek from address to address + nbytes.
+    /// Load the whole machine from the save state at the given path.
+    LoadState(PathBuf),
+    /// Stop the machine before its next instruction, until ResumeExecution. Does nothing if it is already stopped.
+    Pause,
+    /// Peek at the delay timer's current value.
+    PeekDelayTimer,
+    /// Peek at the fault the emulator is halted on, if any.
+    PeekFault,
+    /// Peek at the whole screen.
+    PeekFramebuffer,
+    /// Peek at every breakpoint and watchpoint, with their hit counts.
+    PeekBreakpoints,
+    /// Peek from address to address + nbytes, which has to stay inside RAM.
     PeekAddr(Address, usize),
     /// Peek at register I.
     PeekI,
@@ -21,17 +102,59 @@ pub enum EmulatorCommand {
     PeekSP,
     /// Peek at the whole stack.
     PeekStack,
-    /// Resume normal execution of the program.
+    /// Peek at why the machine is stopped, if it is.
+    PeekStopped,
+    /// Write the bytes into RAM, starting at the address.
+    PokeAddr(Address, Vec<u8>),
+    /// Set the pixel at the given x and y (in the screen's own pixels) to the given color.
+    PokePixel(u32, u32, Pxcolor),
+    /// Set the given register to the given value.
+    PokeReg(u8, u8),
+    /// Set the given slot of the stack to the given address.
+    PokeStack(usize, u16),
+    /// Remove the breakpoint with the given id.
+    RemoveBreakpoint(usize),
+    /// Resume normal execution of the program. Does nothing if it is already running.
     ResumeExecution,
+    /// Save the whole machine to the given path.
+    SaveState(PathBuf),
     /// Set the clock rate to the given value.
     SetClockRate(u64),
+    /// Set the delay timer to the given value.
+    SetDelayTimer(u8),
+    /// Replace the whole screen, which must stay the same size.
+    SetFramebuffer(PixelGrid),
+    /// Set register I to the given value.
+    SetI(u32),
+    /// Hold down exactly the keys in the given mask (bit n for key n) instead of reading the real ones, or go back to
+    /// the real ones with None.
+    SetKeys(Option<u16>),
+    /// Move the PC to the given address.
+    SetPC(u16),
+    /// Set the SP to the given value.
+    SetSP(u8),
+    /// Set the sound timer to the given value.
+    SetSoundTimer(u8),
 }
 
 /// The possible responses from the emulator in response to EmulatorCommands
 #[derive(Debug)]
 pub enum EmulatorResponse {
+    /// Returns whether a command that does something (rather than peeking at something) worked, or why a peek
+    /// couldn't be answered.
+    Ack(Result<(), String>),
+    /// Returns the id of the breakpoint that AddBreakpoint added, or why it couldn't.
+    BreakpointAdded(Result<usize, String>),
+    /// Returns every breakpoint and watchpoint.
+    Breakpoints(Vec<Breakpoint>),
+    /// Returns the current value of the delay timer.
+    DelayTimer(u8),
+    /// Returns the fault the emulator is halted on, or None if it is running fine.
+    Fault(Option<EmulatorError>),
+    /// Returns the whole screen.
+    Framebuffer(PixelGrid),
     /// Returns the contents of register I (index register).
-    I(u16),
+    I(u32),
     /// Returns a bunch of bytes.
     MemorySlice(Vec<u8>),
     /// Returns the current program counter.
@@ -44,4 +167,14 @@ pub enum EmulatorResponse {
     SP(u8),
     /// Returns the current stack.
     Stack(Vec<u16>),
+    /// Returns why the machine is stopped, or None if it is running.
+    Stopped(Option<StopReason>),
+}
+
+/// What the emulator tells an attached debugger without being asked. These go over their own pipe, so they never get
+/// mixed up with the answers to commands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorEvent {
+    /// The machine stopped, and waits for debug commands until one of them lets it go.
+    Stopped(StopReason),
 }
@@ -0,0 +1,56 @@
+//! This module contains the errors the emulator can run into while executing a program.
+
+use std::error;
+use std::fmt;
+
+/// The different ways an instruction can go wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The instruction does not decode to any opcode we know about.
+    InvalidOpcode,
+    /// A CALL was made with every slot in the stack already full.
+    StackOverflow,
+    /// A RET was made with nothing on the stack.
+    StackUnderflow,
+    /// The instruction tried to read or write (or jump to) this address, which is outside of RAM.
+    AddressOutOfRange(usize),
+    /// The instruction referred to a general purpose register that does not exist.
+    BadRegister(u8),
+    /// The instruction asked for the hex sprite for this value, but there are only sprites for 0x0 through 0xF.
+    NoSuchHexSprite(u8),
+    /// A SYS instruction called a machine code routine that never came back with D4.
+    MachineCodeRunaway,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::InvalidOpcode => write!(f, "Not a valid instruction"),
+            ErrorKind::StackOverflow => write!(f, "Stack overflow"),
+            ErrorKind::StackUnderflow => write!(f, "Stack underflow"),
+            ErrorKind::AddressOutOfRange(addr) => write!(f, "Address 0x{:x} is out of range of the RAM", addr),
+            ErrorKind::BadRegister(v) => write!(f, "Register {} does not exist", v),
+            ErrorKind::NoSuchHexSprite(v) => write!(f, "There is no hex sprite for 0x{:x}. Valid sprites are 0x0 through 0xF", v),
+            ErrorKind::MachineCodeRunaway => write!(f, "The machine code routine never returned with D4"),
+        }
+    }
+}
+
+/// An error the emulator ran into, along with where it happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmulatorError {
+    /// What went wrong.
+    pub kind: ErrorKind,
+    /// The address of the instruction that went wrong.
+    pub pc: u16,
+    /// The raw instruction that went wrong. If the PC itself was out of range, this is 0.
+    pub instruction: u16,
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Problem with instruction 0x{:04x} at 0x{:04x}: {}", self.instruction, self.pc, self.kind)
+    }
+}
+
+impl error::Error for EmulatorError {}
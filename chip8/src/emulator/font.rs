@@ -0,0 +1,184 @@
+//! This module contains the fonts for the hex sprites that LDFVx points I at: sixteen 4x5 glyphs for "0" through
+//! "F", five bytes each, with the pixels in the high nibble of every byte.
+//!
+//! Every interpreter drew its own, so programs that print digits look a little different on each of them. The
+//! modern font is the one most emulators use; the others are copied from the interpreters they are named after.
+
+use std::fmt;
+use std::fs;
+use std::path;
+use std::str::FromStr;
+
+/// The number of bytes in each glyph.
+pub const BYTES_PER_GLYPH: usize = 5;
+/// The number of bytes in a whole font.
+pub const FONT_NBYTES: usize = 16 * BYTES_PER_GLYPH;
+
+const MODERN: [u8; FONT_NBYTES] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const VIP: [u8; FONT_NBYTES] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const ETI660: [u8; FONT_NBYTES] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const DREAM6800: [u8; FONT_NBYTES] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const FISH_N_CHIPS: [u8; FONT_NBYTES] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+    0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+    0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+    0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+    0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+    0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+    0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+    0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+    0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+    0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+    0x60, 0x80, 0x80, 0x80, 0x60, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// The fonts we have built in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FontSet {
+    /// The font most emulators use.
+    #[default]
+    Modern,
+    /// The COSMAC VIP's font.
+    Vip,
+    /// The ETI-660's font, which is only three pixels wide.
+    Eti660,
+    /// The DREAM 6800's font, which is also three pixels wide.
+    Dream6800,
+    /// The FISH 'N' CHIPS interpreter's rounder font.
+    FishNChips,
+}
+
+impl FontSet {
+    /// Every font, the modern one first.
+    pub const ALL: [FontSet; 5] = [FontSet::Modern, FontSet::Vip, FontSet::Eti660, FontSet::Dream6800, FontSet::FishNChips];
+
+    /// What this font is called on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            FontSet::Modern => "modern",
+            FontSet::Vip => "vip",
+            FontSet::Eti660 => "eti660",
+            FontSet::Dream6800 => "dream6800",
+            FontSet::FishNChips => "fishnchips",
+        }
+    }
+
+    /// The glyphs for "0" through "F", one after another.
+    pub fn glyphs(self) -> &'static [u8; FONT_NBYTES] {
+        match self {
+            FontSet::Modern => &MODERN,
+            FontSet::Vip => &VIP,
+            FontSet::Eti660 => &ETI660,
+            FontSet::Dream6800 => &DREAM6800,
+            FontSet::FishNChips => &FISH_N_CHIPS,
+        }
+    }
+}
+
+impl fmt::Display for FontSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for FontSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match FontSet::ALL.iter().find(|font| font.name() == s) {
+            Some(font) => Ok(*font),
+            None => {
+                let names: Vec<&str> = FontSet::ALL.iter().map(|font| font.name()).collect();
+                Err(format!("{} is not a font. Try one of {}.", s, names.join(", ")))
+            },
+        }
+    }
+}
+
+/// Reads a font out of the file at `path`, which must hold exactly the sixteen glyphs, "0" first.
+pub fn load(path: &path::Path) -> Result<Vec<u8>, String> {
+    match fs::read(path) {
+        Ok(ref bytes) if bytes.len() != FONT_NBYTES => {
+            Err(format!("The font at {} is {} bytes, but a font is {} bytes.", path.display(), bytes.len(), FONT_NBYTES))
+        },
+        Ok(bytes) => Ok(bytes),
+        Err(e) => Err(format!("Could not read font at {}: {:?}", path.display(), e)),
+    }
+}
@@ -1,14 +1,20 @@
 //! This module contains all the code dealing with the user's inputs on the keyboard.
+use super::backend::InputBackend;
+use std::cell::RefCell;
 use std::io::{self, Read};
+use std::rc::Rc;
 use std::sync::mpsc;
 
+/// The number of keys on the hex keypad.
+const NUM_KEYS: usize = 16;
+
 /// A single keystroke as read directly from a keyboard.
 pub type Key = String;
 
 /// Takes a byte and returns the String representation of it as follows (stolen from https://github.com/indragiek/Chip8, which
 /// in turn stole it from somewhere else...):
 ///
-/// ```
+/// ```text
 /// Keypad                   Keyboard
 /// +-+-+-+-+                +-+-+-+-+
 /// |1|2|3|C|                |1|2|3|4|
@@ -42,7 +48,8 @@ pub fn map(byte: u8) -> Result<Key, String> {
     }.to_string())
 }
 
-fn inverse_map(mut k: Key) -> Result<u8, String> {
+/// Takes a Key and returns the byte it stands for on the hex keypad. This is the inverse of `map`.
+pub fn inverse_map(mut k: Key) -> Result<u8, String> {
     k.make_ascii_uppercase();
     Ok(match k.as_str() {
         "X" => 0x00,
@@ -69,7 +76,14 @@ fn inverse_map(mut k: Key) -> Result<u8, String> {
 pub struct Keyboard {
     /// An optional pipe that a user of this struct can use to bypass stdin.
     /// If present, we will check this instead of stdin for characters.
+    ///
+    /// Every string sent over the pipe is the set of keys held down from then on, as keyboard characters
+    /// (see `map`): "as" holds down 0x7 and 0x8 until the next string comes along, and "" lets go of everything.
     debug_rx: Option<mpsc::Receiver<String>>,
+    /// The most recent string we got over the debug pipe, in lowercase.
+    held: String,
+    /// The most recent key to go down on the debug pipe that nobody has asked about yet.
+    last_pressed: Option<u8>,
 }
 
 impl Keyboard {
@@ -78,92 +92,183 @@ impl Keyboard {
     /// If `debug_rx` is present, we use it instead of stdin.
     pub fn new(debug_rx: Option<mpsc::Receiver<String>>) -> Self {
         Keyboard {
-            debug_rx: debug_rx,
+            debug_rx,
+            held: String::new(),
+            last_pressed: None,
+        }
+    }
+
+    /// Takes everything waiting on the debug pipe, so that we are up to date with the most recent string sent over it.
+    fn drain_debug_pipe(&mut self) {
+        let rx = match &self.debug_rx {
+            Some(rx) => rx,
+            None => return,
+        };
+
+        while let Ok(mut s) = rx.try_recv() {
+            s.make_ascii_lowercase();
+
+            // Any key that is in this string but wasn't in the last one just went down
+            for c in s.chars() {
+                if !self.held.contains(c) {
+                    if let Ok(byte) = inverse_map(c.to_string()) {
+                        self.last_pressed = Some(byte);
+                    }
+                }
+            }
+            self.held = s;
         }
     }
 
     /// Returns true if the given key is currently depressed on the keyboard.
-    pub fn check_keyboard_for_key(&self, k: Key) -> bool {
+    pub fn check_keyboard_for_key(&mut self, k: Key) -> bool {
         let mut lowercase_key = k;
         lowercase_key.make_ascii_lowercase();
 
-        let mut input = match &self.debug_rx {
+        let mut input = if self.debug_rx.is_some() {
             // We have a debug pipe, so use that instead of the normal keyboard input
-            Some(rx) => match rx.recv() {
-                Err(_) => panic!("Never received anything from the test interface over the debug rx pipe to the keyboard."),
-
-                // Match on the string we got over the debug interface
-                Ok(s) => s,
-            },
-
+            self.drain_debug_pipe();
+            self.held.clone()
+        } else {
             // We have no debug pipe, so use the normal keyboard input
-            None => {
-                let mut buffer = String::new();
-                let stdin = io::stdin();
-                let mut handle = stdin.lock();
-                match handle.read_to_string(&mut buffer) {
-                    Err(msg) => {
-                        println!("Got invald input over keyboard interface: {:?}", msg);
-                        return false;
-                    },
-
-                    // Match on the string we built from the keyboard input
-                    Ok(_nbytes) => match buffer.chars().last() {
-                        None => "".to_string(),
-                        Some(c) => c.to_string(),
-                    },
-                }
-            },
+            let mut buffer = String::new();
+            let stdin = io::stdin();
+            let mut handle = stdin.lock();
+            match handle.read_to_string(&mut buffer) {
+                Err(msg) => {
+                    println!("Got invald input over keyboard interface: {:?}", msg);
+                    return false;
+                },
+
+                // Match on the string we built from the keyboard input
+                Ok(_nbytes) => match buffer.chars().last() {
+                    None => "".to_string(),
+                    Some(c) => c.to_string(),
+                },
+            }
         };
 
         input.make_ascii_lowercase();
         input.contains(lowercase_key.as_str())
     }
 
-    /// Waits until the user presses a valid key, then returns the correct byte.
-    pub fn wait_for_keypress(&self) -> u8 {
-        if let Some(rx) = &self.debug_rx {
-            // We are in debug mode, so wait around until we get a valid key
-            loop {
-                match rx.recv() {
-                    Err(e) => panic!("Error on the debug interface keyboard: {:?}", e),
-                    Ok(mut s) => {
-                        s.make_ascii_lowercase();
-                        let c = s.chars().last().expect(format!("Got {}, which does not contain any characters.", s).as_str());
-
-                        if let Ok(byte) = inverse_map(c.to_string()) {
-                            return byte;
-                        } else {
-                            panic!("Got an invalid key on the debug interface: {}", c);
-                        }
-                    }
-                }
+    /// Returns the byte for the key the user pressed, or None if they have not pressed anything valid yet.
+    ///
+    /// On the debug pipe this never blocks. On stdin it blocks until there is something to read.
+    pub fn poll_for_keypress(&mut self) -> Option<u8> {
+        if self.debug_rx.is_some() {
+            // We are in debug mode. Only count the press if the key is still down, just like a Keypad.
+            self.drain_debug_pipe();
+            match self.last_pressed.take() {
+                Some(byte) if self.held.contains(map(byte).ok()?.to_ascii_lowercase().as_str()) => Some(byte),
+                _ => None,
             }
         } else {
             // We are in non-debug mode
 
             let stdin = io::stdin();
             let mut handle = stdin.lock();
+            let mut buffer = String::new();
+            match handle.read_to_string(&mut buffer) {
+                Err(msg) => {
+                    println!("Got invalid input over keyboard interface: {:?}", msg);
+                    None
+                },
 
-            // Sit around reading from stdin until we get something from the user
-            loop {
-                let mut buffer = String::new();
-                match handle.read_to_string(&mut buffer) {
-                    Err(msg) => {
-                        println!("Got invalid input over keyboard interface: {:?}", msg);
-                    },
-
-                    // Check if what we got from the user is a valid key input and return it if so.
-                    Ok(_nbytes) => match buffer.chars().last() {
-                        None => (),
-                        Some(c) => {
-                            if let Ok(byte) = inverse_map(c.to_string()) {
-                                return byte;
-                            }
-                        },
-                    },
-                }
+                // Check if what we got from the user is a valid key input and return it if so.
+                Ok(_nbytes) => match buffer.chars().last() {
+                    None => None,
+                    Some(c) => inverse_map(c.to_string()).ok(),
+                },
             }
         }
     }
 }
+
+impl InputBackend for Keyboard {
+    fn is_key_pressed(&mut self, key: u8) -> bool {
+        match map(key) {
+            Ok(k) => self.check_keyboard_for_key(k),
+            Err(_) => false,
+        }
+    }
+
+    fn poll_keypress(&mut self) -> Option<u8> {
+        self.poll_for_keypress()
+    }
+}
+
+/// The up/down state of each key on the hex keypad.
+#[derive(Debug, Default)]
+struct KeypadState {
+    /// Which keys are currently held down, indexed by key value.
+    pressed: [bool; NUM_KEYS],
+    /// Which keys are currently held down on the CHIP-8X's second keypad.
+    second_pressed: [bool; NUM_KEYS],
+    /// The most recent key to go down that nobody has asked about yet.
+    last_pressed: Option<u8>,
+}
+
+/// A hex keypad that can be shared between whatever is pushing the keys (a window, a test) and the emulator.
+///
+/// Cloning a Keypad gives another handle onto the same keys.
+#[derive(Debug, Clone, Default)]
+pub struct Keypad {
+    state: Rc<RefCell<KeypadState>>,
+}
+
+impl Keypad {
+    /// Constructs a new Keypad with all the keys up.
+    pub fn new() -> Self {
+        Keypad::default()
+    }
+
+    /// Push the given key (0x0 through 0xF) down. Keys outside the keypad are ignored.
+    pub fn press(&self, key: u8) {
+        if (key as usize) < NUM_KEYS {
+            let mut state = self.state.borrow_mut();
+            state.pressed[key as usize] = true;
+            state.last_pressed = Some(key);
+        }
+    }
+
+    /// Let the given key (0x0 through 0xF) back up. Keys outside the keypad are ignored.
+    pub fn release(&self, key: u8) {
+        if (key as usize) < NUM_KEYS {
+            self.state.borrow_mut().pressed[key as usize] = false;
+        }
+    }
+
+    /// Push the given key down on the CHIP-8X's second keypad. Keys outside the keypad are ignored.
+    pub fn press_second(&self, key: u8) {
+        if (key as usize) < NUM_KEYS {
+            self.state.borrow_mut().second_pressed[key as usize] = true;
+        }
+    }
+
+    /// Let the given key back up on the CHIP-8X's second keypad. Keys outside the keypad are ignored.
+    pub fn release_second(&self, key: u8) {
+        if (key as usize) < NUM_KEYS {
+            self.state.borrow_mut().second_pressed[key as usize] = false;
+        }
+    }
+}
+
+impl InputBackend for Keypad {
+    fn is_key_pressed(&mut self, key: u8) -> bool {
+        (key as usize) < NUM_KEYS && self.state.borrow().pressed[key as usize]
+    }
+
+    fn is_second_key_pressed(&mut self, key: u8) -> bool {
+        (key as usize) < NUM_KEYS && self.state.borrow().second_pressed[key as usize]
+    }
+
+    fn poll_keypress(&mut self) -> Option<u8> {
+        // Only count the press if the key is still down, so a key that was tapped long ago doesn't count.
+        let mut state = self.state.borrow_mut();
+        match state.last_pressed.take() {
+            Some(k) if state.pressed[k as usize] => Some(k),
+            _ => None,
+        }
+    }
+}
@@ -7,15 +7,29 @@ extern crate rand;
 use super::display;
 
 /* Public interface */
+pub mod backend;
+pub mod breakpoint;
+pub mod cdp1802;
 pub mod chip8;
+pub mod crashdump;
 pub mod debugiface;
+pub mod error;
+pub mod font;
+pub mod keyboard;
+pub mod movie;
+pub mod opcode;
+pub mod platform;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod savestate;
+pub mod scheduler;
+pub mod timing;
 
 /* Internal Mods */
-mod keyboard;
-mod opcode;
 mod register;
 
 /* Some datatypes that are common to this whole module */
 
 /// An address in RAM. RAM's address space can be described by 12 bits.
-type Address = u16;
+pub type Address = u16;
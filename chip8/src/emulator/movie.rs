@@ -0,0 +1,204 @@
+//! This module contains input movies: a starting state plus every change to the keypad, frame by frame, so
+//! that a play session can be replayed exactly.
+//!
+//! The keypad is only looked at once per frame, at the start of the frame. While recording, that is when we
+//! notice which keys have gone up or down; while playing back, that is when we push them up or down again.
+//! Either way the program sees the keys through a `Keyboard` fed over its mock input pipe, so it can't tell
+//! a recording from a playback. Since the starting state includes the random number generator, the whole run
+//! comes out the same, and we check that by comparing a digest of the final state against the one recorded.
+
+use super::backend::InputBackend;
+use super::keyboard::{self, Keyboard};
+use super::savestate::{Reader, SaveState, Writer};
+use std::fs;
+use std::path;
+use std::sync::mpsc;
+
+/// The first bytes of every movie file.
+const MAGIC: &[u8] = b"MYCHIP8M";
+/// The version of the format that `to_bytes` writes.
+pub const MOVIE_VERSION: u16 = 1;
+/// The number of keys on the hex keypad.
+const NUM_KEYS: u8 = 16;
+
+/// A key going up or down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The frame this happened at the start of, counting the first frame boundary after the movie starts as 0.
+    pub frame: u64,
+    /// The key (0x0 through 0xF).
+    pub key: u8,
+    /// True if the key went down, false if it came up.
+    pub pressed: bool,
+}
+
+/// A recorded play session.
+#[derive(Debug, Clone)]
+pub struct Movie {
+    /// The whole machine when recording started, including the program and the random number generator.
+    pub start: SaveState,
+    /// Every change to the keypad, in order.
+    pub events: Vec<KeyEvent>,
+    /// The number of instructions executed between the start and the end of the movie.
+    pub ninstructions: usize,
+    /// The `digest` of the whole machine at the end of the movie.
+    pub final_digest: u64,
+}
+
+impl Movie {
+    /// Writes this movie to the file at `path`, replacing whatever was there.
+    pub fn save(&self, path: &path::Path) -> Result<(), String> {
+        match fs::write(path, self.to_bytes()) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(format!("Could not write movie to {}: {:?}", path.display(), e)),
+        }
+    }
+
+    /// Reads a movie back out of the file at `path`.
+    pub fn load(path: &path::Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(bytes) => Movie::from_bytes(&bytes),
+            Err(e) => Err(format!("Could not read movie at {}: {:?}", path.display(), e)),
+        }
+    }
+
+    /// Encodes this movie in the current version of the format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer { bytes: MAGIC.to_vec() };
+        w.u16(MOVIE_VERSION);
+
+        w.u8s(&self.start.to_bytes());
+        w.u64(self.ninstructions as u64);
+        w.u64(self.final_digest);
+        w.u32(self.events.len() as u32);
+        for event in self.events.iter() {
+            w.u64(event.frame);
+            w.u8(event.key);
+            w.bool(event.pressed);
+        }
+
+        w.bytes
+    }
+
+    /// Decodes a movie.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err("This is not a movie.".to_string());
+        }
+        let version = r.u16()?;
+        if version == 0 || version > MOVIE_VERSION {
+            return Err(format!("This movie is version {}, but we only understand versions 1 through {}.", version, MOVIE_VERSION));
+        }
+
+        let start = SaveState::from_bytes(&r.u8s()?)?;
+        let ninstructions = r.u64()? as usize;
+        let final_digest = r.u64()?;
+        let mut events = Vec::<KeyEvent>::new();
+        for _ in 0..r.u32()? {
+            let frame = r.u64()?;
+            let key = r.u8()?;
+            if key >= NUM_KEYS {
+                return Err(format!("0x{:x} is not a key on the keypad.", key));
+            }
+            events.push(KeyEvent { frame, key, pressed: r.bool()? });
+        }
+
+        if r.pos != bytes.len() {
+            return Err(format!("There are {} bytes left over at the end of the movie.", bytes.len() - r.pos));
+        }
+
+        Ok(Movie { start, events, ninstructions, final_digest })
+    }
+}
+
+/// The keypad the program sees while a movie is recording or playing.
+///
+/// It is a `Keyboard` on the mock input pipe, and we are the other end of the pipe, sending it whatever keys are
+/// held at the start of each frame.
+pub struct MovieInput {
+    /// What the program actually reads the keys from.
+    keyboard: Keyboard,
+    /// Our end of the keyboard's mock input pipe.
+    tx: mpsc::Sender<String>,
+    /// Which keys we last told the keyboard were held down.
+    held: [bool; NUM_KEYS as usize],
+    /// The number of frames that have started so far.
+    frame: u64,
+    /// Everything that has happened (when recording) or is going to happen (when playing), in order.
+    events: Vec<KeyEvent>,
+    /// When playing, the index of the next event to play.
+    next: usize,
+}
+
+impl MovieInput {
+    /// Creates a keypad with no keys held and nothing recorded yet.
+    pub fn recorder() -> Self {
+        MovieInput::player(Vec::new())
+    }
+
+    /// Creates a keypad that will play back `events`.
+    pub fn player(events: Vec<KeyEvent>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        MovieInput {
+            keyboard: Keyboard::new(Some(rx)),
+            tx,
+            held: [false; NUM_KEYS as usize],
+            frame: 0,
+            events,
+            next: 0,
+        }
+    }
+
+    /// Starts a frame while recording: looks at which keys are held down on `live` and writes down any changes.
+    pub fn record_frame(&mut self, live: &mut dyn InputBackend) {
+        let nevents = self.events.len();
+        for key in 0..NUM_KEYS {
+            let pressed = live.is_key_pressed(key);
+            if pressed != self.held[key as usize] {
+                self.held[key as usize] = pressed;
+                self.events.push(KeyEvent { frame: self.frame, key, pressed });
+            }
+        }
+        let changed = self.events.len() != nevents;
+        self.start_frame(changed);
+    }
+
+    /// Starts a frame while playing: pushes keys up and down as the movie says.
+    pub fn play_frame(&mut self) {
+        let first = self.next;
+        while let Some(event) = self.events.get(self.next).filter(|e| e.frame <= self.frame) {
+            self.held[event.key as usize] = event.pressed;
+            self.next += 1;
+        }
+        let changed = self.next != first;
+        self.start_frame(changed);
+    }
+
+    /// Everything that has been recorded so far.
+    pub fn into_events(self) -> Vec<KeyEvent> {
+        self.events
+    }
+
+    /// Tells the keyboard which keys are held for this frame if that has `changed`, and moves on to the next frame.
+    fn start_frame(&mut self, changed: bool) {
+        if changed {
+            let keys: String = (0..NUM_KEYS)
+                .filter(|k| self.held[*k as usize])
+                .filter_map(|k| keyboard::map(k).ok())
+                .collect();
+            self.tx.send(keys).expect("We own both ends of the movie's input pipe.");
+        }
+        self.frame += 1;
+    }
+}
+
+impl InputBackend for MovieInput {
+    fn is_key_pressed(&mut self, key: u8) -> bool {
+        self.keyboard.is_key_pressed(key)
+    }
+
+    fn poll_keypress(&mut self) -> Option<u8> {
+        self.keyboard.poll_keypress()
+    }
+}
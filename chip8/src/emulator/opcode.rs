@@ -1,16 +1,59 @@
+use super::error::ErrorKind;
+use super::platform::Platform;
 use std::fmt;
 
 #[derive(Debug, Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
 /// Opcodes
 pub enum Opcode {
     /// 0x00A0: Break and wait for emulator to start program again. This is not standard, and is used for a test interface.
     BRK,
     /// 0x0nnn: Jump to a machine code routine at nnn.
     SYS(u16),
-    /// 0x00E0: Clear the display.
+    /// 0x0010: Turn MEGA-CHIP mode off, going back to the SCHIP's screen. (MEGA-CHIP)
+    MEGAOFF,
+    /// 0x0011: Turn MEGA-CHIP mode on, with its 256x192 true color screen. (MEGA-CHIP)
+    MEGAON,
+    /// 0x01nn nnnn: Set I to the 24 bit address nnnnnn, the top byte of which is in this instruction and the rest
+    /// in the next two bytes. This instruction is four bytes long. (MEGA-CHIP)
+    LDHI(u8),
+    /// 0x02nn: Load palette entries 1 through nn with the 4 byte ARGB colors starting at I. (MEGA-CHIP)
+    LDPAL(u8),
+    /// 0x03nn: Set the width of sprites in MEGA-CHIP mode to nn pixels (0 means 256). (MEGA-CHIP)
+    SPRW(u8),
+    /// 0x04nn: Set the height of sprites in MEGA-CHIP mode to nn pixels (0 means 256). (MEGA-CHIP)
+    SPRH(u8),
+    /// 0x05nn: Set how opaque the screen is, from 0 (invisible) to 255. (MEGA-CHIP)
+    ALPHA(u8),
+    /// 0x060n: Play the digitized sound at I, over and over if n is 0, or once otherwise. (MEGA-CHIP)
+    /// The sound starts with its sample rate in 2 bytes, then its length in 3, then a byte we ignore.
+    DIGISND(u8),
+    /// 0x0700: Stop the digitized sound. (MEGA-CHIP)
+    STOPSND,
+    /// 0x080n: Pick how sprites are blended onto the screen: normal, 25%, 50%, or 75% opaque, added, or multiplied. (MEGA-CHIP)
+    BMODE(u8),
+    /// 0x09nn: Make drawing over pixels in palette entry nn count as a collision. (MEGA-CHIP)
+    CCOL(u8),
+    /// 0x02A0: Step the background color to the next one of blue, black, green, and red. (CHIP-8X)
+    BGCOL,
+    /// 0x00Cn: Scroll the display down n pixels. (SCHIP)
+    SCD(u8),
+    /// 0x00Dn: Scroll the display up n pixels. (XO-CHIP, and 0x00Bn on MEGA-CHIP)
+    SCU(u8),
+    /// 0x00E0: Clear the display. On HIRES Chip-8, 0x0230 does the same.
     CLS,
     /// 0x00EE: Return from subroutine.
     RET,
+    /// 0x00FB: Scroll the display right 4 pixels. (SCHIP)
+    SCR,
+    /// 0x00FC: Scroll the display left 4 pixels. (SCHIP)
+    SCL,
+    /// 0x00FD: Exit the interpreter. (SCHIP)
+    EXIT,
+    /// 0x00FE: Switch to the 64x32 low resolution mode. (SCHIP)
+    LOW,
+    /// 0x00FF: Switch to the 128x64 high resolution mode. (SCHIP)
+    HIGH,
     /// 0x1nnn: Jump to location nnn.
     JP(u16),
     /// 0x2nnn: Call subroutine at nnn.
@@ -21,6 +64,12 @@ pub enum Opcode {
     SNEVxByte(u8, u8),
     /// 0x5xy0: Skip next instruction if Vx == Vy; compare register Vx to register Vy. If equal, increment PC by 2.
     SEVxVy(u8, u8),
+    /// 0x5xy2: Store registers Vx through Vy in memory starting at location I. I is left alone. (XO-CHIP)
+    /// If x is bigger than y, the registers are stored in descending order.
+    SAVEVxVy(u8, u8),
+    /// 0x5xy3: Load registers Vx through Vy from memory starting at location I. I is left alone. (XO-CHIP)
+    /// If x is bigger than y, the registers are loaded in descending order.
+    LOADVxVy(u8, u8),
     /// 0x6xkk: Put kk into register Vx.
     LDVxByte(u8, u8),
     /// 0x7xkk: Add the value kk to the value of regsiter Vx, then store the result in Vx.
@@ -37,26 +86,42 @@ pub enum Opcode {
     ADDVxVy(u8, u8),
     /// 0x8xy5: Set Vx to Vx - Vy. If Vx > Vy, VF is set to 1 otherwise 0.
     SUBVxVy(u8, u8),
-    /// 0x8xy6: Shift Vx right 1 (ignore Vy). Store result in Vx. If 0x01 & Vx is 1 before shift, VF is set to 1, oetherwise 0.
-    SHRVx(u8),
+    /// 0x8xy6: Shift Vx right 1 (or Vy, with the shift quirk). Store result in Vx. If 0x01 & Vx is 1 before shift, VF is set to 1, oetherwise 0.
+    SHRVx(u8, u8),
     /// 0x8xy7: Set Vx to Vy - Vx. If Vy > Vx, VF is set to 1 otherwise 0.
     SUBNVxVy(u8, u8),
-    /// 0x8xyE: Shift Vx left 1 (ignore Vy). Store result in Vx. If 0x80 & Vx is 1 before shift, VF is set to 1, oetherwise 0.
-    SHLVx(u8),
+    /// 0x8xyE: Shift Vx left 1 (or Vy, with the shift quirk). Store result in Vx. If 0x80 & Vx is 1 before shift, VF is set to 1, oetherwise 0.
+    SHLVx(u8, u8),
     /// 0x9xy0: Skip next instruction if Vx != Vy. If Vx does not equal Vy, increment PC by 2.
     SNEVxVy(u8, u8),
     /// 0xAnnn: Set I to nnn.
     LDIAddr(u16),
     /// 0xBnnn: Jump to location nnn + V0. The PC is set to nnn plus the value of V0.
+    /// With the jump quirk, this is Bxnn instead, which jumps to xnn plus the value of Vx.
     JPV0Addr(u16),
+    /// 0xBxyn: Set the foreground color of a zone of the screen to the low 3 bits of V(x+1). (CHIP-8X)
+    /// If n is 0, the zone is made of 8x4 blocks: the low nibbles of Vx and Vy are the first block's column
+    /// and row, and their high nibbles are how many more blocks to color across and down.
+    /// Otherwise, the zone is n rows of 8 pixel wide blocks starting at row Vy.
+    COLVxVyN(u8, u8, u8),
     /// 0xCxkk: Generate a random number between 0 and 255, AND it with the value kk. Store the result in Vx.
     RNDVxByte(u8, u8),
     /// 0xDxyn: Display n-byte sprite starting at memory location I at coordinate (Vx, Vy). Set VF equal to collision.
+    /// On SCHIP, n of 0 draws a 16x16 sprite (8x16 in low resolution mode) instead.
+    /// In MEGA-CHIP mode, n is ignored, and the sprite is one palette index per pixel, as big as SPRW and SPRH say.
     DRWVxVyNibble(u8, u8, u8),
     /// 0xEx9E: Skip the next instruction if the key with the value of Vx is pressed.
     SKPVx(u8),
     /// 0xExA1: Skip next instruction if key with the value of Vx is NOT pressed.
     SKNPVx(u8),
+    /// 0xExF2: Skip the next instruction if the key with the value of Vx is pressed on the second keypad. (CHIP-8X)
+    SKP2Vx(u8),
+    /// 0xExF5: Skip the next instruction if the key with the value of Vx is NOT pressed on the second keypad. (CHIP-8X)
+    SKNP2Vx(u8),
+    /// 0xF000 nnnn: Set I to the 16 bit address nnnn in the next two bytes. This instruction is four bytes long. (XO-CHIP)
+    LDILong,
+    /// 0xFn01: Select the planes in the mask n for drawing, scrolling, and clearing. (XO-CHIP)
+    PLANE(u8),
     /// 0xFx07: The value of the delay timer is stored in Vx.
     LDVxDT(u8),
     /// 0xFx0A: Stop execution until a key is pressed. Then store the value of that key in Vx.
@@ -70,21 +135,40 @@ pub enum Opcode {
     /// 0xFx29: Set I equal to the location of sprite for digit Vx.
     /// The value of I is set to the location for the hexadecimal sprite corresponding to the value of Vx.
     LDFVx(u8),
+    /// 0xFx30: Set I equal to the location of the big 8x10 sprite for digit Vx. (SCHIP)
+    LDHFVx(u8),
     /// 0xFx33: Store the binary-coded-decimal representation of Vx in memory locations I, I+1, and I+2.
     /// The hundreds digit is I, tens at I+1, then ones at I+2.
     LDBVx(u8),
     /// 0xFx55: Store registers V0 through Vx in memory starting at location I.
     /// Note that several sources suggest that I should be incremented after this operation, but others disagree.
-    /// I have chosen not to increment I.
+    /// By default, I is not incremented. The load/store quirk increments it.
     LDIVx(u8),
     /// 0xFx65: Loads registers V0 through Vx with memory starting at location I.
     /// Note that several sources suggest that I should be incremented after this operation, but others disagree.
-    /// I have chosen not to increment I.
+    /// By default, I is not incremented. The load/store quirk increments it.
     LDVxI(u8),
+    /// 0xFx75: Store registers V0 through Vx in the RPL user flags. (SCHIP)
+    LDRVx(u8),
+    /// 0xFx85: Load registers V0 through Vx from the RPL user flags. (SCHIP)
+    LDVxR(u8),
+    /// 0xFxF8: Send Vx out of the output port. (CHIP-8X)
+    OUTVx(u8),
+    /// 0xFxFB: Stop execution until a byte comes in on the input port. Then store it in Vx. (CHIP-8X)
+    INVx(u8),
 }
 
+/// Every mnemonic that `Opcode::mnemonic` can give, in the order of the instructions they are for.
+pub const MNEMONICS: [&str; 46] = [
+    "BRK", "SYS", "MEGAOFF", "MEGAON", "LDHI", "LDPAL", "SPRW", "SPRH", "ALPHA", "DIGISND", "STOPSND", "BMODE", "CCOL",
+    "BGCOL", "SCD", "SCU", "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE", "SAVE", "LOAD",
+    "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "COL", "RND", "DRW", "SKP", "SKNP", "PLANE", "OUT",
+    "IN",
+];
+
 impl Opcode {
-    pub fn new(instruction: u16) -> Result<Self, String> {
+    /// Decodes the given raw instruction the way plain Chip-8 would. Use `for_platform` for everything else.
+    pub fn new(instruction: u16) -> Result<Self, ErrorKind> {
         match instruction & 0xF000 {
             0x0000 => {
                 if instruction == 0x00A0 {
@@ -138,10 +222,10 @@ impl Opcode {
                     0x0003 => Ok(Opcode::XORVxVy(x, y)),
                     0x0004 => Ok(Opcode::ADDVxVy(x, y)),
                     0x0005 => Ok(Opcode::SUBVxVy(x, y)),
-                    0x0006 => Ok(Opcode::SHRVx(x)),
+                    0x0006 => Ok(Opcode::SHRVx(x, y)),
                     0x0007 => Ok(Opcode::SUBNVxVy(x, y)),
-                    0x000E => Ok(Opcode::SHLVx(x)),
-                    _ => Err("0x8 is a valid opcode but the submask is not.".to_string()),
+                    0x000E => Ok(Opcode::SHLVx(x, y)),
+                    _ => Err(ErrorKind::InvalidOpcode),
                 }
             },
             0x9000 => {
@@ -171,7 +255,7 @@ impl Opcode {
                 match instruction & 0x00FF {
                     0x009E => Ok(Opcode::SKPVx(x)),
                     0x00A1 => Ok(Opcode::SKNPVx(x)),
-                    _ => Err("0xE is a valid opcode, but the submask is not.".to_string()),
+                    _ => Err(ErrorKind::InvalidOpcode),
                 }
             },
             0xF000 => {
@@ -186,12 +270,167 @@ impl Opcode {
                     0x0033 => Ok(Opcode::LDBVx(x)),
                     0x0055 => Ok(Opcode::LDIVx(x)),
                     0x0065 => Ok(Opcode::LDVxI(x)),
-                    _ => Err("0xF is a valid opcode, but the submask is not.".to_string()),
+                    _ => Err(ErrorKind::InvalidOpcode),
                 }
             },
             _ => panic!("It should be impossible to even get here..."),
         }
     }
+
+    /// Decodes the given raw instruction the way `platform` would. Each platform adds instructions of its own
+    /// to plain Chip-8, mostly out of what Chip-8 would have run as SYS or seen as garbage.
+    pub fn for_platform(instruction: u16, platform: Platform) -> Result<Self, ErrorKind> {
+        let extra = match platform {
+            Platform::Chip8 => None,
+            Platform::HiresChip8 => Opcode::new_hires(instruction),
+            Platform::Chip8X => Opcode::new_chip8x(instruction),
+            Platform::SuperChip => Opcode::new_schip(instruction),
+            Platform::MegaChip => Opcode::new_megachip(instruction).or_else(|| Opcode::new_schip(instruction)),
+            Platform::XoChip => Opcode::new_xochip(instruction).or_else(|| Opcode::new_schip(instruction)),
+        };
+        match extra {
+            Some(op) => Ok(op),
+            None => Opcode::new(instruction),
+        }
+    }
+
+    /// Decodes the instruction the HIRES hack of the COSMAC VIP interpreter adds, if this is it.
+    fn new_hires(instruction: u16) -> Option<Self> {
+        match instruction {
+            0x0230 => Some(Opcode::CLS),
+            _ => None,
+        }
+    }
+
+    /// Decodes one of the CHIP-8X instructions, if this is one.
+    fn new_chip8x(instruction: u16) -> Option<Self> {
+        let x: u8 = ((instruction & 0x0F00) >> 8) as u8;
+        let y: u8 = ((instruction & 0x00F0) >> 4) as u8;
+        let n: u8 = (instruction & 0x000F) as u8;
+        match (instruction & 0xF000, instruction & 0x00FF) {
+            _ if instruction == 0x02A0 => Some(Opcode::BGCOL),
+            (0xB000, _) => Some(Opcode::COLVxVyN(x, y, n)),
+            (0xE000, 0x00F2) => Some(Opcode::SKP2Vx(x)),
+            (0xE000, 0x00F5) => Some(Opcode::SKNP2Vx(x)),
+            (0xF000, 0x00F8) => Some(Opcode::OUTVx(x)),
+            (0xF000, 0x00FB) => Some(Opcode::INVx(x)),
+            _ => None,
+        }
+    }
+
+    /// Decodes one of the SUPER-CHIP 1.1 instructions, if this is one.
+    fn new_schip(instruction: u16) -> Option<Self> {
+        let x: u8 = ((instruction & 0x0F00) >> 8) as u8;
+        match instruction {
+            0x00C0..=0x00CF => Some(Opcode::SCD((instruction & 0x000F) as u8)),
+            0x00FB => Some(Opcode::SCR),
+            0x00FC => Some(Opcode::SCL),
+            0x00FD => Some(Opcode::EXIT),
+            0x00FE => Some(Opcode::LOW),
+            0x00FF => Some(Opcode::HIGH),
+            _ => match instruction & 0xF0FF {
+                0xF030 => Some(Opcode::LDHFVx(x)),
+                0xF075 => Some(Opcode::LDRVx(x)),
+                0xF085 => Some(Opcode::LDVxR(x)),
+                _ => None,
+            },
+        }
+    }
+
+    /// Decodes one of the MEGA-CHIP instructions, if this is one. The SCHIP's are decoded separately.
+    fn new_megachip(instruction: u16) -> Option<Self> {
+        if instruction & 0xF000 != 0x0000 {
+            return None;
+        }
+        let nn: u8 = (instruction & 0x00FF) as u8;
+        match instruction & 0x0F00 {
+            0x0000 if instruction == 0x0010 => Some(Opcode::MEGAOFF),
+            0x0000 if instruction == 0x0011 => Some(Opcode::MEGAON),
+            0x0000 if instruction & 0x00F0 == 0x00B0 => Some(Opcode::SCU(nn & 0x0F)),
+            0x0100 => Some(Opcode::LDHI(nn)),
+            0x0200 => Some(Opcode::LDPAL(nn)),
+            0x0300 => Some(Opcode::SPRW(nn)),
+            0x0400 => Some(Opcode::SPRH(nn)),
+            0x0500 => Some(Opcode::ALPHA(nn)),
+            0x0600 if nn & 0xF0 == 0 => Some(Opcode::DIGISND(nn)),
+            0x0700 if nn == 0 => Some(Opcode::STOPSND),
+            0x0800 if nn & 0xF0 == 0 => Some(Opcode::BMODE(nn)),
+            0x0900 => Some(Opcode::CCOL(nn)),
+            _ => None,
+        }
+    }
+
+    /// Decodes one of the XO-CHIP instructions, if this is one. The SCHIP's are decoded separately.
+    fn new_xochip(instruction: u16) -> Option<Self> {
+        let x: u8 = ((instruction & 0x0F00) >> 8) as u8;
+        let y: u8 = ((instruction & 0x00F0) >> 4) as u8;
+        match (instruction & 0xF000, instruction & 0x00FF) {
+            _ if instruction & 0xFFF0 == 0x00D0 => Some(Opcode::SCU((instruction & 0x000F) as u8)),
+            _ if instruction == 0xF000 => Some(Opcode::LDILong),
+            (0x5000, _) if instruction & 0x000F == 0x0002 => Some(Opcode::SAVEVxVy(x, y)),
+            (0x5000, _) if instruction & 0x000F == 0x0003 => Some(Opcode::LOADVxVy(x, y)),
+            (0xF000, 0x0001) => Some(Opcode::PLANE(x)),
+            _ => None,
+        }
+    }
+}
+
+impl Opcode {
+    /// The assembly mnemonic of this instruction, without its operands. All the loads are LD, both kinds of JP are
+    /// JP, and so on.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::BRK => "BRK",
+            Opcode::SYS(_) => "SYS",
+            Opcode::MEGAOFF => "MEGAOFF",
+            Opcode::MEGAON => "MEGAON",
+            Opcode::LDHI(_) => "LDHI",
+            Opcode::LDPAL(_) => "LDPAL",
+            Opcode::SPRW(_) => "SPRW",
+            Opcode::SPRH(_) => "SPRH",
+            Opcode::ALPHA(_) => "ALPHA",
+            Opcode::DIGISND(_) => "DIGISND",
+            Opcode::STOPSND => "STOPSND",
+            Opcode::BMODE(_) => "BMODE",
+            Opcode::CCOL(_) => "CCOL",
+            Opcode::BGCOL => "BGCOL",
+            Opcode::SCD(_) => "SCD",
+            Opcode::SCU(_) => "SCU",
+            Opcode::CLS => "CLS",
+            Opcode::RET => "RET",
+            Opcode::SCR => "SCR",
+            Opcode::SCL => "SCL",
+            Opcode::EXIT => "EXIT",
+            Opcode::LOW => "LOW",
+            Opcode::HIGH => "HIGH",
+            Opcode::JP(_) | Opcode::JPV0Addr(_) => "JP",
+            Opcode::CALL(_) => "CALL",
+            Opcode::SEVxByte(..) | Opcode::SEVxVy(..) => "SE",
+            Opcode::SNEVxByte(..) | Opcode::SNEVxVy(..) => "SNE",
+            Opcode::SAVEVxVy(..) => "SAVE",
+            Opcode::LOADVxVy(..) => "LOAD",
+            Opcode::LDVxByte(..) | Opcode::LDVxVy(..) | Opcode::LDIAddr(_) | Opcode::LDILong | Opcode::LDVxDT(_)
+                | Opcode::LDVxK(_) | Opcode::LDDTVx(_) | Opcode::LDSTVx(_) | Opcode::LDFVx(_) | Opcode::LDHFVx(_)
+                | Opcode::LDBVx(_) | Opcode::LDIVx(_) | Opcode::LDVxI(_) | Opcode::LDRVx(_) | Opcode::LDVxR(_) => "LD",
+            Opcode::ADDVxByte(..) | Opcode::ADDVxVy(..) | Opcode::ADDIVx(_) => "ADD",
+            Opcode::ORVxVy(..) => "OR",
+            Opcode::ANDVxVy(..) => "AND",
+            Opcode::XORVxVy(..) => "XOR",
+            Opcode::SUBVxVy(..) => "SUB",
+            Opcode::SHRVx(..) => "SHR",
+            Opcode::SUBNVxVy(..) => "SUBN",
+            Opcode::SHLVx(..) => "SHL",
+            Opcode::COLVxVyN(..) => "COL",
+            Opcode::RNDVxByte(..) => "RND",
+            Opcode::DRWVxVyNibble(..) => "DRW",
+            // The CHIP-8X's second keypad is checked with the same mnemonics as the first
+            Opcode::SKPVx(_) | Opcode::SKP2Vx(_) => "SKP",
+            Opcode::SKNPVx(_) | Opcode::SKNP2Vx(_) => "SKNP",
+            Opcode::PLANE(_) => "PLANE",
+            Opcode::OUTVx(_) => "OUT",
+            Opcode::INVx(_) => "IN",
+        }
+    }
 }
 
 impl fmt::Display for Opcode {
@@ -201,13 +440,34 @@ impl fmt::Display for Opcode {
         match self {
             Opcode::BRK => write!(f, "BRK"),
             Opcode::SYS(addr) => write!(f, "SYS(0x{:04x})", addr),
+            Opcode::MEGAOFF => write!(f, "MEGAOFF"),
+            Opcode::MEGAON => write!(f, "MEGAON"),
+            Opcode::LDHI(nn) => write!(f, "LDHI(0x{:02x})", nn),
+            Opcode::LDPAL(nn) => write!(f, "LDPAL({})", nn),
+            Opcode::SPRW(nn) => write!(f, "SPRW({})", nn),
+            Opcode::SPRH(nn) => write!(f, "SPRH({})", nn),
+            Opcode::ALPHA(nn) => write!(f, "ALPHA({})", nn),
+            Opcode::DIGISND(n) => write!(f, "DIGISND({})", n),
+            Opcode::STOPSND => write!(f, "STOPSND"),
+            Opcode::BMODE(n) => write!(f, "BMODE({})", n),
+            Opcode::CCOL(nn) => write!(f, "CCOL({})", nn),
+            Opcode::BGCOL => write!(f, "BGCOL"),
+            Opcode::SCD(n) => write!(f, "SCD({})", n),
+            Opcode::SCU(n) => write!(f, "SCU({})", n),
             Opcode::CLS => write!(f, "CLS"),
             Opcode::RET => write!(f, "RET"),
+            Opcode::SCR => write!(f, "SCR"),
+            Opcode::SCL => write!(f, "SCL"),
+            Opcode::EXIT => write!(f, "EXIT"),
+            Opcode::LOW => write!(f, "LOW"),
+            Opcode::HIGH => write!(f, "HIGH"),
             Opcode::JP(addr) => write!(f, "JP(0x{:04x})", addr),
             Opcode::CALL(addr) => write!(f, "CALL(0x{:04x})", addr),
             Opcode::SEVxByte(x, kk) => write!(f, "SEVxByte(V{}, {})", x, kk),
             Opcode::SNEVxByte(x, kk) => write!(f, "SNEVxByte(V{}, {})", x, kk),
             Opcode::SEVxVy(x, y) => write!(f, "SEVxVy(V{}, V{})", x, y),
+            Opcode::SAVEVxVy(x, y) => write!(f, "SAVEVxVy(V{}, V{})", x, y),
+            Opcode::LOADVxVy(x, y) => write!(f, "LOADVxVy(V{}, V{})", x, y),
             Opcode::LDVxByte(x, kk) => write!(f, "LDVxByte(V{}, {})", x, kk),
             Opcode::ADDVxByte(x, kk) => write!(f, "ADDVxByte(V{}, {})", x, kk),
             Opcode::LDVxVy(x, y) => write!(f, "LDVxVy(V{}, V{})", x, y),
@@ -216,25 +476,35 @@ impl fmt::Display for Opcode {
             Opcode::XORVxVy(x, y) => write!(f, "XORVxVy(V{}, V{})", x, y),
             Opcode::ADDVxVy(x, y) => write!(f, "ADDVxVy(V{}, V{})", x, y),
             Opcode::SUBVxVy(x, y) => write!(f, "SUBVxVy(V{}, V{})", x, y),
-            Opcode::SHRVx(x) => write!(f, "SHRVx(V{})", x),
+            Opcode::SHRVx(x, y) => write!(f, "SHRVx(V{}, V{})", x, y),
             Opcode::SUBNVxVy(x, y) => write!(f, "SUBNVxVy(V{}, V{})", x, y),
-            Opcode::SHLVx(x) => write!(f, "SHLVx({})", x),
+            Opcode::SHLVx(x, y) => write!(f, "SHLVx(V{}, V{})", x, y),
             Opcode::SNEVxVy(x, y) => write!(f, "SNEVxVy(V{}, V{})", x, y),
             Opcode::LDIAddr(addr) => write!(f, "LDIAddr(0x{:04x})", addr),
             Opcode::JPV0Addr(addr) => write!(f, "JPV0Addr(0x{:04x})", addr),
+            Opcode::COLVxVyN(x, y, n) => write!(f, "COLVxVyN(V{}, V{}, {})", x, y, n),
             Opcode::RNDVxByte(x, kk) => write!(f, "RNDVxByte(V{}, 0x{:02x})", x, kk),
             Opcode::DRWVxVyNibble(x, y, n) => write!(f, "DRWVxVyNibble(V{}, V{}, {})", x, y, n),
             Opcode::SKPVx(x) => write!(f, "SKPVx(V{})", x),
             Opcode::SKNPVx(x) => write!(f, "SKNPVx(V{})", x),
+            Opcode::SKP2Vx(x) => write!(f, "SKP2Vx(V{})", x),
+            Opcode::SKNP2Vx(x) => write!(f, "SKNP2Vx(V{})", x),
+            Opcode::LDILong => write!(f, "LDILong"),
+            Opcode::PLANE(n) => write!(f, "PLANE({})", n),
             Opcode::LDVxDT(x) => write!(f, "LDVxDT(V{})", x),
             Opcode::LDVxK(x) => write!(f, "LDVxK(V{})", x),
             Opcode::LDDTVx(x) => write!(f, "LDDTVx(V{})", x),
             Opcode::LDSTVx(x) => write!(f, "LDSTVx(V{})", x),
             Opcode::ADDIVx(x) => write!(f, "ADDIVx(V{})", x),
             Opcode::LDFVx(x) => write!(f, "LDFVx(V{})", x),
+            Opcode::LDHFVx(x) => write!(f, "LDHFVx(V{})", x),
             Opcode::LDBVx(x) => write!(f, "LDBVx(V{})", x),
             Opcode::LDIVx(x) => write!(f, "LDIVx(V{})", x),
             Opcode::LDVxI(x) => write!(f, "LDVxI(V{})", x),
+            Opcode::LDRVx(x) => write!(f, "LDRVx(V{})", x),
+            Opcode::LDVxR(x) => write!(f, "LDVxR(V{})", x),
+            Opcode::OUTVx(x) => write!(f, "OUTVx(V{})", x),
+            Opcode::INVx(x) => write!(f, "INVx(V{})", x),
         }
     }
 }
@@ -0,0 +1,133 @@
+//! This module contains the platforms we can emulate: the different machines and interpreters that Chip-8
+//! programs were written for, each of which grew the instruction set in its own way.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Which machine we are pretending to be.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// The original Chip-8 with 4 KiB of RAM.
+    #[default]
+    Chip8,
+    /// The two-page HIRES hack of the COSMAC VIP interpreter: a 64x64 screen, with the program starting at 0x2C0
+    /// (after the interpreter's own patch) and 0230 to clear the screen. These programs start with 1260.
+    HiresChip8,
+    /// CHIP-8X, for the COSMAC VIP with the VP-590 color board: a background color, foreground colors for
+    /// zones of the screen, a second hex keypad, and an I/O port. Bnnn sets colors instead of jumping.
+    Chip8X,
+    /// SUPER-CHIP 1.1 on the HP-48 calculators: scrolling, a 128x64 high resolution mode with 16x16 sprites, a big
+    /// hex font, and the RPL user flags.
+    SuperChip,
+    /// MEGA-CHIP: 16 MiB of RAM, and a 256x192 mode with 256 color sprites of any size, blending, and digitized sound.
+    /// Until the program turns that mode on, it's the same as the SCHIP.
+    MegaChip,
+    /// XO-CHIP: 64 KiB of RAM, long I loads, saving and loading ranges of registers, and a second bitplane
+    /// for four colour graphics.
+    XoChip,
+}
+
+impl Platform {
+    /// Every platform, in the order they came along.
+    pub const ALL: [Platform; 6] = [Platform::Chip8, Platform::HiresChip8, Platform::Chip8X, Platform::SuperChip, Platform::MegaChip, Platform::XoChip];
+
+    /// The number of bytes of RAM this platform has.
+    pub fn memory_len(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Chip8X | Platform::SuperChip => 4096,
+            Platform::MegaChip => 0x0100_0000,
+            Platform::XoChip => 65536,
+        }
+    }
+
+    /// The most bytes of RAM this platform's programs can address. RAM can be made smaller or bigger than it
+    /// really was, up to this.
+    pub fn max_memory_len(self) -> usize {
+        match self {
+            Platform::MegaChip => 0x0100_0000,
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Chip8X | Platform::SuperChip | Platform::XoChip => 0x0001_0000,
+        }
+    }
+
+    /// The widths and heights in pixels that this platform's screen can be. Each is the highest resolution of a
+    /// mode the platform can be in, and the screen starts out the first one.
+    pub fn screen_sizes(self) -> &'static [(u32, u32)] {
+        match self {
+            Platform::HiresChip8 => &[(64, 64)],
+            Platform::MegaChip => &[(128, 64), (256, 192)],
+            Platform::Chip8 | Platform::Chip8X | Platform::SuperChip | Platform::XoChip => &[(128, 64)],
+        }
+    }
+
+    /// The most columns and the most rows that any platform's screen has, in any mode. Nothing we read back in
+    /// can have a bigger screen than this.
+    pub fn largest_screen() -> (u32, u32) {
+        let sizes = Platform::ALL.iter().flat_map(|p| p.screen_sizes().iter());
+        let ncols = sizes.clone().map(|size| size.0).max().unwrap_or(0);
+        let nrows = sizes.map(|size| size.1).max().unwrap_or(0);
+        (ncols, nrows)
+    }
+
+    /// The address the program starts running from.
+    pub fn start_address(self) -> u16 {
+        match self {
+            Platform::HiresChip8 => 0x02C0,
+            Platform::Chip8 | Platform::Chip8X | Platform::SuperChip | Platform::MegaChip | Platform::XoChip => 0x0200,
+        }
+    }
+
+    /// Does this platform run the SUPER-CHIP 1.1 instructions, along with its own?
+    pub fn has_schip(self) -> bool {
+        match self {
+            Platform::SuperChip | Platform::MegaChip | Platform::XoChip => true,
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Chip8X => false,
+        }
+    }
+
+    /// The platform `binary` was written for, if that's obvious from the way it starts.
+    ///
+    /// Right now, that means HIRES programs, which always start by jumping over the interpreter's patch with 1260.
+    /// Plenty of ordinary programs start with that jump too, so the patch has to be there at 0x202 (it is machine
+    /// code, so it isn't all zeros), and the program has to go on to where HIRES programs start running.
+    pub fn detect(binary: &[u8]) -> Option<Platform> {
+        let patch = &binary[binary.len().min(2)..binary.len().min(0x60)];
+        let start = (Platform::HiresChip8.start_address() - 0x200) as usize;
+        if binary.starts_with(&[0x12, 0x60]) && patch.iter().any(|b| *b != 0) && binary.len() > start {
+            Some(Platform::HiresChip8)
+        } else {
+            None
+        }
+    }
+
+    /// What this platform is called on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::HiresChip8 => "hires",
+            Platform::Chip8X => "chip8x",
+            Platform::SuperChip => "schip",
+            Platform::MegaChip => "megachip",
+            Platform::XoChip => "xochip",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match Platform::ALL.iter().find(|p| p.name() == s) {
+            Some(p) => Ok(*p),
+            None => {
+                let names: Vec<&str> = Platform::ALL.iter().map(|p| p.name()).collect();
+                Err(format!("{} is not a platform. Try one of {}.", s, names.join(", ")))
+            },
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! This module contains quirks: the places where the interpreters of different platforms disagree about what
+//! an instruction does.
+//!
+//! Programs tend to rely on whatever their interpreter happened to do, so running a program the way it was
+//! meant to be run means picking the right set of quirks. There is a preset for each of the common platforms.
+
+use super::platform::Platform;
+use std::fmt;
+use std::str::FromStr;
+
+/// The most addresses the stack can hold, whatever the quirks say.
+pub const MAX_STACK_DEPTH: usize = 16;
+
+/// The choices that differ between platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// Do 8xy6 and 8xyE shift Vy and put the result in Vx? If not, they shift Vx in place and ignore Vy.
+    pub shift_reads_vy: bool,
+    /// Do Fx55 and Fx65 leave I pointing just past the last register they touched? If not, I is left alone.
+    pub load_store_increments_i: bool,
+    /// Do 8xy1, 8xy2, and 8xy3 set VF to 0?
+    pub logic_resets_vf: bool,
+    /// Does Bxnn jump to xnn plus Vx? If not, Bnnn jumps to nnn plus V0.
+    pub jump_uses_vx: bool,
+    /// Are the parts of a sprite that hang off the edge of the screen cut off? If not, they wrap around to the
+    /// other side. Either way, a sprite that starts off the screen wraps around to start on it.
+    pub clip_sprites: bool,
+    /// Does DRW wait for the start of the next frame before drawing, like the VIP waiting for its vertical blank?
+    pub display_wait: bool,
+    /// How many addresses the stack holds before CALL overflows it (at most `MAX_STACK_DEPTH`).
+    pub stack_depth: usize,
+}
+
+impl Default for Quirks {
+    /// What we have always done: shifts ignore Vy, I is left alone, VF is left alone, Bnnn uses V0,
+    /// sprites wrap, there is no display wait, and the stack holds 16 addresses.
+    fn default() -> Self {
+        Quirks {
+            shift_reads_vy: false,
+            load_store_increments_i: false,
+            logic_resets_vf: false,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            display_wait: false,
+            stack_depth: MAX_STACK_DEPTH,
+        }
+    }
+}
+
+impl Quirks {
+    /// The quirks programs for `platform` expect if nobody says otherwise.
+    pub fn for_platform(platform: Platform) -> Self {
+        match platform {
+            Platform::Chip8 | Platform::MegaChip => Quirks::default(),
+            Platform::HiresChip8 | Platform::Chip8X => Preset::CosmacVip.quirks(),
+            Platform::SuperChip => Preset::Schip.quirks(),
+            Platform::XoChip => Preset::XoChip.quirks(),
+        }
+    }
+}
+
+/// The quirks of a well-known platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// The original interpreter on the COSMAC VIP.
+    CosmacVip,
+    /// CHIP-48 on the HP-48 calculators.
+    Chip48,
+    /// SUPER-CHIP 1.1 on the HP-48 calculators.
+    Schip,
+    /// XO-CHIP, as Octo runs it.
+    XoChip,
+}
+
+impl Preset {
+    /// Every preset, in the order the platforms came along.
+    pub const ALL: [Preset; 4] = [Preset::CosmacVip, Preset::Chip48, Preset::Schip, Preset::XoChip];
+
+    /// The quirks of this platform.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Preset::CosmacVip => Quirks {
+                shift_reads_vy: true,
+                load_store_increments_i: true,
+                logic_resets_vf: true,
+                jump_uses_vx: false,
+                clip_sprites: true,
+                display_wait: true,
+                stack_depth: 12,
+            },
+            Preset::Chip48 => Quirks {
+                shift_reads_vy: false,
+                load_store_increments_i: true,
+                logic_resets_vf: false,
+                jump_uses_vx: true,
+                clip_sprites: true,
+                display_wait: false,
+                stack_depth: 16,
+            },
+            Preset::Schip => Quirks {
+                shift_reads_vy: false,
+                load_store_increments_i: false,
+                logic_resets_vf: false,
+                jump_uses_vx: true,
+                clip_sprites: true,
+                display_wait: false,
+                stack_depth: 16,
+            },
+            Preset::XoChip => Quirks {
+                shift_reads_vy: true,
+                load_store_increments_i: true,
+                logic_resets_vf: false,
+                jump_uses_vx: false,
+                clip_sprites: false,
+                display_wait: false,
+                stack_depth: 16,
+            },
+        }
+    }
+
+    /// What this preset is called on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::CosmacVip => "vip",
+            Preset::Chip48 => "chip48",
+            Preset::Schip => "schip",
+            Preset::XoChip => "xochip",
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match Preset::ALL.iter().find(|p| p.name() == s) {
+            Some(p) => Ok(*p),
+            None => {
+                let names: Vec<&str> = Preset::ALL.iter().map(|p| p.name()).collect();
+                Err(format!("{} is not a quirks preset. Try one of {}.", s, names.join(", ")))
+            },
+        }
+    }
+}
@@ -67,4 +67,27 @@ impl RegisterArray {
     pub fn len(&self) -> usize {
         NUM_REGISTERS
     }
+
+    /// Returns the value of register V`v`, or None if there is no such register.
+    pub fn get(&self, v: Register) -> Option<Register> {
+        match v {
+            0 => Some(self.v0),
+            1 => Some(self.v1),
+            2 => Some(self.v2),
+            3 => Some(self.v3),
+            4 => Some(self.v4),
+            5 => Some(self.v5),
+            6 => Some(self.v6),
+            7 => Some(self.v7),
+            8 => Some(self.v8),
+            9 => Some(self.v9),
+            10 => Some(self.va),
+            11 => Some(self.vb),
+            12 => Some(self.vc),
+            13 => Some(self.vd),
+            14 => Some(self.ve),
+            15 => Some(self.vf),
+            _ => None,
+        }
+    }
 }
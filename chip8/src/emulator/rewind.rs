@@ -0,0 +1,327 @@
+//! This module contains the rewind buffer: a bounded history of snapshots, one per frame, that can be walked
+//! backwards to run time in reverse.
+//!
+//! Most of a snapshot is RAM, and hardly any of it changes from one frame to the next, so the buffer holds a copy
+//! of RAM as it was in the newest frame, and every older frame only holds the pages of RAM that the frame after it
+//! changed. The emulator tells the buffer which pages it writes to, so that only those get compared with the copy. The rest of the machine, screen included, is small, so it is written out whole for the newest frame,
+//! and every older frame keeps the difference between it and the one after it. That is almost entirely
+//! zeros, so we run-length encode the zeros away. Walking backwards just undoes one frame's changes at a time.
+//!
+//! Programs that change a lot of RAM every frame (which on MEGA-CHIP can be a lot of RAM) still make big
+//! differences, so the buffer also has a limit on how many bytes it takes up, and forgets the oldest frames to
+//! stay under it.
+
+use super::display::argb::ArgbLayer;
+use super::display::chip8panel::{ColorLayer, PixelGrid, PLANE_1, PLANE_2};
+use super::savestate::{pack_plane, unpack_plane, Reader, SaveState, Writer};
+use std::collections::{BTreeSet, VecDeque};
+use std::mem;
+
+/// How many bytes the emulator lets its rewind buffer take up, however many frames it was asked to keep.
+pub const REWIND_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// How many bytes of RAM we compare and keep at a time.
+pub const REWIND_PAGE_LEN: usize = 256;
+
+/// One frame's snapshot, as the changes to get back to it from the frame after it.
+#[derive(Debug, Clone)]
+struct Frame {
+    /// How many bytes of RAM there were.
+    memory_len: usize,
+    /// The pages of RAM the next frame changed, by page number, as they were in this frame.
+    pages: Vec<(usize, Vec<u8>)>,
+    /// The rest of the machine, encoded as its difference from the next frame's.
+    delta: Vec<u8>,
+}
+
+impl Frame {
+    /// Roughly how much memory this frame is taking up, in bytes.
+    fn nbytes(&self) -> usize {
+        mem::size_of::<Frame>() + self.pages.iter().map(|(_, page)| page.len()).sum::<usize>() + self.delta.len()
+    }
+}
+
+/// A ring buffer of snapshots.
+#[derive(Debug, Clone)]
+pub struct RewindBuffer {
+    /// The most number of snapshots we will hold onto. The oldest ones fall off the end.
+    capacity: usize,
+    /// The most bytes the snapshots can take up between them, except that the newest one is always kept.
+    max_bytes: usize,
+    /// How many bytes the frames take up between them.
+    frame_bytes: usize,
+    /// Every snapshot, oldest first. The newest one has no changes, since nothing came after it.
+    frames: VecDeque<Frame>,
+    /// RAM as it was in the newest snapshot.
+    memory: Vec<u8>,
+    /// The pages of RAM that may have been written since our copy of it matched the emulator's.
+    dirty: BTreeSet<usize>,
+    /// Might RAM have been written anywhere at all?
+    all_dirty: bool,
+    /// The rest of the machine as it was in the newest snapshot, as written by `to_bytes`.
+    newest: Vec<u8>,
+}
+
+impl RewindBuffer {
+    /// Creates an empty buffer that holds at most `capacity` snapshots, taking up at most `max_bytes`.
+    pub fn new(capacity: usize, max_bytes: usize) -> Self {
+        RewindBuffer {
+            capacity,
+            max_bytes,
+            frame_bytes: 0,
+            frames: VecDeque::new(),
+            memory: Vec::new(),
+            dirty: BTreeSet::new(),
+            all_dirty: false,
+            newest: Vec::new(),
+        }
+    }
+
+    /// Notes that `len` bytes of RAM starting at `addr` may have been written since the last snapshot.
+    pub fn mark_dirty(&mut self, addr: usize, len: usize) {
+        if len > 0 {
+            self.dirty.extend(addr / REWIND_PAGE_LEN..=(addr + len - 1) / REWIND_PAGE_LEN);
+        }
+    }
+
+    /// Notes that any of RAM may have been written since the last snapshot, like when it gets replaced entirely.
+    pub fn mark_all_dirty(&mut self) {
+        self.all_dirty = true;
+    }
+
+    /// Forgets which pages of RAM are dirty, returning them. Pages past the end of our copy of RAM are left out.
+    fn take_dirty(&mut self) -> Vec<usize> {
+        let npages = self.memory.len().div_ceil(REWIND_PAGE_LEN);
+        let dirty = mem::take(&mut self.dirty);
+        if mem::replace(&mut self.all_dirty, false) {
+            (0..npages).collect()
+        } else {
+            dirty.into_iter().take_while(|page| *page < npages).collect()
+        }
+    }
+
+    /// The part of RAM that `page` covers, when RAM is `len` bytes long.
+    fn page_range(page: usize, len: usize) -> std::ops::Range<usize> {
+        page * REWIND_PAGE_LEN..((page + 1) * REWIND_PAGE_LEN).min(len)
+    }
+
+    /// Adds a snapshot of a machine in `state` with `memory` for RAM and `framebuffer` for a screen as the most
+    /// recent one, forgetting the oldest ones if we are full. Whatever RAM and screen `state` has are ignored.
+    ///
+    /// Only the pages of `memory` marked dirty since the last snapshot are looked at.
+    pub fn push(&mut self, state: SaveState, memory: &[u8], framebuffer: &PixelGrid) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let bytes = to_bytes(state, framebuffer);
+        let dirty = self.take_dirty();
+        if let Some(newest) = self.frames.back_mut() {
+            self.frame_bytes -= newest.nbytes();
+            if memory.len() == self.memory.len() {
+                for page in dirty {
+                    let range = RewindBuffer::page_range(page, memory.len());
+                    if self.memory[range.clone()] != memory[range.clone()] {
+                        newest.pages.push((page, self.memory[range.clone()].to_vec()));
+                        self.memory[range.clone()].copy_from_slice(&memory[range]);
+                    }
+                }
+            } else {
+                newest.pages = self.memory.chunks(REWIND_PAGE_LEN).map(|page| page.to_vec()).enumerate().collect();
+                self.memory = memory.to_vec();
+            }
+            newest.delta = encode(&self.newest, &bytes);
+            self.frame_bytes += newest.nbytes();
+        } else {
+            self.memory = memory.to_vec();
+        }
+        self.newest = bytes;
+
+        let frame = Frame { memory_len: memory.len(), pages: Vec::new(), delta: Vec::new() };
+        self.frame_bytes += frame.nbytes();
+        self.frames.push_back(frame);
+
+        while self.frames.len() > self.capacity || (self.frames.len() > 1 && self.nbytes() > self.max_bytes) {
+            let oldest = self.frames.pop_front().expect("We just checked there are frames");
+            self.frame_bytes -= oldest.nbytes();
+        }
+    }
+
+    /// Removes the most recent snapshot, so that the next call returns the one before it.
+    ///
+    /// The snapshot's RAM is put into `memory`, only writing over the pages marked dirty since the last snapshot,
+    /// and the rest of it is returned. The pages that differ from the snapshot before it are then marked dirty.
+    pub fn pop(&mut self, memory: &mut Vec<u8>) -> Option<SaveState> {
+        let frame = self.frames.pop_back()?;
+        self.frame_bytes -= frame.nbytes();
+        let dirty = self.take_dirty();
+        if memory.len() == self.memory.len() {
+            for page in dirty {
+                let range = RewindBuffer::page_range(page, memory.len());
+                memory[range.clone()].copy_from_slice(&self.memory[range]);
+            }
+        } else {
+            memory.clear();
+            memory.extend_from_slice(&self.memory);
+        }
+        let state = from_bytes(&self.newest).expect("We only ever read snapshots we wrote");
+
+        // Undo the changes the popped frame made, so that our copies are of the frame before it
+        if let Some(previous) = self.frames.back_mut() {
+            self.frame_bytes -= previous.nbytes();
+            self.memory.resize(previous.memory_len, 0);
+            for (page, saved) in previous.pages.drain(..) {
+                let start = page * REWIND_PAGE_LEN;
+                self.memory[start..start + saved.len()].copy_from_slice(&saved);
+                self.dirty.insert(page);
+            }
+            self.newest = decode(&mem::take(&mut previous.delta), &self.newest);
+            self.frame_bytes += previous.nbytes();
+        }
+
+        Some(state)
+    }
+
+    /// The number of snapshots we are holding.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Are we holding any snapshots?
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Roughly how much memory the snapshots are taking up, in bytes.
+    pub fn nbytes(&self) -> usize {
+        self.memory.len() + self.newest.len() + self.frame_bytes
+    }
+}
+
+/// Writes out everything in `state` but its RAM, with `framebuffer` for a screen.
+///
+/// The screen goes in the save state's format, but written separately, so that it doesn't have to be copied into
+/// `state` first.
+fn to_bytes(mut state: SaveState, framebuffer: &PixelGrid) -> Vec<u8> {
+    state.memory = Vec::new();
+    state.framebuffer = PixelGrid::new(0, 0);
+    let mut w = Writer { bytes: Vec::new() };
+    w.u8s(&state.to_bytes());
+    w.u32(framebuffer.nrows);
+    w.u32(framebuffer.ncols);
+    w.u8s(&pack_plane(framebuffer, PLANE_1));
+    w.u8s(&pack_plane(framebuffer, PLANE_2));
+    w.bool(framebuffer.colors.is_some());
+    if let Some(ref colors) = framebuffer.colors {
+        w.u8(colors.background);
+        w.u8s(&colors.foreground);
+    }
+    w.bool(framebuffer.argb.is_some());
+    if let Some(ref argb) = framebuffer.argb {
+        w.u8(argb.alpha);
+        for color in argb.shown.iter().chain(argb.drawing.iter()) {
+            w.u32(*color);
+        }
+        w.u8s(&argb.indexes);
+    }
+    w.bytes
+}
+
+/// Undoes `to_bytes`, giving back a state with no RAM.
+fn from_bytes(bytes: &[u8]) -> Result<SaveState, String> {
+    let mut r = Reader { bytes, pos: 0 };
+    let mut state = SaveState::from_bytes(&r.u8s()?)?;
+    let nrows = r.u32()?;
+    let ncols = r.u32()?;
+    let mut framebuffer = PixelGrid::new(nrows, ncols);
+    let npixels = framebuffer.pixels.len();
+    unpack_plane(&mut framebuffer, PLANE_1, &r.u8s()?)?;
+    unpack_plane(&mut framebuffer, PLANE_2, &r.u8s()?)?;
+    if r.bool()? {
+        let background = r.u8()?;
+        framebuffer.colors = Some(ColorLayer { background, foreground: r.u8s()? });
+    }
+    if r.bool()? {
+        let alpha = r.u8()?;
+        let mut colors = Vec::with_capacity(2 * npixels);
+        for _ in 0..2 * npixels {
+            colors.push(r.u32()?);
+        }
+        let drawing = colors.split_off(npixels);
+        framebuffer.argb = Some(ArgbLayer { shown: colors, drawing, indexes: r.u8s()?, alpha });
+    }
+    state.framebuffer = framebuffer;
+    Ok(state)
+}
+
+/// Encodes `older` as its difference from `newer`.
+///
+/// The difference is the two XORed together (treating `newer` as zeros past its end), written as a series of
+/// (number of zero bytes, number of literal bytes, the literal bytes) runs. The whole thing starts with the
+/// length of `older`, since the two don't have to be the same length.
+fn encode(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut out = Vec::<u8>::new();
+    write_varint(&mut out, older.len());
+
+    let xored: Vec<u8> = older.iter().enumerate().map(|(i, b)| b ^ newer.get(i).cloned().unwrap_or(0)).collect();
+    let mut i = 0;
+    while i < xored.len() {
+        let zeros_start = i;
+        while i < xored.len() && xored[i] == 0 {
+            i += 1;
+        }
+        let literals_start = i;
+        while i < xored.len() && xored[i] != 0 {
+            i += 1;
+        }
+        write_varint(&mut out, literals_start - zeros_start);
+        write_varint(&mut out, i - literals_start);
+        out.extend_from_slice(&xored[literals_start..i]);
+    }
+
+    out
+}
+
+/// Undoes `encode`, getting `older` back from its difference and `newer`.
+fn decode(delta: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut older: Vec<u8> = (0..len).map(|i| newer.get(i).cloned().unwrap_or(0)).collect();
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let nliterals = read_varint(delta, &mut pos);
+        for b in &delta[pos..pos + nliterals] {
+            older[i] ^= b;
+            i += 1;
+        }
+        pos += nliterals;
+    }
+
+    older
+}
+
+/// Writes `n` seven bits at a time, with the top bit of each byte saying whether there are more to come.
+fn write_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7F) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// Reads a number written with `write_varint` starting at `pos`, moving `pos` past it.
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let b = bytes[*pos];
+        *pos += 1;
+        n |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return n;
+        }
+        shift += 7;
+    }
+}
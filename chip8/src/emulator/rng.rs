@@ -0,0 +1,79 @@
+//! This module contains the random number generator behind the RND instruction.
+//!
+//! The generator is owned by the Chip8 and its whole state is a single number, so it can be seeded for
+//! reproducible runs and put into save states along with everything else.
+
+use super::rand::{thread_rng, Rng as _};
+
+/// Where the COSMAC VIP's random number routine reads its table of "random" bytes from: the page of RAM
+/// that holds the interpreter itself.
+pub const VIP_RANDOM_TABLE_ADDR: usize = 0x0100;
+
+/// Which algorithm to use for random numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RngMode {
+    /// A good modern generator (xorshift64*).
+    #[default]
+    Modern,
+    /// The original COSMAC VIP interpreter's routine, which keeps a two byte seed. Every call, it increments
+    /// the low byte and uses it to pick a byte out of the interpreter's own code (at `VIP_RANDOM_TABLE_ADDR`),
+    /// adds that to the high byte, and hands the high byte back as the random number.
+    ///
+    /// Just like on a real VIP, the numbers are only as random as whatever is in that page of RAM.
+    CosmacVip,
+}
+
+/// A seedable random number generator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    /// Which algorithm we are using.
+    pub mode: RngMode,
+    /// Everything the algorithm needs to pick up where it left off. The VIP algorithm only uses the bottom
+    /// 16 bits: the low byte of the seed, then the high byte.
+    pub state: u64,
+}
+
+impl Rng {
+    /// Creates a generator that will always produce the same numbers for the same `mode` and `seed`.
+    pub fn new(mode: RngMode, seed: u64) -> Self {
+        let state = match mode {
+            // Mix the seed up so that small seeds don't start us off with a run of small numbers, and never
+            // let the state be zero, since xorshift gets stuck there
+            RngMode::Modern => std::cmp::max(1, splitmix64(seed)),
+            RngMode::CosmacVip => seed & 0xFFFF,
+        };
+        Rng { mode, state }
+    }
+
+    /// Creates a generator with a seed nobody can predict.
+    pub fn from_entropy(mode: RngMode) -> Self {
+        Rng::new(mode, thread_rng().gen())
+    }
+
+    /// Returns the next random byte. `memory` is only used by the VIP algorithm.
+    pub fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        match self.mode {
+            RngMode::Modern => {
+                self.state ^= self.state >> 12;
+                self.state ^= self.state << 25;
+                self.state ^= self.state >> 27;
+                (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            },
+            RngMode::CosmacVip => {
+                let low = (self.state as u8).wrapping_add(1);
+                let table_byte = memory.get(VIP_RANDOM_TABLE_ADDR + low as usize).cloned().unwrap_or(0);
+                let high = ((self.state >> 8) as u8).wrapping_add(table_byte);
+                self.state = ((high as u64) << 8) | (low as u64);
+                high
+            },
+        }
+    }
+}
+
+/// Scrambles a seed into a starting state.
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
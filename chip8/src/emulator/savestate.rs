@@ -0,0 +1,532 @@
+//! This module contains save states: the complete state of the machine, so it can be put away and picked back up later.
+//!
+//! Save states are stored in a small binary format. Every file starts with a version number, and every time
+//! something gets added to the machine, the version goes up and `from_bytes` learns to fill in a sensible
+//! value for the new stuff when it reads an older file. That way old saves keep loading.
+
+use super::display::argb::{ArgbLayer, BlendMode, PALETTE_SIZE};
+use super::display::chip8panel::{ColorLayer, PixelGrid, Pxcolor, PLANE_1, PLANE_2};
+use super::platform::Platform;
+use super::quirks::Quirks;
+use super::rng::{Rng, RngMode};
+use super::timing::Timing;
+use std::fs;
+use std::path;
+
+/// The first bytes of every save state file.
+const MAGIC: &[u8] = b"MYCHIP8S";
+/// The version of the format that `to_bytes` writes.
+///
+/// Version history:
+/// 1. The first version.
+/// 2. Added the random number generator. Older saves get a Modern generator seeded with 0.
+/// 3. Added the SCHIP's resolution mode and RPL user flags. Older saves are in low resolution with the flags all 0.
+/// 4. Added the platform, the selected XO-CHIP planes, and the framebuffer's second plane. Older saves are plain
+///    Chip-8 with the first plane selected and nothing in the second one.
+/// 5. Added the quirks. Older saves get the default quirks.
+/// 6. Added the CHIP-8X colors and output port. Older saves have no color board and 0 on the port.
+/// 7. Added the top byte of I and everything MEGA-CHIP. Older saves have I under 0x10000, a black palette, 256x256
+///    sprites, normal blending, no collision color, and MEGA-CHIP mode off.
+/// 8. Added the VIP memory layout. Older saves keep the screen and stack out of RAM.
+/// 9. Added running SYS routines on the CDP1802. Older saves ignore SYS.
+/// 10. Added the timing model and the machine cycles spent so far. Older saves have flat timing.
+/// 11. Added where the font is. Older saves have it at 0.
+/// 12. Added where the program was loaded. Older saves have it at 0x200.
+/// 13. Added whether a DRW was waiting for the display. Older saves weren't.
+/// 14. Split the SCHIP off from plain Chip-8 as a platform of its own. Older Chip-8 saves could run SCHIP
+///     instructions, so they are SCHIP saves.
+pub const SAVE_STATE_VERSION: u16 = 14;
+
+/// Everything about the machine.
+#[derive(Debug, Clone)]
+pub struct SaveState {
+    /// Program counter
+    pub pc: u16,
+    /// Register I
+    pub index: u32,
+    /// Stack pointer
+    pub sp: u8,
+    /// V0 through VF
+    pub registers: Vec<u8>,
+    /// The whole stack, including the slots above the stack pointer.
+    pub stack: Vec<u16>,
+    /// Value of the delay timer
+    pub delay_timer: u8,
+    /// Value of the sound timer
+    pub sound_timer: u8,
+    /// The number of instructions executed so far
+    pub instruction_count: usize,
+    /// The clock rate of the CPU in Hz
+    pub clock_rate_hz: u64,
+    /// Was an LDVxK instruction waiting for a key?
+    pub awaiting_key: bool,
+    /// Were we stopped on a BRK (so that the next cycle executes it instead of stopping again)?
+    pub at_breakpoint: bool,
+    /// All of RAM
+    pub memory: Vec<u8>,
+    /// The Chip-8 screen
+    pub framebuffer: PixelGrid,
+    /// The random number generator behind RND
+    pub rng: Rng,
+    /// Was the SCHIP's high resolution mode on?
+    pub hires: bool,
+    /// The SCHIP's RPL user flags
+    pub rpl_flags: Vec<u8>,
+    /// Which machine we were pretending to be
+    pub platform: Platform,
+    /// The mask of the XO-CHIP planes that drawing, scrolling, and clearing were working on
+    pub planes: u8,
+    /// The choices about what instructions do that differ between platforms
+    pub quirks: Quirks,
+    /// The last byte sent out of the CHIP-8X output port
+    pub port_out: u8,
+    /// The MEGA-CHIP's palette of ARGB colors
+    pub palette: Vec<u32>,
+    /// The width of MEGA-CHIP sprites, where 0 means 256
+    pub sprite_width: u8,
+    /// The height of MEGA-CHIP sprites, where 0 means 256
+    pub sprite_height: u8,
+    /// How MEGA-CHIP sprites were being blended onto the screen
+    pub blend_mode: BlendMode,
+    /// The palette entry MEGA-CHIP sprites collide with
+    pub collision_index: u8,
+    /// Were the screen and stack kept in RAM where the COSMAC VIP kept them?
+    pub vip_layout: bool,
+    /// Did SYS run machine code routines on the CDP1802?
+    pub cdp1802: bool,
+    /// How emulated time was going by
+    pub timing: Timing,
+    /// With COSMAC VIP timing, the machine cycles the interpreter had spent
+    pub cycle_count: u64,
+    /// With COSMAC VIP timing, the machine cycles the interpreter had spent when the last instruction started
+    pub last_cycle_count: u64,
+    /// Where the hex sprites' font was in RAM
+    pub font_address: u16,
+    /// Where the program was loaded in RAM
+    pub load_address: u16,
+    /// Was a DRW sitting idle until the next frame because of the display wait quirk?
+    pub display_waiting: bool,
+}
+
+impl SaveState {
+    /// Writes this state to the file at `path`, replacing whatever was there.
+    pub fn save(&self, path: &path::Path) -> Result<(), String> {
+        match fs::write(path, self.to_bytes()) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(format!("Could not write save state to {}: {:?}", path.display(), e)),
+        }
+    }
+
+    /// Reads a state back out of the file at `path`.
+    pub fn load(path: &path::Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(bytes) => SaveState::from_bytes(&bytes),
+            Err(e) => Err(format!("Could not read save state at {}: {:?}", path.display(), e)),
+        }
+    }
+
+    /// Encodes this state in the current version of the format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer { bytes: MAGIC.to_vec() };
+        w.u16(SAVE_STATE_VERSION);
+
+        w.u16(self.pc);
+        // Only the bottom 16 bits of I are here. The top byte came along later, so it is at the end.
+        w.u16(self.index as u16);
+        w.u8(self.sp);
+        w.u8s(&self.registers);
+        w.u32(self.stack.len() as u32);
+        for addr in self.stack.iter() {
+            w.u16(*addr);
+        }
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.u64(self.instruction_count as u64);
+        w.u64(self.clock_rate_hz);
+        w.bool(self.awaiting_key);
+        w.bool(self.at_breakpoint);
+        w.u8s(&self.memory);
+
+        // The framebuffer is packed eight pixels to a byte, a set bit meaning the pixel is on in the first plane
+        w.u32(self.framebuffer.nrows);
+        w.u32(self.framebuffer.ncols);
+        w.u8s(&pack_plane(&self.framebuffer, PLANE_1));
+
+        w.u8(match self.rng.mode {
+            RngMode::Modern => 0,
+            RngMode::CosmacVip => 1,
+        });
+        w.u64(self.rng.state);
+
+        w.bool(self.hires);
+        w.u8s(&self.rpl_flags);
+
+        w.u8(match self.platform {
+            Platform::Chip8 => 0,
+            Platform::XoChip => 1,
+            Platform::Chip8X => 2,
+            Platform::HiresChip8 => 3,
+            Platform::MegaChip => 4,
+            Platform::SuperChip => 5,
+        });
+        w.u8(self.planes);
+        w.u8s(&pack_plane(&self.framebuffer, PLANE_2));
+
+        w.bool(self.quirks.shift_reads_vy);
+        w.bool(self.quirks.load_store_increments_i);
+        w.bool(self.quirks.logic_resets_vf);
+        w.bool(self.quirks.jump_uses_vx);
+        w.bool(self.quirks.clip_sprites);
+        w.bool(self.quirks.display_wait);
+        w.u32(self.quirks.stack_depth as u32);
+
+        // The colors are a flag for whether there is a color board, then the background, then a foreground per pixel
+        w.bool(self.framebuffer.colors.is_some());
+        if let Some(ref colors) = self.framebuffer.colors {
+            w.u8(colors.background);
+            w.u8s(&colors.foreground);
+        }
+        w.u8(self.port_out);
+
+        w.u8((self.index >> 16) as u8);
+        w.u32(self.palette.len() as u32);
+        for color in self.palette.iter() {
+            w.u32(*color);
+        }
+        w.u8(self.sprite_width);
+        w.u8(self.sprite_height);
+        w.u8(self.blend_mode.number());
+        w.u8(self.collision_index);
+        w.bool(self.framebuffer.argb.is_some());
+        if let Some(ref argb) = self.framebuffer.argb {
+            w.u8(argb.alpha);
+            for colors in [&argb.shown, &argb.drawing].iter() {
+                for color in colors.iter() {
+                    w.u32(*color);
+                }
+            }
+            w.u8s(&argb.indexes);
+        }
+        w.bool(self.vip_layout);
+        w.bool(self.cdp1802);
+        w.u8(match self.timing {
+            Timing::Flat => 0,
+            Timing::CosmacVip => 1,
+        });
+        w.u64(self.cycle_count);
+        w.u64(self.last_cycle_count);
+        w.u16(self.font_address);
+        w.u16(self.load_address);
+        w.bool(self.display_waiting);
+
+        w.bytes
+    }
+
+    /// A fingerprint of this state, so that two states can be checked for being the same without keeping both around.
+    ///
+    /// This is a 64-bit FNV-1a hash of `to_bytes`.
+    pub fn digest(&self) -> u64 {
+        self.to_bytes().iter().fold(0xCBF2_9CE4_8422_2325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01B3))
+    }
+
+    /// Decodes a state from any version of the format up to the current one.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err("This is not a save state.".to_string());
+        }
+        let version = r.u16()?;
+        if version == 0 || version > SAVE_STATE_VERSION {
+            return Err(format!("This save state is version {}, but we only understand versions 1 through {}.", version, SAVE_STATE_VERSION));
+        }
+
+        let pc = r.u16()?;
+        let mut index = r.u16()? as u32;
+        let sp = r.u8()?;
+        let registers = r.u8s()?;
+        let mut stack = Vec::<u16>::new();
+        for _ in 0..r.u32()? {
+            stack.push(r.u16()?);
+        }
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let instruction_count = r.u64()? as usize;
+        let clock_rate_hz = r.u64()?;
+        let awaiting_key = r.bool()?;
+        let at_breakpoint = r.bool()?;
+        let memory = r.u8s()?;
+
+        let nrows = r.u32()?;
+        let ncols = r.u32()?;
+        let (maxcols, maxrows) = Platform::largest_screen();
+        if ncols > maxcols || nrows > maxrows {
+            return Err(format!("The screen is {}x{}, which is bigger than any platform's.", ncols, nrows));
+        } else if (ncols * nrows / 8) as usize > r.remaining() {
+            return Err("The file ends too early.".to_string());
+        }
+        let mut framebuffer = PixelGrid::new(nrows, ncols);
+        unpack_plane(&mut framebuffer, PLANE_1, &r.u8s()?)?;
+
+        let rng = if version >= 2 {
+            let mode = match r.u8()? {
+                0 => RngMode::Modern,
+                1 => RngMode::CosmacVip,
+                m => return Err(format!("{} is not a kind of random number generator.", m)),
+            };
+            Rng { mode, state: r.u64()? }
+        } else {
+            Rng::new(RngMode::Modern, 0)
+        };
+
+        let (hires, rpl_flags) = if version >= 3 {
+            (r.bool()?, r.u8s()?)
+        } else {
+            (false, vec![0; 16])
+        };
+
+        let (platform, planes) = if version >= 4 {
+            let platform = match r.u8()? {
+                0 => Platform::Chip8,
+                1 => Platform::XoChip,
+                2 => Platform::Chip8X,
+                3 => Platform::HiresChip8,
+                4 => Platform::MegaChip,
+                5 => Platform::SuperChip,
+                p => return Err(format!("{} is not a platform.", p)),
+            };
+            let planes = r.u8()?;
+            unpack_plane(&mut framebuffer, PLANE_2, &r.u8s()?)?;
+            (platform, planes)
+        } else {
+            (Platform::Chip8, PLANE_1)
+        };
+        let platform = if version < 14 && platform == Platform::Chip8 { Platform::SuperChip } else { platform };
+
+        let quirks = if version >= 5 {
+            Quirks {
+                shift_reads_vy: r.bool()?,
+                load_store_increments_i: r.bool()?,
+                logic_resets_vf: r.bool()?,
+                jump_uses_vx: r.bool()?,
+                clip_sprites: r.bool()?,
+                display_wait: r.bool()?,
+                stack_depth: r.u32()? as usize,
+            }
+        } else {
+            Quirks::default()
+        };
+
+        let port_out = if version >= 6 {
+            if r.bool()? {
+                let background = r.u8()?;
+                let foreground = r.u8s()?;
+                if foreground.len() != framebuffer.pixels.len() {
+                    return Err(format!("The screen has {} pixels, but there are colors for {}.", framebuffer.pixels.len(), foreground.len()));
+                }
+                framebuffer.colors = Some(ColorLayer { background, foreground });
+            }
+            r.u8()?
+        } else {
+            0
+        };
+
+        let (palette, sprite_width, sprite_height, blend_mode, collision_index) = if version >= 7 {
+            index |= (r.u8()? as u32) << 16;
+            let mut palette = Vec::<u32>::new();
+            for _ in 0..r.u32()? {
+                palette.push(r.u32()?);
+            }
+            if palette.len() != PALETTE_SIZE {
+                return Err(format!("The palette has {} colors, but it should have {}.", palette.len(), PALETTE_SIZE));
+            }
+            let sprite_width = r.u8()?;
+            let sprite_height = r.u8()?;
+            let blend_mode = match BlendMode::from_number(r.u8()?) {
+                Some(m) => m,
+                None => return Err("That is not a blend mode.".to_string()),
+            };
+            let collision_index = r.u8()?;
+            if r.bool()? {
+                let npixels = framebuffer.pixels.len();
+                let mut argb = ArgbLayer::new(npixels);
+                argb.alpha = r.u8()?;
+                for colors in [&mut argb.shown, &mut argb.drawing].iter_mut() {
+                    for color in colors.iter_mut() {
+                        *color = r.u32()?;
+                    }
+                }
+                argb.indexes = r.u8s()?;
+                if argb.indexes.len() != npixels {
+                    return Err(format!("The screen has {} pixels, but there are palette indexes for {}.", npixels, argb.indexes.len()));
+                }
+                framebuffer.argb = Some(argb);
+            }
+            (palette, sprite_width, sprite_height, blend_mode, collision_index)
+        } else {
+            (vec![0; PALETTE_SIZE], 0, 0, BlendMode::Normal, 0)
+        };
+
+        let vip_layout = if version >= 8 { r.bool()? } else { false };
+        let cdp1802 = if version >= 9 { r.bool()? } else { false };
+        let (timing, cycle_count, last_cycle_count) = if version >= 10 {
+            let timing = match r.u8()? {
+                0 => Timing::Flat,
+                1 => Timing::CosmacVip,
+                t => return Err(format!("{} is not a timing model.", t)),
+            };
+            (timing, r.u64()?, r.u64()?)
+        } else {
+            (Timing::Flat, 0, 0)
+        };
+        let font_address = if version >= 11 { r.u16()? } else { 0 };
+        let load_address = if version >= 12 { r.u16()? } else { 0x0200 };
+        let display_waiting = if version >= 13 { r.bool()? } else { false };
+
+        if r.pos != bytes.len() {
+            return Err(format!("There are {} bytes left over at the end of the save state.", bytes.len() - r.pos));
+        }
+
+        Ok(SaveState {
+            pc,
+            index,
+            sp,
+            registers,
+            stack,
+            delay_timer,
+            sound_timer,
+            instruction_count,
+            clock_rate_hz,
+            awaiting_key,
+            at_breakpoint,
+            memory,
+            framebuffer,
+            rng,
+            hires,
+            rpl_flags,
+            platform,
+            planes,
+            quirks,
+            port_out,
+            palette,
+            sprite_width,
+            sprite_height,
+            blend_mode,
+            collision_index,
+            vip_layout,
+            cdp1802,
+            timing,
+            cycle_count,
+            last_cycle_count,
+            font_address,
+            load_address,
+            display_waiting,
+        })
+    }
+}
+
+/// Packs one plane of `framebuffer` eight pixels to a byte, a set bit meaning the pixel is on in that plane.
+pub(super) fn pack_plane(framebuffer: &PixelGrid, plane: u8) -> Vec<u8> {
+    framebuffer.pixels.chunks(8).map(|pixels| {
+        pixels.iter().enumerate().fold(0u8, |byte, (bit, p)| {
+            if p.value.planes() & plane != 0 { byte | (0x80 >> bit) } else { byte }
+        })
+    }).collect()
+}
+
+/// Turns on the pixels of `framebuffer` in `plane` wherever `packed` (as written by `pack_plane`) says they are on.
+pub(super) fn unpack_plane(framebuffer: &mut PixelGrid, plane: u8, packed: &[u8]) -> Result<(), String> {
+    if packed.len() * 8 < framebuffer.pixels.len() {
+        return Err(format!("The framebuffer should be {}x{}, but there are only {} bytes of it.", framebuffer.ncols, framebuffer.nrows, packed.len()));
+    }
+    for (i, p) in framebuffer.pixels.iter_mut().enumerate() {
+        if packed[i / 8] & (0x80 >> (i % 8)) != 0 {
+            p.value = Pxcolor::from_planes(p.value.planes() | plane);
+        }
+    }
+    Ok(())
+}
+
+/// Appends little-endian values to a buffer.
+pub(super) struct Writer {
+    pub(super) bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub(super) fn u8(&mut self, v: u8) {
+        self.bytes.push(v);
+    }
+
+    pub(super) fn u16(&mut self, v: u16) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(super) fn u32(&mut self, v: u32) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(super) fn u64(&mut self, v: u64) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(super) fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    /// A length, then that many bytes.
+    pub(super) fn u8s(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.bytes.extend_from_slice(v);
+    }
+}
+
+/// Reads little-endian values back out of a buffer written by a Writer.
+pub(super) struct Reader<'a> {
+    pub(super) bytes: &'a [u8],
+    pub(super) pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.pos + n > self.bytes.len() {
+            return Err("The file ends too early.".to_string());
+        }
+        let taken = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(taken)
+    }
+
+    pub(super) fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(super) fn u16(&mut self) -> Result<u16, String> {
+        let mut buf = [0u8; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32, String> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub(super) fn u64(&mut self) -> Result<u64, String> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub(super) fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub(super) fn u8s(&mut self) -> Result<Vec<u8>, String> {
+        let n = self.u32()? as usize;
+        Ok(self.take(n)?.to_vec())
+    }
+}
@@ -0,0 +1,81 @@
+//! This module contains the frame scheduler, which decides when each 60 Hz frame starts and keeps the frames
+//! from going by faster than real time.
+//!
+//! Time on the emulated machine is counted in instructions: at a clock rate of `clock_rate_hz`, instruction
+//! number `n` runs `n / clock_rate_hz` seconds in. A frame starts every 1/60th of a second of that time, so when
+//! the frames (and the timers that tick on them) happen has nothing to do with how fast the host is going.
+//! Clock rates below 60 Hz just mean that more than one frame goes by per instruction. With COSMAC VIP timing,
+//! time is counted in machine cycles instead (see the `timing` module), but the frames are paced the same way.
+
+use std::cmp;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The number of frames in a second of emulated time. The delay and sound timers tick once a frame.
+pub const FRAME_RATE_HZ: u64 = 60;
+/// If we ever fall this many frames behind real time (say, because we sat on a breakpoint), we stop trying
+/// to catch up and just carry on from now.
+const MAX_FRAMES_BEHIND: u32 = 6;
+
+/// Works out where the frames start, and paces them against the wall clock.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    /// Should we wait for real time to catch up at the start of each frame?
+    throttle: bool,
+    /// When the next frame should start in real time, if we are throttled and have started a frame yet.
+    next_frame_at: Option<Instant>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler. If `throttle` is false, frames start as soon as the previous one is done.
+    pub fn new(throttle: bool) -> Self {
+        Scheduler {
+            throttle,
+            next_frame_at: None,
+        }
+    }
+
+    /// The number of frames that start right before instruction number `n` (counting from 0) at `clock_rate_hz`.
+    ///
+    /// The very first instruction starts the first frame. After that, this is usually 0 or 1, but it can be more
+    /// when the clock is slower than the frame rate. A clock rate of 0 is treated as 1 Hz.
+    pub fn frames_starting_at(n: usize, clock_rate_hz: u64) -> u64 {
+        if n == 0 {
+            return 1;
+        }
+        let clock_rate_hz = cmp::max(1, clock_rate_hz) as u128;
+        let frame_of = |i: usize| (i as u128 * FRAME_RATE_HZ as u128 / clock_rate_hz) as u64;
+        frame_of(n) - frame_of(n - 1)
+    }
+
+    /// Turns waiting for real time on or off.
+    pub fn set_throttle(&mut self, throttle: bool) {
+        self.throttle = throttle;
+        self.next_frame_at = None;
+    }
+
+    /// Are we waiting for real time?
+    pub fn is_throttled(&self) -> bool {
+        self.throttle
+    }
+
+    /// Called at the start of every frame. If we are throttled, this sleeps until it is time for the frame to start.
+    pub fn wait_for_frame(&mut self) {
+        if !self.throttle {
+            return;
+        }
+
+        let now = Instant::now();
+        let period = Duration::from_nanos(1_000_000_000 / FRAME_RATE_HZ);
+        self.next_frame_at = Some(match self.next_frame_at {
+            Some(at) if at > now => {
+                thread::sleep(at - now);
+                at + period
+            },
+            // We're running a little late, so go right away and let the next few frames catch up
+            Some(at) if now - at < period * MAX_FRAMES_BEHIND => at + period,
+            // This is the first frame, or we are so far behind that we should just start over
+            _ => now + period,
+        });
+    }
+}
@@ -0,0 +1,112 @@
+//! This module contains the timing models, which decide how much emulated time each instruction takes, and so
+//! how many instructions go by in each 60 Hz frame.
+//!
+//! The flat model gives every instruction the same time at some clock rate. The COSMAC VIP model charges each
+//! instruction the machine cycles the original interpreter spent on it, out of the share of each frame that the
+//! display leaves it.
+
+use super::opcode::Opcode;
+use std::fmt;
+use std::str::FromStr;
+
+/// The machine cycles in a COSMAC VIP frame: 262 scan lines of 14 machine cycles (of 8 clocks at 1.7609 MHz).
+pub const VIP_CYCLES_PER_FRAME: u64 = 3668;
+/// The machine cycles of each frame that go to the display: its DMA and interrupt routine keep the CPU busy for
+/// the 128 lines of picture, plus the routine's own setup.
+pub const VIP_DISPLAY_CYCLES: u64 = 1832;
+/// The machine cycles of each frame that the interpreter gets.
+pub const VIP_INTERPRETER_CYCLES_PER_FRAME: u64 = VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES;
+/// The machine cycles the interpreter takes to fetch and decode an instruction, before doing anything about it.
+const VIP_FETCH_CYCLES: u64 = 40;
+/// The extra machine cycles an instruction takes when it skips the next one.
+pub const VIP_SKIP_CYCLES: u64 = 4;
+
+/// How emulated time goes by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes the same time at the clock rate.
+    #[default]
+    Flat,
+    /// Every instruction takes as many machine cycles as it did on the COSMAC VIP, and DRW waits for the display's
+    /// interrupt at the start of the next frame. The clock rate doesn't matter.
+    CosmacVip,
+}
+
+impl Timing {
+    /// Every timing model, the flat one first.
+    pub const ALL: [Timing; 2] = [Timing::Flat, Timing::CosmacVip];
+
+    /// What this timing model is called on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Timing::Flat => "flat",
+            Timing::CosmacVip => "vip",
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match Timing::ALL.iter().find(|t| t.name() == s) {
+            Some(t) => Ok(*t),
+            None => {
+                let names: Vec<&str> = Timing::ALL.iter().map(|t| t.name()).collect();
+                Err(format!("{} is not a timing model. Try one of {}.", s, names.join(", ")))
+            },
+        }
+    }
+}
+
+/// The machine cycles the COSMAC VIP interpreter spends on `opcode`, when the registers hold what `v` says.
+/// This doesn't count skipping the next instruction (see `VIP_SKIP_CYCLES`), machine code that SYS runs, or waiting
+/// for the display.
+///
+/// DRW costs more for sprites that aren't lined up with the bytes of the screen, since each of their rows has to be
+/// shifted into place and written across two bytes. The instructions the VIP never had cost the same as LD I.
+pub fn vip_cycles<F>(opcode: Opcode, v: F) -> u64 where F: Fn(u8) -> u8 {
+    let execute = match opcode {
+        // Not a real instruction, so it takes no time at all
+        Opcode::BRK => return 0,
+        Opcode::SYS(_) => 26,
+        Opcode::CLS => 3078,
+        Opcode::RET => 10,
+        Opcode::JP(_) => 12,
+        Opcode::CALL(_) => 26,
+        Opcode::SEVxByte(..) | Opcode::SNEVxByte(..) => 10,
+        Opcode::SEVxVy(..) | Opcode::SNEVxVy(..) => 14,
+        Opcode::LDVxByte(..) => 6,
+        Opcode::ADDVxByte(..) => 10,
+        Opcode::LDVxVy(..) => 12,
+        Opcode::ORVxVy(..) | Opcode::ANDVxVy(..) | Opcode::XORVxVy(..) | Opcode::ADDVxVy(..) | Opcode::SUBVxVy(..)
+            | Opcode::SHRVx(..) | Opcode::SUBNVxVy(..) | Opcode::SHLVx(..) => 44,
+        Opcode::LDIAddr(_) => 12,
+        // Going over a page boundary takes one more instruction
+        Opcode::JPV0Addr(addr) => if (addr & 0xFF) + v(0) as u16 > 0xFF { 24 } else { 22 },
+        Opcode::RNDVxByte(..) => 36,
+        Opcode::DRWVxVyNibble(x, _, n) => {
+            let shift = v(x) as u64 % 8;
+            let row = if shift == 0 { 30 } else { 42 + 8 * shift };
+            26 + n as u64 * row
+        },
+        Opcode::SKPVx(_) | Opcode::SKNPVx(_) => 14,
+        Opcode::LDVxDT(_) | Opcode::LDDTVx(_) | Opcode::LDSTVx(_) => 10,
+        Opcode::LDVxK(_) => 38,
+        Opcode::ADDIVx(_) | Opcode::LDFVx(_) => 16,
+        // Each digit is counted out one at a time
+        Opcode::LDBVx(x) => {
+            let vx = v(x) as u64;
+            80 + 16 * (vx / 100 + vx / 10 % 10 + vx % 10)
+        },
+        Opcode::LDIVx(x) | Opcode::LDVxI(x) => 14 + 14 * (x as u64 + 1),
+        _ => 12,
+    };
+    VIP_FETCH_CYCLES + execute
+}
@@ -0,0 +1,2839 @@
+//! A Chip 8 emulator that can be embedded in other programs.
+//!
+//! The emulator core (`Chip8`) can be driven directly with `step`, `run_for`, and `run_until`,
+//! or run on its own thread with `emulate` and poked at over the debug interface.
+
+/* Mods */
+pub mod display;
+pub mod emulator;
+
+/* Public interface */
+pub use self::display::argb::{ArgbLayer, BlendMode};
+pub use self::display::chip8panel::{ColorLayer, Pixel, PixelGrid, Pxcolor, ALL_PLANES, PLANE_1, PLANE_2};
+pub use self::display::gui::Gui;
+pub use self::display::headless::HeadlessDisplay;
+pub use self::display::sprite::Sprite;
+pub use self::emulator::backend::{AudioBackend, Backend, DisplayBackend, InputBackend, UserRequest};
+pub use self::emulator::breakpoint::{Access, Breakpoint, Condition};
+pub use self::emulator::chip8::{Budget, Chip8, StopReason};
+pub use self::emulator::crashdump::CrashDump;
+pub use self::emulator::debugiface::{BreakPolicy, EmulatorCommand, EmulatorEvent, EmulatorResponse};
+pub use self::emulator::error::{EmulatorError, ErrorKind};
+pub use self::emulator::font::FontSet;
+pub use self::emulator::keyboard::Keypad;
+pub use self::emulator::movie::{KeyEvent, Movie};
+pub use self::emulator::opcode::Opcode;
+pub use self::emulator::platform::Platform;
+pub use self::emulator::quirks::{Preset, Quirks};
+pub use self::emulator::rewind::{RewindBuffer, REWIND_MAX_BYTES};
+pub use self::emulator::rng::{Rng, RngMode, VIP_RANDOM_TABLE_ADDR};
+pub use self::emulator::savestate::SaveState;
+pub use self::emulator::timing::Timing;
+
+/* Uses */
+use self::emulator::chip8;
+use self::emulator::debugiface as dbg;
+use self::emulator::font;
+use self::emulator::keyboard::Keyboard;
+use std::fs;
+use std::sync::mpsc;
+use std::path;
+use std::thread;
+
+/// Settings for `emulate_with` that most programs can leave at their defaults.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Where to write a crash dump if the program faults. If this is None, no dump is written.
+    pub crash_dump_path: Option<path::PathBuf>,
+    /// Where the GUI's save and load hotkeys put the save state. If this is None, the hotkeys do nothing.
+    pub state_path: Option<path::PathBuf>,
+    /// How many frames back the GUI's rewind hotkey can go. Zero turns rewinding off.
+    pub rewind_frames: usize,
+    /// The seed for RND's random number generator. If this is None, we pick one nobody can predict.
+    pub seed: Option<u64>,
+    /// Which algorithm RND uses.
+    pub rng_mode: RngMode,
+    /// Where to write a movie of the whole session when the emulator exits. If this is None, nothing is recorded.
+    pub record_path: Option<path::PathBuf>,
+    /// How many instructions to run in each 60 Hz frame. If this is None, we run at the default clock rate.
+    pub instructions_per_frame: Option<u64>,
+    /// Should each frame wait for its turn in real time? If not, we run as fast as we can.
+    pub throttle: bool,
+    /// Where the SCHIP's RPL user flags are kept between runs. If this is None, they are forgotten when we exit.
+    pub rpl_path: Option<path::PathBuf>,
+    /// Which machine to pretend to be when starting a program. Save states, crash dumps, and movies bring their own.
+    /// If this is None, we use whatever platform the program is obviously for (see `Platform::detect`), or plain Chip-8.
+    pub platform: Option<Platform>,
+    /// The choices about what instructions do that differ between platforms. If this is None, we use the ones
+    /// that go with `platform`.
+    pub quirks: Option<Quirks>,
+    /// Should the screen and stack be kept in RAM where the COSMAC VIP kept them, for programs that poke at them there?
+    pub vip_layout: bool,
+    /// Should SYS run the COSMAC VIP machine code routine it calls on a CDP1802, for hybrid programs?
+    pub cdp1802: bool,
+    /// How emulated time goes by. With COSMAC VIP timing, `instructions_per_frame` doesn't matter.
+    pub timing: Timing,
+    /// How many bytes of RAM the machine has. If this is None, it has as much as `platform` had.
+    pub memory_len: Option<usize>,
+    /// Where programs are loaded, like 0x600 for the ETI-660. If this is None, they go at 0x200.
+    pub load_address: Option<u16>,
+    /// Which built in font the hex sprites use.
+    pub font: FontSet,
+    /// A file holding a font to use instead of `font`: sixteen 5-byte glyphs for "0" through "F".
+    pub font_path: Option<path::PathBuf>,
+    /// Where the hex sprites' font goes in RAM, like the common 0x050. If this is None, it goes at 0.
+    pub font_address: Option<u16>,
+    /// What a BRK does when no debugger is attached.
+    pub break_policy: BreakPolicy,
+    /// Where to tell a debugger attached from the very start whenever the machine stops, as if it had sent Attach
+    /// before the first instruction. If this is None, a fault with no debugger attached ends the emulator thread.
+    pub debugger: Option<mpsc::Sender<dbg::EmulatorEvent>>,
+}
+
+/// What the emulator thread should start out running.
+enum Image {
+    /// A program binary to load at the start of the program space.
+    Program(Vec<u8>),
+    /// A crash dump to pick back up, halted on its fault.
+    CrashDump(CrashDump),
+    /// A save state to pick back up from.
+    SaveState(SaveState),
+    /// A movie to play back from its starting state.
+    Movie(Movie),
+}
+
+/// Everything `emulate` hands back: the emulator thread, the debug pipes to and from it, and the mock input pipe (if asked for).
+/// The thread ends with the fault that stopped the program, if nobody was debugging it.
+pub type Emulation = (thread::JoinHandle<Result<(), EmulatorError>>, mpsc::Sender<dbg::EmulatorCommand>, mpsc::Receiver<dbg::EmulatorResponse>, Option<mpsc::Sender<String>>);
+
+/// Creates an emulator thread and returns it along with the pipe to and from it.
+/// If we are testing, you should pass in true for fake_input, in which case we will also return
+/// a pipe to the emulator that will be used to read data from (instead of using the typical input mechanism).
+/// If `headless` is true, the emulator runs entirely in memory instead of opening a window.
+///
+/// Returns an error message if the program can't be read or doesn't fit in memory.
+pub fn emulate(progpath: &path::Path, fake_input: bool, headless: bool) -> Result<Emulation, String> {
+    emulate_with(progpath, fake_input, headless, Options::default())
+}
+
+/// Same as `emulate`, but with some extra `options`.
+pub fn emulate_with(progpath: &path::Path, fake_input: bool, headless: bool, options: Options) -> Result<Emulation, String> {
+    // Read the contents of the file into a buffer of bytes
+    let binary = match fs::read(progpath) {
+        Ok(b) => b,
+        Err(e) => return Err(format!("Problem reading file at location {}: {:?}", progpath.display(), e)),
+    };
+
+    spawn(Image::Program(binary), fake_input, headless, options)
+}
+
+/// Creates an emulator thread that picks up from the crash dump at `dumppath`, halted on whatever fault it was dumped on,
+/// so that a debugger attached with `options` can inspect it. Otherwise the same as `emulate_with`.
+pub fn postmortem(dumppath: &path::Path, headless: bool, options: Options) -> Result<Emulation, String> {
+    let dump = CrashDump::load(dumppath)?;
+    spawn(Image::CrashDump(dump), false, headless, options)
+}
+
+/// Creates an emulator thread that picks up from the save state at `statepath`. Otherwise the same as `emulate_with`.
+pub fn resume(statepath: &path::Path, fake_input: bool, headless: bool, options: Options) -> Result<Emulation, String> {
+    let state = SaveState::load(statepath)?;
+    spawn(Image::SaveState(state), fake_input, headless, options)
+}
+
+/// Creates an emulator thread that plays back the movie at `moviepath`, then carries on with the normal input
+/// once the movie is over. Otherwise the same as `emulate_with`.
+pub fn play(moviepath: &path::Path, headless: bool, options: Options) -> Result<Emulation, String> {
+    let movie = Movie::load(moviepath)?;
+    spawn(Image::Movie(movie), false, headless, options)
+}
+
+/// Does the work for `emulate_with`, `postmortem`, `resume`, and `play`.
+fn spawn(image: Image, fake_input: bool, headless: bool, options: Options) -> Result<Emulation, String> {
+
+    // Make some pipes. Use these for debugging and in the test rig.
+    let (mytx, yourrx): (mpsc::Sender<dbg::EmulatorCommand>, mpsc::Receiver<dbg::EmulatorCommand>) = mpsc::channel();
+    let (yourtx, myrx): (mpsc::Sender<dbg::EmulatorResponse>, mpsc::Receiver<dbg::EmulatorResponse>) = mpsc::channel();
+    let (mock_input_tx, mock_input_rx): (Option<mpsc::Sender<String>>, Option<mpsc::Receiver<String>>) = if fake_input {
+        let (a, b) = mpsc::channel();
+        (Some(a), Some(b))
+    } else {
+        (None, None)
+    };
+
+    // Programs that are obviously for some other platform get run on it, unless a platform was asked for
+    let platform = match (options.platform, &image) {
+        (Some(platform), _) => platform,
+        (None, Image::Program(binary)) => Platform::detect(binary).unwrap_or_default(),
+        (None, _) => Platform::default(),
+    };
+
+    // A font from a file has to be read before anything else, in case it isn't there
+    let glyphs = match options.font_path {
+        Some(ref p) => font::load(p)?,
+        None => options.font.glyphs().to_vec(),
+    };
+
+    // The emulator gets built on its own thread, so it tells us over this pipe whether the program loaded.
+    let (loadtx, loadrx) = mpsc::channel();
+
+    // Spawn an emulator. We can send it commands while it is running. Useful for debugging.
+    let emuthread = thread::spawn(move || {
+        // Pick the front end. The GUI has to be created on this thread, since this is where it gets driven from.
+        let mut backend = if headless { Backend::headless(Keypad::new()) } else { Backend::gui() };
+        if let Some(rx) = mock_input_rx {
+            backend.input = Box::new(Keyboard::new(Some(rx)));
+        }
+
+        // Create and initialize a Chip 8 instance
+        let mut emu = chip8::Chip8::new(yourtx, yourrx, backend);
+        emu.set_platform(platform);
+        emu.set_quirks(options.quirks.unwrap_or_else(|| Quirks::for_platform(platform)));
+        let layout = match options.memory_len {
+            Some(nbytes) => emu.set_memory_len(nbytes),
+            None => Ok(()),
+        };
+        let layout = layout
+            .and_then(|()| emu.set_font(&glyphs, options.font_address.unwrap_or(chip8::HEX_SPRITE_ZERO_ADDR)))
+            .and_then(|()| match options.load_address {
+                Some(addr) => emu.set_load_address(addr),
+                None => Ok(()),
+            })
+            .and_then(|()| emu.set_vip_layout(options.vip_layout))
+            .and_then(|()| emu.set_cdp1802(options.cdp1802));
+        emu.set_crash_dump_path(options.crash_dump_path);
+        emu.set_state_path(options.state_path);
+        emu.set_rewind_frames(options.rewind_frames);
+        emu.set_throttle(options.throttle);
+        emu.set_timing(options.timing);
+        emu.set_break_policy(options.break_policy);
+        if let Some(events) = options.debugger {
+            emu.attach_debugger(events);
+        }
+        emu.set_rpl_path(options.rpl_path);
+        if let Some(n) = options.instructions_per_frame {
+            emu.set_instructions_per_frame(n);
+        }
+        emu.set_rng(match options.seed {
+            Some(seed) => Rng::new(options.rng_mode, seed),
+            None => Rng::from_entropy(options.rng_mode),
+        });
+
+        // Load the program (or everything from the crash dump) into memory
+        let loaded = layout.and_then(|()| match image {
+            Image::Program(binary) => emu.load(&binary),
+            Image::CrashDump(dump) => emu.load_crash_dump(&dump),
+            Image::SaveState(state) => emu.load_state(&state),
+            Image::Movie(movie) => emu.play_movie(&movie),
+        });
+        let ok = loaded.is_ok();
+        loadtx.send(loaded).expect("Nobody is waiting to hear whether the binary loaded.");
+        if !ok {
+            return Ok(());
+        }
+
+        if options.record_path.is_some() {
+            emu.start_recording();
+        }
+
+        let ran = emu.run();
+
+        if let (Some(p), Some(movie)) = (options.record_path, emu.stop_recording()) {
+            match movie.save(&p) {
+                Ok(()) => println!("Recorded a movie to {}", p.display()),
+                Err(msg) => println!("{}", msg),
+            }
+        }
+        ran
+    });
+
+    match loadrx.recv() {
+        Ok(Ok(())) => Ok((emuthread, mytx, myrx, mock_input_tx)),
+        Ok(Err(s)) => Err(format!("Could not load image: {}", s)),
+        Err(_) => Err("The emulator thread died before it could load the binary.".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::dbg::EmulatorCommand;
+    use super::dbg::{EmulatorEvent, EmulatorResponse};
+    use std::time;
+
+    /// Handles getting the response from the RX pipe, dealing with timeouts and errors as appropriate.
+    fn get_response(rx: &mpsc::Receiver<EmulatorResponse>) -> EmulatorResponse {
+        match rx.recv_timeout(time::Duration::new(15, 0)) {
+            Err(_) => panic!("Could not receive anything from the emulator. Probably it never reached a BRK."),
+            Ok(response) => response,
+        }
+    }
+
+    /// Handles getting something the emulator told an attached debugger without being asked.
+    fn get_event(events: &mpsc::Receiver<EmulatorEvent>) -> EmulatorEvent {
+        match events.recv_timeout(time::Duration::new(15, 0)) {
+            Err(_) => panic!("The emulator never told the debugger anything."),
+            Ok(event) => event,
+        }
+    }
+
+    /// Waits for the emulator to stop (on a BRK, usually), and returns why it did.
+    fn wait_for_stop(tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) -> StopReason {
+        loop {
+            tx.send(EmulatorCommand::PeekStopped).expect("Could not send.");
+            match get_response(rx) {
+                EmulatorResponse::Stopped(Some(reason)) => return reason,
+                EmulatorResponse::Stopped(None) => thread::sleep(time::Duration::from_millis(1)),
+                response => panic!("Response {:?} makes no sense...", response),
+            }
+        }
+    }
+
+    /// Waits for the emulator to stop, then sends the given `msg`, waits to hear back, and returns the response.
+    /// The emulator answers while it is running too, but the tests want to look at it where it stopped.
+    fn send_and_receive(msg: EmulatorCommand, tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) -> EmulatorResponse {
+        wait_for_stop(tx, rx);
+        tx.send(msg).expect("Could not send.");
+        get_response(rx)
+    }
+
+    /// Waits for the emulator to stop, then tells it to carry on.
+    fn resume(tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) {
+        wait_for_stop(tx, rx);
+        tx.send(EmulatorCommand::ResumeExecution).expect("Could not send resume command");
+    }
+
+    /// Sends the exit command and then joins with the emulator thread.
+    fn exit_and_join(emu: thread::JoinHandle<Result<(), EmulatorError>>, tx: &mpsc::Sender<EmulatorCommand>) {
+        tx.send(EmulatorCommand::Exit).expect("Could not send exit signal.");
+        emu.join().ok();
+    }
+
+    /// Asserts that the PC is at the given location.
+    fn assert_pc(pc: u16, tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) {
+        match send_and_receive(EmulatorCommand::PeekPC, tx, rx) {
+            EmulatorResponse::PC(received_pc) => assert_eq!(received_pc, pc),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+    }
+
+    /// Asserts that the stack item at `stackidx` is equal to `stackitem`.
+    fn assert_stack_item(stackidx: usize, stackitem: u16, tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) {
+        match send_and_receive(EmulatorCommand::PeekStack, tx, rx) {
+            EmulatorResponse::Stack(stack) => assert_eq!(stack[stackidx], stackitem),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+    }
+
+    /// Asserts that the stack pointer is at the given location.
+    fn assert_sp(sp: u8, tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) {
+        match send_and_receive(EmulatorCommand::PeekSP, tx, rx) {
+            EmulatorResponse::SP(received_sp) => assert_eq!(received_sp, sp),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+    }
+
+    /// Asserts that the contents of the given register are equal to the given contents.
+    fn assert_register(regidx: u8, regval: u8, tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) {
+        match send_and_receive(EmulatorCommand::PeekReg(regidx), tx, rx) {
+            EmulatorResponse::Reg(received_regval) => assert_eq!(received_regval, regval),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+    }
+
+    /// Asserts that the contents of register I are equal to the given `regval`.
+    fn assert_iregister(regval: u32, tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) {
+        match send_and_receive(EmulatorCommand::PeekI, tx, rx) {
+            EmulatorResponse::I(received_regval) => assert_eq!(received_regval, regval),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+    }
+
+    /// Asserts that the sound timer is equal to `timer_value`.
+    fn assert_sound_timer(timer_value: u8, tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) {
+        match send_and_receive(EmulatorCommand::PeekSoundTimer, tx, rx) {
+            EmulatorResponse::SoundTimer(received_value) => assert_eq!(received_value, timer_value),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+    }
+
+    /// Asserts that the slice of memory at `address` is equal to `values`, byte-wise.
+    fn assert_memory(address: u16, values: &[u8], tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) {
+        match send_and_receive(EmulatorCommand::PeekAddr(address, values.len()), tx, rx) {
+            EmulatorResponse::MemorySlice(bytes) => {
+                for (a, b) in bytes.iter().zip(values) {
+                    assert_eq!(a, b);
+                }
+            },
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+    }
+
+    /// Creates a headless emulator with the given program loaded, to be driven with `step`, `run_for`, and `run_until`.
+    fn embed(progpath: &str, keypad: Keypad) -> chip8::Chip8 {
+        let binary = fs::read(progpath).expect("Could not read test program");
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(keypad));
+        emu.load(&binary).expect("Could not load test program");
+        emu
+    }
+
+    /// SYS is a NOP, so really just test that nothing breaks.
+    #[test]
+    fn test_sys() {
+        let (emu, tx, _rx, _mockinput) = emulate(path::Path::new("testprograms/SYS/systest.bin"), false, true).expect("Could not start emulator");
+        exit_and_join(emu, &tx);
+    }
+
+    /// CLS is not really testable from this test harness - requires manual oversight. Included here to make sure it doesn't break things.
+    #[test]
+    fn test_cls() {
+        let (emu, tx, _rx, _mockinput) = emulate(path::Path::new("testprograms/CLS/clstest.bin"), false, true).expect("Could not start emulator");
+        exit_and_join(emu, &tx);
+    }
+
+    /// RET test. Go to a subroutine then return from it and make sure we break at the right place.
+    #[test]
+    fn test_ret() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/RET/rettest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that PC is at correct location
+        assert_pc(0x0202, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// JP test. Jump to a specific address and break. Check PC.
+    #[test]
+    fn test_jp() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/JP/jptest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that PC is at correct location
+        assert_pc(0x020A, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// CALL test. Jump to an address and break. Check PC and stack.
+    #[test]
+    fn test_call() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/CALL/calltest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that PC is at correct location
+        assert_pc(0x020A, &tx, &rx);
+
+        // Check that the first item in the stack is correct.
+        assert_stack_item(0, 0x0204, &tx, &rx);
+
+        // Check that the stack pointer is correct
+        assert_sp(1, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test that the SEVxByte instruction works by loading a value into a register, then comparing a byte with that register
+    /// and seeing if we break at the appropriate place.
+    #[test]
+    fn test_sevxbyte() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/SEVxByte/sevxbytetest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that the PC is at the correct location
+        assert_pc(0x020C, &tx, &rx);
+
+        // Check that register V3 has the expected value
+        assert_register(3, 0x23, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test that the SNEVxByte instruction works by loading a value into a register, then comparing a byte with that register
+    /// and seeing if we break at the appropriate place.
+    #[test]
+    fn test_snevxbyte() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/SNEVxByte/snevxbytetest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that the PC is at the correct location
+        assert_pc(0x020C, &tx, &rx);
+
+        // Check that register V3 has the expected value
+        assert_register(3, 0x25, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test that the SEVxVy instruction works by loading a value into two different registers and comparing them
+    /// and then checking if we break at the right place.
+    #[test]
+    fn test_sevxvy() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/SEVxVy/sevxvytest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that the PC is at the correct location
+        assert_pc(0x020C, &tx, &rx);
+
+        // Check that register V3 has the expected value
+        assert_register(3, 0x25, &tx, &rx);
+
+        // Check that register V4 has the expected value
+        assert_register(4, 0x25, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test LDVxByte instruction by loading each general purpose register with a known value and checking them.
+    #[test]
+    fn test_ldvxybyte() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/LDVxByte/ldvxbytetest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that the PC is where we expect
+        assert_pc(0x021E, &tx, &rx);
+
+        /* Now check all the registers */
+        assert_register(0, 0x25, &tx, &rx);
+        assert_register(1, 0x0A, &tx, &rx);
+        assert_register(2, 0xCC, &tx, &rx);
+        assert_register(3, 0xFF, &tx, &rx);
+        assert_register(4, 0x10, &tx, &rx);
+        assert_register(5, 0x11, &tx, &rx);
+        assert_register(6, 0x22, &tx, &rx);
+        assert_register(7, 0x23, &tx, &rx);
+        assert_register(8, 0x85, &tx, &rx);
+        assert_register(9, 0x09, &tx, &rx);
+        assert_register(10, 0xAE, &tx, &rx);
+        assert_register(11, 0x0E, &tx, &rx);
+        assert_register(12, 0x44, &tx, &rx);
+        assert_register(13, 0x35, &tx, &rx);
+        assert_register(14, 0x15, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test ADDVxByte instruction.
+    #[test]
+    fn test_addvxbyte() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/ADDVxByte/addvxbytetest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that the register is what we expect it should be
+        assert_register(10, 0x67, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test LDVxVy instruction.
+    #[test]
+    fn test_ldvxvy() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/LDVxVy/ldvxvytest.bin"), false, true).expect("Could not start emulator");
+
+        // Check register VA
+        assert_register(10, 0x02, &tx, &rx);
+
+        // Check register VD
+        assert_register(10, 0x02, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test the ORVxVy instruction.
+    #[test]
+    fn test_orvxvy() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/ORVxVy/orvxvytest.bin"), false, true).expect("Could not start emulator");
+
+        // Check register VA
+        assert_register(10, 0x0E | 0x03, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test the ANDVxVy instruction.
+    #[test]
+    fn test_andvxvy() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/ANDVxVy/andvxvytest.bin"), false, true).expect("Could not start emulator");
+
+        // Check register VA
+        assert_register(10, 0x0E & 0x03, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test the XORVxVy instruction.
+    #[test]
+    fn test_xorvxvy() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/XORVxVy/xorvxvytest.bin"), false, true).expect("Could not start emulator");
+
+        // Check register VA
+        assert_register(10, 0x0E ^ 0x03, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test ADDVxVy with carry bit and without.
+    #[test]
+    fn test_addvxvy() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/ADDVxVy/addvxvytest.bin"), false, true).expect("Could not start emulator");
+
+        // Check register VA
+        assert_register(10, 0x11, &tx, &rx);
+
+        // Check no carry in VF
+        assert_register(15, 0x00, &tx, &rx);
+
+        // Continue to next break point
+        resume(&tx, &rx);
+
+        // Check register VB
+        assert_register(11, 0xE7, &tx, &rx);
+
+        // Check carry in VF
+        assert_register(15, 0x01, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test SUBVxVy with borrow/no-borrow.
+    #[test]
+    fn test_subvxvy() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/SUBVxVy/subvxvytest.bin"), false, true).expect("Could not start emulator");
+
+        // Check register VA
+        assert_register(10, 0x0B, &tx, &rx);
+
+        // Check no borrow in VF
+        assert_register(15, 0x01, &tx, &rx);
+
+        // Continue to next break point
+        resume(&tx, &rx);
+
+        // Check register VB
+        assert_register(11, 0xDD, &tx, &rx);
+
+        // Check borrow in VF
+        assert_register(15, 0x00, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test SHRVx with LSB/no LSB
+    #[test]
+    fn test_shrvx() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/SHRVx/shrvxtest.bin"), false, true).expect("Could not start emulator");
+
+        // Check register VA
+        assert_register(10, 0x07, &tx, &rx);
+
+        // Check VF
+        assert_register(15, 0x00, &tx, &rx);
+
+        // Continue to next break point
+        resume(&tx, &rx);
+
+        // Check register VB
+        assert_register(11, 0x7E, &tx, &rx);
+
+        // Check VF
+        assert_register(15, 0x01, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test SUBVNxVy with borrow/no-borrow.
+    #[test]
+    fn test_subnvxvy() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/SUBNVxVy/subnvxvytest.bin"), false, true).expect("Could not start emulator");
+
+        // Check register VA
+        assert_register(10, 0x0B, &tx, &rx);
+
+        // Check no borrow in VF
+        assert_register(15, 0x00, &tx, &rx);
+
+        // Continue to next break point
+        resume(&tx, &rx);
+
+        // Check register VB
+        assert_register(11, 0xDD, &tx, &rx);
+
+        // Check borrow in VF
+        assert_register(15, 0x01, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test SHLVx with LSB/no LSB
+    #[test]
+    fn test_shlvx() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/SHLVx/shlvxtest.bin"), false, true).expect("Could not start emulator");
+
+        // Check register VA
+        assert_register(10, 0x1C, &tx, &rx);
+
+        // Check VF
+        assert_register(15, 0x00, &tx, &rx);
+
+        // Continue to next break point
+        resume(&tx, &rx);
+
+        // Check register VB
+        assert_register(11, 0xFA, &tx, &rx);
+
+        // Check VF
+        assert_register(15, 0x01, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test that the SNEVxVy instruction works by loading a value into two different registers and comparing them
+    /// and then checking if we break at the right place.
+    #[test]
+    fn test_snevxvy() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/SNEVxVy/snevxvytest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that the PC is at the correct location
+        assert_pc(0x020C, &tx, &rx);
+
+        // Check that register V3 has the expected value
+        assert_register(3, 0x25, &tx, &rx);
+
+        // Check that register V4 has the expected value
+        assert_register(4, 0x26, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test LDIAddr by loading a byte into I and checking it.
+    #[test]
+    fn test_ldiaddr() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/LDIAddr/ldiaddrtest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that register I has the right value.
+        assert_iregister(0x021E, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test JPV0Addr instruction by loading a value into v0, jumping, and seeing if the PC is in the right place.
+    #[test]
+    fn test_jpv0addr() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/JPV0Addr/jpv0addrtest.bin"), false, true).expect("Could not start emulator");
+
+        // Check that the PC is at the right place.
+        assert_pc(0x020C, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test RNDVxByte instruction by getting ten random numbers and making sure they aren't all the same.
+    #[test]
+    fn test_rndvxbyte() {
+        let options = Options { seed: Some(8), ..Options::default() };
+        let (emu, tx, rx, _mockinput) = emulate_with(path::Path::new("testprograms/RNDVxByte/rndvxbytetest.bin"), false, true, options).expect("Could not start emulator");
+
+        /* Collect ten random bytes */
+        let mut randombytes = Vec::<u8>::new();
+        for i in 0..10 {
+            match send_and_receive(EmulatorCommand::PeekReg(i), &tx, &rx) {
+                EmulatorResponse::Reg(b) => randombytes.push(b),
+                response => panic!("Response {:?} makes no sense...", response),
+            }
+        }
+
+        assert!(!randombytes.is_empty());
+
+        /* Make sure they aren't all the same - with this seed, they aren't */
+        let val = randombytes[0];
+        let mut all_the_same = true;
+        for other in randombytes {
+            if other != val {
+                all_the_same = false;
+                break;
+            }
+        }
+
+        assert!(!all_the_same);
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test DRWVxVyNibble instruction.
+    #[test]
+    fn test_drwvxvynibble() {
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/DRWVxVyNibble/drwvxvynibbletest.bin"), false, true).expect("Could not start emulator");
+
+        // Let program draw some sprites, then check VF for collision
+        assert_register(15, 0, &tx, &rx);
+
+        // Continue
+        resume(&tx, &rx);
+
+        // Do it again
+        assert_register(15, 1, &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test SKPVx instruction by using the mock input pipe to pretend to be a user pushing on the keyboard.
+    #[test]
+    fn test_skpvx() {
+        let (emu, tx, rx, mk) = emulate(path::Path::new("testprograms/SKPVx/skpvxtest.bin"), true, true).expect("Could not start emulator");
+        let mockinput = mk.unwrap();
+
+        // Send an input sequence that contains the character we are interested in
+        mockinput.send("asd".to_string()).expect("Could not send");
+
+        // Now tell the program to continue executing
+        resume(&tx, &rx);
+
+        // Check that the program counter is where we expect
+        assert_pc(0x020C, &tx, &rx);
+
+        // Send an input sequence that does not contain the character we are interested in
+        mockinput.send("qwe".to_string()).expect("Could not send second thing");
+
+        // Continue again
+        resume(&tx, &rx);
+
+        // Check that the program counter is where we expect
+        assert_pc(0x0210, &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test SKNPVx instruction by using the mock input pipe to pretend to be a user pushing on the keyboard.
+    #[test]
+    fn test_sknpvx() {
+        let (emu, tx, rx, mk) = emulate(path::Path::new("testprograms/SKNPVx/sknpvxtest.bin"), true, true).expect("Could not start emulator");
+        let mockinput = mk.unwrap();
+
+        // Send an input sequence that does NOT contain the character we are interested in
+        mockinput.send("qwe".to_string()).expect("Could not send");
+
+        // Now tell the program to continue executing
+        resume(&tx, &rx);
+
+        // Check that the program counter is where we expect
+        assert_pc(0x020C, &tx, &rx);
+
+        // Send an input sequence that DOES contain the character we are interested in
+        mockinput.send("asd".to_string()).expect("Could not send second thing");
+
+        // Continue again
+        resume(&tx, &rx);
+
+        // Check that the program counter is where we expect
+        assert_pc(0x0210, &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test LDVxDT instruction.
+    #[test]
+    fn test_ldvxdt() {
+        let (emu, tx, rx, _) = emulate(path::Path::new("testprograms/LDVxDT/ldvxdttest.bin"), false, true).expect("Could not start emulator");
+
+        // Set the emulator's clock rate while it waits around
+        wait_for_stop(&tx, &rx);
+        tx.send(EmulatorCommand::SetClockRate(60)).expect("Could not set clock rate");
+
+        // Continue now that the CPU is the right Hz
+        resume(&tx, &rx);
+
+        // Now let the emulator execute a known number of cycles, then make sure its delay timer is at a known value.
+        assert_register(5, 0x1E, &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test the LDVxK instruction.
+    #[test]
+    fn test_ldvxk() {
+        let (emu, tx, rx, mk) = emulate(path::Path::new("testprograms/LDVxK/ldvxktest.bin"), true, true).expect("Could not start emulator");
+        let mockinput = mk.unwrap();
+
+        // Send a key through the test interface
+        mockinput.send("s".to_string()).expect("Could not send over mockinput");
+
+        // Check that the register contains 's'
+        assert_register(2, 0x08, &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test LDSTVx instruction.
+    #[test]
+    fn test_ldstvx() {
+        let (emu, tx, rx, _) = emulate(path::Path::new("testprograms/LDSTVx/ldstvxtest.bin"), false, true).expect("Could not start emulator");
+
+        // Set the emulator's clock rate while it waits around
+        wait_for_stop(&tx, &rx);
+        tx.send(EmulatorCommand::SetClockRate(60)).expect("Could not set clock rate");
+
+        // Continue now that the CPU is the right Hz
+        resume(&tx, &rx);
+
+        // Now let the emulator execute a known number of cycles, then make sure its sound timer is at a known value.
+        assert_sound_timer(0x1E, &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test ADDIVx instruction.
+    #[test]
+    fn test_addivx() {
+        let (emu, tx, rx, _) = emulate(path::Path::new("testprograms/ADDIVx/addivxtest.bin"), false, true).expect("Could not start emulator");
+
+        // Assert that the I register is what we expect
+        assert_iregister(0x20E, &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test LDFVx instruction.
+    #[test]
+    fn test_ldfvx() {
+        let (emu, tx, rx, _) = emulate(path::Path::new("testprograms/LDFVx/ldfvxtest.bin"), false, true).expect("Could not start emulator");
+
+        // Assert that the I register is the right value for each possible value.
+        assert_iregister(chip8::HEX_SPRITE_ZERO_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_ONE_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_TWO_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_THREE_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_FOUR_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_FIVE_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_SIX_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_SEVEN_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_EIGHT_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_NINE_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_A_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_B_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_C_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_D_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_E_ADDR as u32, &tx, &rx);
+        resume(&tx, &rx);
+
+        assert_iregister(chip8::HEX_SPRITE_F_ADDR as u32, &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test LDBVx instruction.
+    #[test]
+    fn test_ldbvx() {
+        let (emu, tx, rx, _) = emulate(path::Path::new("testprograms/LDBVx/ldbvxtest.bin"), false, true).expect("Could not start emulator");
+
+        // Assert the memory at 0x0321 is what we expect
+        // Assert the memory at 0x0322 is what we expect
+        // Assert the memory at 0x0323 is what we expect
+        assert_memory(0x0321, &[2, 1, 7], &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test LDIVx and LDVxI instructions.
+    #[test]
+    fn test_ldivx_and_ldvxi() {
+        let (emu, tx, rx, _) = emulate(path::Path::new("testprograms/LDI/lditest.bin"), false, true).expect("Could not start emulator");
+
+        // Test that a register currently holds only zeros
+        assert_register(3, 0x00, &tx, &rx);
+
+        // Continue
+        resume(&tx, &rx);
+
+        // Test that the memory is correct and that the registers have the values from memory
+        assert_memory(0x334, &[0x04, 0x07, 0x11, 0xAA, 0xBC, 0x00, 0x97], &tx, &rx);
+        assert_register(0, 0x04, &tx, &rx);
+        assert_register(1, 0x07, &tx, &rx);
+        assert_register(2, 0x11, &tx, &rx);
+        assert_register(3, 0xAA, &tx, &rx);
+        assert_register(4, 0xBC, &tx, &rx);
+        assert_register(5, 0x00, &tx, &rx);
+        assert_register(6, 0x97, &tx, &rx);
+
+        // Quit
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test that step executes a single instruction at a time.
+    #[test]
+    fn test_step() {
+        let mut emu = embed("testprograms/SYS/systest.bin", Keypad::new());
+
+        assert_eq!(emu.step(), StopReason::BudgetExhausted);
+        assert_eq!(emu.pc(), 0x0202);
+        assert_eq!(emu.instruction_count(), 1);
+
+        assert_eq!(emu.step(), StopReason::BudgetExhausted);
+        assert_eq!(emu.pc(), 0x0204);
+
+        // Now we are on the BRK, which should stop us without moving the PC, then let us past it on the next step.
+        assert_eq!(emu.step(), StopReason::Breakpoint);
+        assert_eq!(emu.pc(), 0x0204);
+        assert_eq!(emu.step(), StopReason::BudgetExhausted);
+        assert_eq!(emu.pc(), 0x0206);
+    }
+
+    /// Test that run_for stops at breakpoints and when it runs out of budget.
+    #[test]
+    fn test_run_for() {
+        let mut emu = embed("testprograms/ADDVxVy/addvxvytest.bin", Keypad::new());
+
+        assert_eq!(emu.run_for(Budget::Cycles(1000)), StopReason::Breakpoint);
+        assert_eq!(emu.register(10), Some(0x11));
+        assert_eq!(emu.register(15), Some(0x00));
+
+        assert_eq!(emu.run_for(Budget::Frames(10)), StopReason::Breakpoint);
+        assert_eq!(emu.register(11), Some(0xE7));
+        assert_eq!(emu.register(15), Some(0x01));
+
+        // Two instructions is not enough to get through the whole program
+        let mut emu = embed("testprograms/ADDVxVy/addvxvytest.bin", Keypad::new());
+        assert_eq!(emu.run_for(Budget::Cycles(2)), StopReason::BudgetExhausted);
+        assert_eq!(emu.pc(), 0x0204);
+    }
+
+    /// Test that run_until stops as soon as its condition is true.
+    #[test]
+    fn test_run_until() {
+        let mut emu = embed("testprograms/CALL/calltest.bin", Keypad::new());
+
+        assert_eq!(emu.run_until(|e| e.sp() == 1), StopReason::ConditionMet);
+        assert_eq!(emu.stack()[0], 0x0204);
+        assert_eq!(emu.pc(), 0x020A);
+    }
+
+    /// Test that LDVxK stops us until a key shows up on the keypad.
+    #[test]
+    fn test_wait_for_key() {
+        let keypad = Keypad::new();
+        let mut emu = embed("testprograms/LDVxK/ldvxktest.bin", keypad.clone());
+
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::WaitingForKey);
+        assert!(emu.is_awaiting_key());
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::WaitingForKey);
+        assert_eq!(emu.pc(), 0x0200);
+
+        keypad.press(0x08);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert!(!emu.is_awaiting_key());
+        assert_eq!(emu.register(2), Some(0x08));
+    }
+
+    /// Test that an instruction that does not exist faults without moving the PC.
+    #[test]
+    fn test_fault() {
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.load(&[0x60, 0x01, 0x80, 0x0F]).expect("Could not load program");
+
+        let expected = EmulatorError { kind: ErrorKind::InvalidOpcode, pc: 0x0202, instruction: 0x800F };
+        assert_eq!(emu.run_for(Budget::Cycles(10)), StopReason::Fault(expected));
+        assert_eq!(emu.pc(), 0x0202);
+        assert_eq!(emu.register(0), Some(0x01));
+
+        // We should stay halted on the bad instruction
+        assert_eq!(emu.step(), StopReason::Fault(expected));
+        assert_eq!(emu.pc(), 0x0202);
+        assert_eq!(emu.fault(), Some(&expected));
+    }
+
+    /// Test that a faulted emulator thread stays alive and can still be inspected over the debug interface.
+    #[test]
+    fn test_fault_halts() {
+        let (eventtx, eventrx) = mpsc::channel();
+        let options = Options { debugger: Some(eventtx), ..Options::default() };
+        let (emu, tx, rx, _mockinput) = emulate_with(path::Path::new("testprograms/FAULT/faulttest.bin"), false, true, options).expect("Could not start emulator");
+        match get_event(&eventrx) {
+            EmulatorEvent::Stopped(StopReason::Fault(err)) => assert_eq!(err.kind, ErrorKind::StackUnderflow),
+            event => panic!("Event {:?} makes no sense...", event),
+        }
+
+        match send_and_receive(EmulatorCommand::PeekFault, &tx, &rx) {
+            EmulatorResponse::Fault(Some(err)) => {
+                assert_eq!(err.kind, ErrorKind::StackUnderflow);
+                assert_eq!(err.pc, 0x0202);
+                assert_eq!(err.instruction, 0x00EE);
+            },
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        assert_pc(0x0202, &tx, &rx);
+        assert_register(0, 0x01, &tx, &rx);
+
+        // Carrying on just halts on the same fault again, and the debugger doesn't hear about it twice
+        resume(&tx, &rx);
+        assert!(matches!(wait_for_stop(&tx, &rx), StopReason::Fault(err) if err.kind == ErrorKind::StackUnderflow));
+        assert!(eventrx.recv_timeout(time::Duration::from_millis(100)).is_err());
+
+        exit_and_join(emu, &tx);
+    }
+
+    /// Test that a fault with no debugger attached ends the emulator thread with that fault, even though the debug
+    /// pipes are still there.
+    #[test]
+    fn test_fault_ends_thread() {
+        let (emu, _tx, _rx, _mockinput) = emulate(path::Path::new("testprograms/FAULT/faulttest.bin"), false, true).expect("Could not start emulator");
+        match emu.join().expect("Did not join emu thread correctly.") {
+            Err(err) => {
+                assert_eq!(err.kind, ErrorKind::StackUnderflow);
+                assert_eq!(err.pc, 0x0202);
+            },
+            Ok(()) => panic!("The program faulted, but the emulator thread ended fine."),
+        }
+    }
+
+    /// Test that peeks that can't be answered say so, and that a debugger that stops listening lets the machine go.
+    #[test]
+    fn test_bad_peeks() {
+        // BRK then JP 0x202, forever
+        let progpath = std::env::temp_dir().join("mychip8_test_bad_peeks.bin");
+        fs::write(&progpath, [0x00, 0xA0, 0x12, 0x02]).expect("Could not write program");
+        let (emu, tx, rx, _mockinput) = emulate(&progpath, false, true).expect("Could not start emulator");
+
+        assert_ack(EmulatorCommand::PeekAddr(0x0FFE, 4), false, &tx, &rx);
+        assert_ack(EmulatorCommand::PeekReg(16), false, &tx, &rx);
+        assert_pc(0x0200, &tx, &rx);
+
+        // Nobody hears the answer, so the machine carries on past the BRK
+        drop(rx);
+        tx.send(EmulatorCommand::PeekPC).expect("Could not send.");
+        exit_and_join(emu, &tx);
+        fs::remove_file(&progpath).unwrap_or(());
+    }
+
+    /// Test that a program with no BRK in it can be looked at, paused, and exited while it runs.
+    #[test]
+    fn test_pause() {
+        // ADD V0, 1 then JP 0x200, forever
+        let progpath = std::env::temp_dir().join("mychip8_test_pause.bin");
+        fs::write(&progpath, [0x70, 0x01, 0x12, 0x00]).expect("Could not write program");
+        let (emu, tx, rx, _mockinput) = emulate(&progpath, false, true).expect("Could not start emulator");
+
+        // It never stops on its own, but still answers
+        tx.send(EmulatorCommand::PeekStopped).expect("Could not send.");
+        match get_response(&rx) {
+            EmulatorResponse::Stopped(None) => (),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        tx.send(EmulatorCommand::PeekPC).expect("Could not send.");
+        match get_response(&rx) {
+            EmulatorResponse::PC(pc) => assert!(pc == 0x0200 || pc == 0x0202),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+
+        // Once paused, it stays put
+        tx.send(EmulatorCommand::Pause).expect("Could not send.");
+        match get_response(&rx) {
+            EmulatorResponse::Ack(Ok(())) => (),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        assert_eq!(wait_for_stop(&tx, &rx), StopReason::Paused);
+        let count = match send_and_receive(EmulatorCommand::PeekReg(0), &tx, &rx) {
+            EmulatorResponse::Reg(v) => v,
+            response => panic!("Response {:?} makes no sense...", response),
+        };
+        thread::sleep(time::Duration::from_millis(20));
+        assert_register(0, count, &tx, &rx);
+
+        // Then carries on, and can be exited without ever hitting a BRK
+        tx.send(EmulatorCommand::ResumeExecution).expect("Could not send.");
+        tx.send(EmulatorCommand::PeekStopped).expect("Could not send.");
+        match get_response(&rx) {
+            EmulatorResponse::Stopped(None) => (),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        exit_and_join(emu, &tx);
+        fs::remove_file(&progpath).unwrap_or(());
+    }
+
+    /// Test what BRKs do with and without a debugger attached.
+    #[test]
+    fn test_attach() {
+        assert_eq!("log".parse::<BreakPolicy>(), Ok(BreakPolicy::Log));
+        assert!("explode".parse::<BreakPolicy>().is_err());
+
+        // BRK then LD V0, 5 then BRK then JP 0x206, forever
+        let progpath = std::env::temp_dir().join("mychip8_test_attach.bin");
+        fs::write(&progpath, [0x00, 0xA0, 0x60, 0x05, 0x00, 0xA0, 0x12, 0x06]).expect("Could not write program");
+
+        // Ignored BRKs don't stop anything
+        let options = Options { break_policy: BreakPolicy::Ignore, ..Options::default() };
+        let (emu, tx, rx, _mockinput) = emulate_with(&progpath, false, true, options).expect("Could not start emulator");
+        let mut v0 = 0;
+        for _ in 0..1000 {
+            tx.send(EmulatorCommand::PeekReg(0)).expect("Could not send.");
+            match get_response(&rx) {
+                EmulatorResponse::Reg(v) => v0 = v,
+                response => panic!("Response {:?} makes no sense...", response),
+            }
+            if v0 == 5 {
+                break;
+            }
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        assert_eq!(v0, 5);
+        exit_and_join(emu, &tx);
+
+        // An attached debugger hears about every stop, even one that happened before it attached
+        let (emu, tx, rx, _mockinput) = emulate(&progpath, false, true).expect("Could not start emulator");
+        let (eventtx, eventrx) = mpsc::channel();
+        match send_and_receive(EmulatorCommand::Attach(eventtx), &tx, &rx) {
+            EmulatorResponse::Ack(Ok(())) => (),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        assert_eq!(get_event(&eventrx), EmulatorEvent::Stopped(StopReason::Breakpoint));
+        assert_pc(0x0200, &tx, &rx);
+
+        // Stops never show up among the answers, even when they happen while we are asking
+        tx.send(EmulatorCommand::ResumeExecution).expect("Could not send.");
+        tx.send(EmulatorCommand::PeekPC).expect("Could not send.");
+        match get_response(&rx) {
+            EmulatorResponse::PC(_) => (),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        assert_eq!(get_event(&eventrx), EmulatorEvent::Stopped(StopReason::Breakpoint));
+        assert_pc(0x0204, &tx, &rx);
+
+        // Detaching lets it go
+        tx.send(EmulatorCommand::Detach).expect("Could not send.");
+        match get_response(&rx) {
+            EmulatorResponse::Ack(Ok(())) => (),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        tx.send(EmulatorCommand::PeekStopped).expect("Could not send.");
+        match get_response(&rx) {
+            EmulatorResponse::Stopped(None) => (),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        exit_and_join(emu, &tx);
+        fs::remove_file(&progpath).unwrap_or(());
+    }
+
+    /// Sends the given `msg`, which changes something, and checks whether that worked.
+    fn assert_ack(msg: EmulatorCommand, ok: bool, tx: &mpsc::Sender<EmulatorCommand>, rx: &mpsc::Receiver<EmulatorResponse>) {
+        match send_and_receive(msg, tx, rx) {
+            EmulatorResponse::Ack(result) => assert_eq!(result.is_ok(), ok, "{:?}", result),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+    }
+
+    /// Test setting up the machine over the debug interface instead of in the program.
+    #[test]
+    fn test_poke() {
+        // Anything put in I is wrapped to the address space, so ADD I, Vx can't overflow it afterwards
+        let mut emu = machine_with(&[0x60, 0xFF, 0xF0, 0x1E, 0x00, 0xA0]); // LD V0, 0xFF then ADD I, V0 then BRK
+        emu.set_index(0xFFFF_FFFF);
+        assert_eq!(emu.index(), 0xFFFF);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.index(), 0x00FE);
+
+        // BRK then LD V2, DT then DRW V0, V1, 5 then BRK
+        let progpath = std::env::temp_dir().join("mychip8_test_poke.bin");
+        fs::write(&progpath, [0x00, 0xA0, 0xF2, 0x07, 0xD0, 0x15, 0x00, 0xA0]).expect("Could not write program");
+        let (emu, tx, rx, _mockinput) = emulate(&progpath, false, true).expect("Could not start emulator");
+
+        // Put a sprite at 0x300 and point everything at it
+        assert_ack(EmulatorCommand::PokeAddr(0x0300, vec![0xF0, 0x90, 0x90, 0x90, 0xF0]), true, &tx, &rx);
+        assert_ack(EmulatorCommand::SetI(0x0300), true, &tx, &rx);
+        assert_ack(EmulatorCommand::PokeReg(0, 8), true, &tx, &rx);
+        assert_ack(EmulatorCommand::PokeReg(1, 4), true, &tx, &rx);
+        assert_ack(EmulatorCommand::SetDelayTimer(0x40), true, &tx, &rx);
+        match send_and_receive(EmulatorCommand::PeekDelayTimer, &tx, &rx) {
+            EmulatorResponse::DelayTimer(value) => assert_eq!(value, 0x40),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        resume(&tx, &rx);
+
+        assert_pc(0x0206, &tx, &rx);
+        assert_register(2, 0x40, &tx, &rx);
+        assert_register(15, 0, &tx, &rx);
+        match send_and_receive(EmulatorCommand::PeekFramebuffer, &tx, &rx) {
+            EmulatorResponse::Framebuffer(pixels) => {
+                // Low resolution pixels are two screen pixels across
+                assert_eq!(pixels.get_pixel_at(16, 8).value, Pxcolor::Black);
+                assert_eq!(pixels.get_pixel_at(18, 10).value, Pxcolor::White);
+            },
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+
+        // Wipe the screen and draw the sprite again. With nothing there, nothing collides.
+        assert_ack(EmulatorCommand::SetFramebuffer(PixelGrid::new(64, 128)), true, &tx, &rx);
+        assert_ack(EmulatorCommand::SetPC(0x0204), true, &tx, &rx);
+        resume(&tx, &rx);
+        assert_pc(0x0206, &tx, &rx);
+        assert_register(15, 0, &tx, &rx);
+
+        // Single pixels can be set too
+        assert_ack(EmulatorCommand::PokePixel(0, 0, Pxcolor::Black), true, &tx, &rx);
+        match send_and_receive(EmulatorCommand::PeekFramebuffer, &tx, &rx) {
+            EmulatorResponse::Framebuffer(pixels) => assert_eq!(pixels.get_pixel_at(0, 0).value, Pxcolor::Black),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+
+        // The stack can be rearranged too
+        assert_ack(EmulatorCommand::PokeStack(0, 0x0208), true, &tx, &rx);
+        assert_ack(EmulatorCommand::SetSP(1), true, &tx, &rx);
+        assert_stack_item(0, 0x0208, &tx, &rx);
+        assert_sp(1, &tx, &rx);
+        assert_ack(EmulatorCommand::SetKeys(Some(0x0001)), true, &tx, &rx);
+
+        // But not in ways that don't fit
+        assert_ack(EmulatorCommand::SetSP(17), false, &tx, &rx);
+        assert_ack(EmulatorCommand::PokeStack(16, 0x0200), false, &tx, &rx);
+        assert_ack(EmulatorCommand::PokeReg(16, 0), false, &tx, &rx);
+        assert_ack(EmulatorCommand::PokeAddr(0x0FFF, vec![1, 2]), false, &tx, &rx);
+        assert_ack(EmulatorCommand::PokePixel(128, 0, Pxcolor::Black), false, &tx, &rx);
+        assert_ack(EmulatorCommand::SetFramebuffer(PixelGrid::new(32, 64)), false, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+        fs::remove_file(&progpath).unwrap_or(());
+    }
+
+    /// Test holding keys down from outside, in place of the input backend.
+    #[test]
+    fn test_set_keys() {
+        // SKP V5 then BRK then BRK, then LD V0, K
+        let keypad = Keypad::new();
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(keypad.clone()));
+        emu.load(&[0xE5, 0x9E, 0x00, 0xA0, 0x00, 0xA0, 0xF0, 0x0A]).expect("Could not load program");
+        emu.set_register(5, 5).expect("Could not set V5");
+        keypad.press(5);
+        emu.set_keys(Some(0x0000));
+        assert_eq!(emu.run_for(Budget::Cycles(10)), StopReason::Breakpoint);
+        assert_eq!(emu.pc(), 0x0202);
+
+        emu.set_pc(0x0200);
+        emu.set_keys(Some(1 << 5));
+        assert_eq!(emu.run_for(Budget::Cycles(10)), StopReason::Breakpoint);
+        assert_eq!(emu.pc(), 0x0204);
+
+        // Keys that go down count as presses, and the real keys come back with None
+        emu.set_pc(0x0206);
+        emu.set_keys(Some(1 << 5 | 1 << 0xA));
+        emu.step();
+        assert_eq!(emu.register(0), Some(0xA));
+        emu.set_keys(None);
+        emu.set_pc(0x0200);
+        assert_eq!(emu.run_for(Budget::Cycles(10)), StopReason::Breakpoint);
+        assert_eq!(emu.pc(), 0x0204);
+    }
+
+    /// The stop for the breakpoints `ids` going off on the instruction at `pc`, with I at `index`.
+    fn hit(ids: &[usize], pc: u16, index: u32) -> StopReason {
+        StopReason::BreakpointHit { ids: ids.to_vec(), pc, index }
+    }
+
+    /// Test that breakpoints stop before the instructions they are on, and watchpoints stop right after the
+    /// instructions that touch what they watch.
+    #[test]
+    fn test_breakpoints() {
+        let mut emu = machine_with(&[
+            0x60, 0x05, // LD V0, 5
+            0xA3, 0x00, // LD I, 0x300
+            0xF0, 0x33, // LD B, V0
+            0xF2, 0x65, // LD V2, [I]
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x12, 0x0A, // JP 0x20A
+        ]);
+        assert_eq!(emu.add_breakpoint(Condition::Address(0x0204)), Ok(0));
+        assert_eq!(emu.add_breakpoint(Condition::Instruction("drw".to_string())), Ok(1));
+        assert_eq!(emu.add_breakpoint(Condition::Memory { start: 0x0301, len: 1, access: Access::Write }), Ok(2));
+        assert_eq!(emu.add_breakpoint(Condition::Memory { start: 0x0301, len: 1, access: Access::Change }), Ok(3));
+        assert_eq!(emu.add_breakpoint(Condition::Memory { start: 0x0302, len: 4, access: Access::Read }), Ok(4));
+        assert_eq!(emu.add_breakpoint(Condition::Register { x: 2, access: Access::Change }), Ok(5));
+        assert!(emu.add_breakpoint(Condition::Instruction("FOO".to_string())).is_err());
+        assert!(emu.add_breakpoint(Condition::Register { x: 16, access: Access::Read }).is_err());
+
+        // Breakpoints stop before the instruction, and only once
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[0], 0x0204, 0x0300));
+        assert_eq!(emu.pc(), 0x0204);
+
+        // Writing the 0 that was already there is a write, but not a change
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[2], 0x0204, 0x0300));
+        assert_eq!(emu.pc(), 0x0206);
+        assert_eq!(emu.memory()[0x0302], 5);
+
+        // One instruction can set off more than one watchpoint
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[4, 5], 0x0206, 0x0300));
+        assert_eq!(emu.pc(), 0x0208);
+        assert_eq!(emu.register(2), Some(5));
+
+        assert_eq!(emu.add_breakpoint(Condition::Index(Access::Read)), Ok(6));
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[1], 0x0208, 0x0300));
+        assert_eq!(emu.pc(), 0x0208);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[6], 0x0208, 0x0300));
+        assert_eq!(emu.pc(), 0x020A);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::BudgetExhausted);
+        let hits: Vec<u64> = emu.breakpoints().iter().map(|bp| bp.hits).collect();
+        assert_eq!(hits, vec![1, 1, 1, 0, 1, 1, 1]);
+
+        // Disabled and removed breakpoints don't stop anything, but LD B still reads I
+        assert_eq!(emu.enable_breakpoint(0, false), Ok(()));
+        assert_eq!(emu.remove_breakpoint(2), Ok(()));
+        assert!(emu.remove_breakpoint(2).is_err());
+        emu.set_pc(0x0204);
+        assert_eq!(emu.step(), hit(&[6], 0x0204, 0x0300));
+        assert_eq!(emu.pc(), 0x0206);
+        assert!(!emu.breakpoints()[0].enabled);
+
+        // A DRW that waits for the display only stops once
+        emu.set_quirks(Preset::CosmacVip.quirks());
+        emu.set_pc(0x0208);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[1], 0x0208, 0x0300));
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[6], 0x0208, 0x0300));
+        assert_eq!(emu.pc(), 0x020A);
+    }
+
+    /// Test that the screen's watchpoints say which instruction, and which sprite, changed it.
+    #[test]
+    fn test_display_breakpoints() {
+        let mut emu = machine_with(&[
+            0xA2, 0x10, // LD I, 0x210
+            0xD0, 0x01, // DRW V0, V0, 1
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x00, 0xE0, // CLS
+            0x12, 0x08, // JP 0x208
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xFF,       // 0x210: A row of eight pixels
+        ]);
+        assert_eq!(emu.add_breakpoint(Condition::Pixels { x: 20, y: 0, width: 4, height: 4 }), Ok(0));
+        assert_eq!(emu.add_breakpoint(Condition::Pixels { x: 14, y: 1, width: 10, height: 1 }), Ok(1));
+        assert_eq!(emu.add_breakpoint(Condition::Collision), Ok(2));
+        assert_eq!(emu.add_breakpoint(Condition::Clear), Ok(3));
+        assert!(emu.add_breakpoint(Condition::Pixels { x: 0, y: 0, width: 0, height: 1 }).is_err());
+
+        // The row is eight low resolution pixels, so sixteen screen pixels, across
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[1], 0x0202, 0x0210));
+        assert_eq!(emu.pc(), 0x0204);
+        assert!(pixel_on(&emu, 15, 1));
+
+        // Drawing it again erases it
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[1, 2], 0x0204, 0x0210));
+        assert!(!pixel_on(&emu, 15, 1));
+
+        // CLS stops before it runs, and clearing a blank screen changes nothing
+        assert_eq!(emu.run_for(Budget::Cycles(100)), hit(&[3], 0x0206, 0x0210));
+        assert_eq!(emu.pc(), 0x0206);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::BudgetExhausted);
+        let hits: Vec<u64> = emu.breakpoints().iter().map(|bp| bp.hits).collect();
+        assert_eq!(hits, vec![0, 2, 1, 1]);
+    }
+
+    /// Test setting, hitting, and taking away breakpoints through debug commands while the program runs.
+    #[test]
+    fn test_breakpoint_commands() {
+        // ADD V0, 1 then JP 0x200, forever
+        let progpath = std::env::temp_dir().join("mychip8_test_breakpoint_commands.bin");
+        fs::write(&progpath, [0x70, 0x01, 0x12, 0x00]).expect("Could not write program");
+        let (emu, tx, rx, _mockinput) = emulate(&progpath, false, true).expect("Could not start emulator");
+
+        tx.send(EmulatorCommand::AddBreakpoint(Condition::Address(0x0202))).expect("Could not send.");
+        match get_response(&rx) {
+            EmulatorResponse::BreakpointAdded(Ok(id)) => assert_eq!(id, 0),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        assert_eq!(wait_for_stop(&tx, &rx), hit(&[0], 0x0202, 0));
+        assert_pc(0x0202, &tx, &rx);
+
+        // Swap the breakpoint for a watchpoint on V0
+        match send_and_receive(EmulatorCommand::AddBreakpoint(Condition::Register { x: 0, access: Access::Change }), &tx, &rx) {
+            EmulatorResponse::BreakpointAdded(Ok(id)) => assert_eq!(id, 1),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        assert_ack(EmulatorCommand::EnableBreakpoint(0, false), true, &tx, &rx);
+        resume(&tx, &rx);
+        assert_eq!(wait_for_stop(&tx, &rx), hit(&[1], 0x0200, 0));
+        assert_pc(0x0202, &tx, &rx);
+
+        assert_ack(EmulatorCommand::RemoveBreakpoint(1), true, &tx, &rx);
+        assert_ack(EmulatorCommand::RemoveBreakpoint(1), false, &tx, &rx);
+        match send_and_receive(EmulatorCommand::PeekBreakpoints, &tx, &rx) {
+            EmulatorResponse::Breakpoints(bps) => {
+                assert_eq!(bps, vec![Breakpoint { id: 0, condition: Condition::Address(0x0202), enabled: false, hits: 1 }]);
+            },
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+
+        exit_and_join(emu, &tx);
+        fs::remove_file(&progpath).unwrap_or(());
+    }
+
+    /// Test that a crash dump survives being written out and read back in, and puts a fresh emulator back on the fault.
+    #[test]
+    fn test_crash_dump() {
+        let mut emu = embed("testprograms/FAULT/faulttest.bin", Keypad::new());
+        let fault = match emu.run_for(Budget::Cycles(10)) {
+            StopReason::Fault(err) => err,
+            reason => panic!("Expected a fault, but stopped because of {:?}", reason),
+        };
+
+        let mut text = Vec::<u8>::new();
+        emu.crash_dump().write(&mut text).expect("Could not write crash dump");
+        let dump = CrashDump::read(&text[..]).expect("Could not read crash dump back in");
+        // RAM is mostly zeros, which are left out, so there's only the font's eight lines and the program's one
+        assert!(String::from_utf8_lossy(&text).contains("ram: 4096 9\n"));
+        assert_eq!(dump.fault, Some(fault));
+        assert_eq!(dump.pc, 0x0202);
+        assert_eq!(dump.registers[0], 0x01);
+        assert_eq!(dump.history, vec![(0x0200, 0x6001)]);
+        assert_eq!(&dump.memory[..], emu.memory());
+
+        let mut other = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        other.load_crash_dump(&dump).expect("Could not load crash dump");
+        assert_eq!(other.step(), StopReason::Fault(fault));
+        assert_eq!(other.pc(), 0x0202);
+        assert_eq!(other.register(0), Some(0x01));
+        assert_eq!(other.memory(), emu.memory());
+    }
+
+    /// Test that a crash dump says what kind of machine it came from, and that the older dumps that didn't say can
+    /// still be read.
+    #[test]
+    fn test_crash_dump_versions() {
+        let mut emu = embed("testprograms/FAULT/faulttest.bin", Keypad::new());
+        emu.run_for(Budget::Cycles(10));
+        let mut dump = emu.crash_dump();
+        assert_eq!(dump.platform, Some(Platform::Chip8));
+
+        // Everything about the machine comes back, even what couldn't be guessed from the RAM and the screen
+        dump.platform = Some(Platform::XoChip);
+        dump.quirks = Some(Preset::CosmacVip.quirks());
+        dump.hires = Some(true);
+        dump.load_address = Some(0x0300);
+        let mut text = Vec::<u8>::new();
+        dump.write(&mut text).expect("Could not write crash dump");
+        let back = CrashDump::read(&text[..]).expect("Could not read crash dump back in");
+        assert_eq!(back.platform, Some(Platform::XoChip));
+        assert_eq!(back.quirks, Some(Preset::CosmacVip.quirks()));
+        assert_eq!(back.hires, Some(true));
+        assert_eq!(back.load_address, Some(0x0300));
+        let mut other = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        other.load_crash_dump(&back).expect("Could not load crash dump");
+        assert_eq!(other.platform(), Platform::XoChip);
+        assert_eq!(other.quirks(), Preset::CosmacVip.quirks());
+
+        // The history is decoded the way that platform runs it
+        let mut hires = emu.crash_dump();
+        hires.platform = Some(Platform::HiresChip8);
+        hires.history = vec![(0x0200, 0x0230)];
+        let mut hires_text = Vec::<u8>::new();
+        hires.write(&mut hires_text).expect("Could not write crash dump");
+        assert!(String::from_utf8_lossy(&hires_text).contains("0x0200: 0230  Op: CLS"));
+
+        // Plain Chip-8 used to run the SCHIP's instructions, so a version 3 dump from it is an SCHIP one
+        let mut plain_text = Vec::<u8>::new();
+        emu.crash_dump().write(&mut plain_text).expect("Could not write crash dump");
+        let plain_text = String::from_utf8_lossy(&plain_text).replace("crash dump v4", "crash dump v3");
+        let back = CrashDump::read(plain_text.as_bytes()).expect("Could not read a version 3 crash dump");
+        assert_eq!(back.platform, Some(Platform::SuperChip));
+
+        // A version 1 dump has none of that, so it can be read but not loaded. It also wrote out every line of RAM.
+        let text = String::from_utf8(text).expect("The crash dump is not text");
+        let mut old = Vec::<String>::new();
+        let mut lines = text.lines();
+        while let Some(line) = lines.next() {
+            if line.starts_with("ram:") {
+                let nlines: usize = line.split_whitespace().nth(2).and_then(|n| n.parse().ok()).expect("No number of lines of RAM");
+                lines.nth(nlines - 1);
+                old.push(format!("ram: {}", emu.memory().len()));
+                for (n, bytes) in emu.memory().chunks(32).enumerate() {
+                    old.push(format!("  0x{:04x}: {}", n * 32, bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(" ")));
+                }
+            } else if !["platform:", "quirks:", "stack depth:", "hires:", "load address:"].iter().any(|field| line.starts_with(field)) {
+                old.push(line.to_string());
+            }
+        }
+        let old = old.join("\n").replace("crash dump v4", "crash dump v1");
+        let back = CrashDump::read(old.as_bytes()).expect("Could not read a version 1 crash dump");
+        assert_eq!(back.platform, None);
+        assert_eq!(back.quirks, None);
+        assert_eq!(back.memory, emu.memory());
+        let mut other = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        assert!(other.load_crash_dump(&back).is_err());
+
+        // And dumps from the future are turned away
+        assert!(CrashDump::read(text.replace("crash dump v4", "crash dump v5").as_bytes()).is_err());
+    }
+
+    /// Test that a save state taken while a DRW waits for the display picks up where it left off, without stopping
+    /// on the DRW's breakpoint a second time.
+    #[test]
+    fn test_save_state_display_wait() {
+        // LD V0, 0 then DRW V0, V0, 1 then BRK, stopping before the DRW. Then SKP V0 skips a BRK if key 0 is down.
+        let program = [0x60, 0x00, 0xD0, 0x01, 0x00, 0xA0, 0xE0, 0x9E, 0x00, 0xA0, 0x00, 0xA0];
+        let quirks = Quirks { display_wait: true, ..Quirks::default() };
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_quirks(quirks);
+        emu.load(&program).expect("Could not load program");
+        emu.add_breakpoint(Condition::Address(0x0202)).expect("Could not add breakpoint");
+        assert!(matches!(emu.run_for(Budget::Cycles(10)), StopReason::BreakpointHit { pc: 0x0202, .. }));
+
+        // Now the DRW waits for the next frame
+        assert_eq!(emu.run_for(Budget::Cycles(1)), StopReason::BudgetExhausted);
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        assert!(state.display_waiting);
+
+        // Load it into a machine that was stopped on the breakpoint, with a debugger holding keys down
+        let mut other = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        other.set_quirks(quirks);
+        other.load(&program).expect("Could not load program");
+        other.add_breakpoint(Condition::Address(0x0202)).expect("Could not add breakpoint");
+        other.set_keys(Some(0xFFFF));
+        assert!(matches!(other.run_for(Budget::Cycles(10)), StopReason::BreakpointHit { pc: 0x0202, .. }));
+        other.load_state(&state).expect("Could not load save state");
+
+        // Both draw and carry on to the BRK, and end up the same
+        assert_eq!(emu.run_for(Budget::Frames(2)), StopReason::Breakpoint);
+        assert_eq!(other.run_for(Budget::Frames(2)), StopReason::Breakpoint);
+        assert_eq!(other.pc(), 0x0204);
+        assert_eq!(other.save_state().digest(), emu.save_state().digest());
+
+        // And the debugger's keys were let go
+        assert_eq!(other.run_for(Budget::Cycles(10)), StopReason::Breakpoint);
+        assert_eq!(other.pc(), 0x0208);
+    }
+
+    /// Test that snapshots whose PC, I, SP, or RAM make no sense are turned away without touching the machine.
+    #[test]
+    fn test_bad_snapshots() {
+        let mut emu = embed("testprograms/FAULT/faulttest.bin", Keypad::new());
+        emu.run_for(Budget::Cycles(10));
+        let good = emu.save_state();
+        let dump = emu.crash_dump();
+
+        let mut other = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        let bad_states = [
+            SaveState { sp: 200, ..good.clone() },
+            SaveState { memory: Vec::new(), ..good.clone() },
+            SaveState { memory: vec![0; 0x100], ..good.clone() },
+            SaveState { pc: 0x2000, ..good.clone() },
+            SaveState { index: 0x0001_0000, ..good.clone() },
+            SaveState { font_address: 0x0FF0, ..good.clone() },
+            SaveState { load_address: 0x1000, ..good.clone() },
+            SaveState { planes: PLANE_2, ..good.clone() },
+            SaveState { planes: 0x04, platform: Platform::XoChip, ..good.clone() },
+        ];
+        for state in bad_states.iter() {
+            assert!(other.load_state(state).is_err());
+            assert_eq!(other.pc(), 0x0200);
+            assert_eq!(other.memory().len(), 4096);
+        }
+        other.load_state(&good).expect("Could not load save state");
+
+        let bad_dumps = [
+            CrashDump { sp: 200, ..dump.clone() },
+            CrashDump { memory: Vec::new(), ..dump.clone() },
+            CrashDump { pc: 0x2000, ..dump.clone() },
+        ];
+        let mut other = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        for dump in bad_dumps.iter() {
+            assert!(other.load_crash_dump(dump).is_err());
+            assert_eq!(other.pc(), 0x0200);
+        }
+        other.load_crash_dump(&dump).expect("Could not load crash dump");
+
+        // Screens bigger than any platform's are turned away before anything gets made for them
+        let mut bytes = good.to_bytes();
+        let dims = bytes.windows(8).position(|w| w == [64, 0, 0, 0, 128, 0, 0, 0]).expect("Could not find the screen's size");
+        bytes[dims..dims + 8].copy_from_slice(&[0xFF; 8]);
+        assert!(SaveState::from_bytes(&bytes).is_err());
+        let mut text = Vec::<u8>::new();
+        dump.write(&mut text).expect("Could not write crash dump");
+        let text = String::from_utf8(text).expect("The crash dump is not text").replace("framebuffer: 64 128", "framebuffer: 4294967295 4294967295");
+        assert!(CrashDump::read(text.as_bytes()).is_err());
+    }
+
+    /// Test that an emulator thread can be started from a crash dump file and inspected.
+    #[test]
+    fn test_postmortem() {
+        let dumppath = std::env::temp_dir().join("mychip8_test_postmortem.txt");
+        let mut emu = embed("testprograms/FAULT/faulttest.bin", Keypad::new());
+        emu.set_crash_dump_path(Some(dumppath.clone()));
+        emu.run_for(Budget::Cycles(10));
+
+        let (eventtx, _eventrx) = mpsc::channel();
+        let options = Options { debugger: Some(eventtx), ..Options::default() };
+        let (emu, tx, rx, _mockinput) = postmortem(&dumppath, true, options).expect("Could not start emulator from crash dump");
+        match send_and_receive(EmulatorCommand::PeekFault, &tx, &rx) {
+            EmulatorResponse::Fault(Some(err)) => assert_eq!(err.kind, ErrorKind::StackUnderflow),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        assert_pc(0x0202, &tx, &rx);
+        assert_register(0, 0x01, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+        fs::remove_file(&dumppath).expect("Could not clean up the crash dump");
+    }
+
+    /// Returns which pixels are on in the given framebuffer.
+    fn pixels_on(framebuffer: &PixelGrid) -> Vec<bool> {
+        framebuffer.pixels.iter().map(|p| p.value == Pxcolor::Black).collect()
+    }
+
+    /// Test that a save state goes to bytes and back, and puts the machine back where it was.
+    #[test]
+    fn test_save_state() {
+        let mut emu = embed("testprograms/DRWVxVyNibble/drwvxvynibbletest.bin", Keypad::new());
+        assert_eq!(emu.run_for(Budget::Cycles(1000)), StopReason::Breakpoint);
+        let pc = emu.pc();
+        let pixels = pixels_on(emu.framebuffer());
+        let bytes = emu.save_state().to_bytes();
+
+        // Carry on to the next breakpoint, where there has been a collision
+        assert_eq!(emu.run_for(Budget::Cycles(1000)), StopReason::Breakpoint);
+        assert_eq!(emu.register(15), Some(1));
+
+        let state = SaveState::from_bytes(&bytes).expect("Could not read save state back in");
+        emu.load_state(&state).expect("Could not load save state");
+        assert_eq!(emu.pc(), pc);
+        assert_eq!(emu.register(15), Some(0));
+        assert_eq!(pixels_on(emu.framebuffer()), pixels);
+
+        // And everything should happen again just the same
+        assert_eq!(emu.run_for(Budget::Cycles(1000)), StopReason::Breakpoint);
+        assert_eq!(emu.register(15), Some(1));
+
+        // Versions from the future are refused
+        let mut future = bytes.clone();
+        future[8] = 0xFF;
+        assert!(SaveState::from_bytes(&future).is_err());
+
+        // Version 13 saves are laid out the same, but plain Chip-8 could still run SCHIP instructions then
+        assert_eq!(SaveState::from_bytes(&bytes).expect("Could not read save state").platform, Platform::Chip8);
+        let mut old = bytes.clone();
+        old[8] = 13;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 13 save state");
+        assert_eq!(state.platform, Platform::SuperChip);
+
+        // Version 12 saves had no display wait on the end, but still load
+        let v13_only = 1;
+        let mut old = bytes[..bytes.len() - v13_only].to_vec();
+        old[8] = 12;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 12 save state");
+        assert_eq!(state.pc, pc);
+        assert!(!state.display_waiting);
+
+        // Version 11 saves had no load address either
+        let v12_only = v13_only + 2;
+        let mut old = bytes[..bytes.len() - v12_only].to_vec();
+        old[8] = 11;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 11 save state");
+        assert_eq!(state.pc, pc);
+        assert_eq!(state.load_address, 0x0200);
+
+        // Version 10 saves had no font address either
+        let v11_only = v12_only + 2;
+        let mut old = bytes[..bytes.len() - v11_only].to_vec();
+        old[8] = 10;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 10 save state");
+        assert_eq!(state.pc, pc);
+        assert_eq!(state.font_address, 0);
+
+        // Version 9 saves had no timing model either
+        let v10_only = v11_only + 1 + 8 + 8;
+        let mut old = bytes[..bytes.len() - v10_only].to_vec();
+        old[8] = 9;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 9 save state");
+        assert_eq!(state.pc, pc);
+        assert_eq!((state.timing, state.cycle_count), (Timing::Flat, 0));
+
+        // Version 8 saves had no CDP1802 flag either
+        let v9_only = v10_only + 1;
+        let mut old = bytes[..bytes.len() - v9_only].to_vec();
+        old[8] = 8;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 8 save state");
+        assert_eq!(state.pc, pc);
+        assert!(!state.cdp1802);
+
+        // Version 7 saves had no VIP memory layout flag either
+        let v8_only = v9_only + 1;
+        let mut old = bytes[..bytes.len() - v8_only].to_vec();
+        old[8] = 7;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 7 save state");
+        assert_eq!(state.pc, pc);
+        assert!(!state.vip_layout);
+
+        // Version 6 saves had no top byte of I or MEGA-CHIP state either
+        let v7_only = v8_only + 1 + 4 + 256 * 4 + 4 + 1;
+        let mut old = bytes[..bytes.len() - v7_only].to_vec();
+        old[8] = 6;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 6 save state");
+        assert_eq!(state.pc, pc);
+        assert_eq!(state.palette, vec![0; 256]);
+        assert!(state.framebuffer.argb.is_none());
+
+        // Version 5 saves had no colors or output port either
+        let v6_only = v7_only + 1 + 1;
+        let mut old = bytes[..bytes.len() - v6_only].to_vec();
+        old[8] = 5;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 5 save state");
+        assert_eq!(state.pc, pc);
+        assert!(state.framebuffer.colors.is_none());
+        assert_eq!(state.port_out, 0);
+
+        // Version 4 saves had no quirks either
+        let v5_only = v6_only + 6 + 4;
+        let mut old = bytes[..bytes.len() - v5_only].to_vec();
+        old[8] = 4;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 4 save state");
+        assert_eq!(state.pc, pc);
+        assert_eq!(state.quirks, Quirks::default());
+
+        // Version 3 saves had no platform, planes, or second plane of the framebuffer either
+        let v4_only = v5_only + 1 + 1 + 4 + 128 * 64 / 8;
+        let mut old = bytes[..bytes.len() - v4_only].to_vec();
+        old[8] = 3;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 3 save state");
+        assert_eq!(state.pc, pc);
+        assert_eq!(state.platform, Platform::SuperChip);
+        assert_eq!(state.planes, PLANE_1);
+
+        // Version 2 saves had no resolution mode or RPL user flags either
+        let mut old = bytes[..bytes.len() - v4_only - 21].to_vec();
+        old[8] = 2;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 2 save state");
+        assert_eq!(state.pc, pc);
+        assert!(!state.hires);
+        assert_eq!(state.rpl_flags, vec![0; 16]);
+
+        // Version 1 saves had no random number generator either
+        let mut old = bytes[..bytes.len() - v4_only - 30].to_vec();
+        old[8] = 1;
+        let state = SaveState::from_bytes(&old).expect("Could not read a version 1 save state");
+        assert_eq!(state.pc, pc);
+        assert_eq!(state.rng, Rng::new(RngMode::Modern, 0));
+    }
+
+    /// Test SaveState and LoadState over the debug interface.
+    #[test]
+    fn test_save_state_debug() {
+        let statepath = std::env::temp_dir().join("mychip8_test_save_state_debug.state");
+        let (emu, tx, rx, _mockinput) = emulate(path::Path::new("testprograms/DRWVxVyNibble/drwvxvynibbletest.bin"), false, true).expect("Could not start emulator");
+
+        assert_register(15, 0, &tx, &rx);
+        match send_and_receive(EmulatorCommand::SaveState(statepath.clone()), &tx, &rx) {
+            EmulatorResponse::Ack(result) => result.expect("Could not save state"),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        resume(&tx, &rx);
+        assert_register(15, 1, &tx, &rx);
+
+        match send_and_receive(EmulatorCommand::LoadState(statepath.clone()), &tx, &rx) {
+            EmulatorResponse::Ack(result) => result.expect("Could not load state"),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        assert_register(15, 0, &tx, &rx);
+
+        exit_and_join(emu, &tx);
+        fs::remove_file(&statepath).expect("Could not clean up the save state");
+    }
+
+    /// Runs the RND test program with the given random number generator and returns the ten numbers it generated.
+    fn random_numbers(rng: Rng) -> Vec<u8> {
+        let mut emu = embed("testprograms/RNDVxByte/rndvxbytetest.bin", Keypad::new());
+        emu.set_rng(rng);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        (0..10).map(|x| emu.register(x).unwrap()).collect()
+    }
+
+    /// Test that RND gives the same numbers for the same seed, and that the generator goes into save states.
+    #[test]
+    fn test_rnd_seed() {
+        let numbers = random_numbers(Rng::new(RngMode::Modern, 1234));
+        assert_eq!(random_numbers(Rng::new(RngMode::Modern, 1234)), numbers);
+        assert_ne!(random_numbers(Rng::new(RngMode::Modern, 4321)), numbers);
+
+        // Save halfway through, then make sure loading the save gives the same second half
+        let mut emu = embed("testprograms/RNDVxByte/rndvxbytetest.bin", Keypad::new());
+        emu.set_rng(Rng::new(RngMode::Modern, 1234));
+        emu.run_for(Budget::Cycles(5));
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        emu.set_rng(Rng::new(RngMode::Modern, 4321));
+        emu.load_state(&state).expect("Could not load save state");
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!((0..10).map(|x| emu.register(x).unwrap()).collect::<Vec<u8>>(), numbers);
+    }
+
+    /// Test the COSMAC VIP algorithm: it adds bytes from the interpreter's page of RAM to a running total.
+    #[test]
+    fn test_rnd_vip() {
+        // Our interpreter page is empty, so the numbers never move off of the high byte of the seed
+        assert_eq!(random_numbers(Rng::new(RngMode::CosmacVip, 0x4200)), vec![0x42; 10]);
+
+        let mut rng = Rng::new(RngMode::CosmacVip, 0x0000);
+        let mut memory = vec![0u8; 4096];
+        memory[VIP_RANDOM_TABLE_ADDR + 1] = 3;
+        memory[VIP_RANDOM_TABLE_ADDR + 2] = 5;
+        memory[VIP_RANDOM_TABLE_ADDR + 3] = 250;
+        assert_eq!(rng.next_byte(&memory), 3);
+        assert_eq!(rng.next_byte(&memory), 8);
+        assert_eq!(rng.next_byte(&memory), 2);
+        assert_eq!(rng.state, 0x0203);
+    }
+
+    /// Test that rewinding walks back a frame at a time, and only as far as it was told to remember.
+    #[test]
+    fn test_rewind() {
+        // Count up in V0 and RAM forever: ADD V0, 1 then LD I, 0x300 then LD [I], V0 then JP 0x200
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.load(&[0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00]).expect("Could not load program");
+        emu.set_rewind_frames(10);
+        assert!(!emu.rewind());
+
+        emu.set_instructions_per_frame(16);
+        assert_eq!(emu.run_for(Budget::Frames(20)), StopReason::BudgetExhausted);
+        emu.step();
+        assert_eq!(emu.rewind_len(), 10);
+
+        // The first rewind goes back to the start of this frame, and each one after that goes back one more
+        let mut expected_count = 20 * 16;
+        while emu.rewind() {
+            assert_eq!(emu.instruction_count(), expected_count);
+            assert_eq!(emu.pc(), 0x0200);
+            assert_eq!(emu.register(0), Some((expected_count / 4) as u8));
+            assert_eq!(emu.memory()[0x300], (expected_count / 4) as u8);
+            expected_count -= 16;
+        }
+        assert_eq!(expected_count, 10 * 16);
+
+        // And we can carry on from there
+        assert_eq!(emu.run_for(Budget::Cycles(2)), StopReason::BudgetExhausted);
+        assert_eq!(emu.register(0), Some(((expected_count + 16) / 4 + 1) as u8));
+    }
+
+    /// Test that the rewind buffer holds onto a lot less than a whole save state for every frame, and still gives
+    /// back exactly what went in.
+    #[test]
+    fn test_rewind_buffer_size() {
+        let mut emu = embed("testprograms/DRWVxVyNibble/drwvxvynibbletest.bin", Keypad::new());
+        let snapshot_len = emu.save_state().to_bytes().len();
+        let mut buffer = RewindBuffer::new(100, REWIND_MAX_BYTES);
+        let mut digests = Vec::new();
+        for _ in 0..50 {
+            emu.step();
+            let state = emu.save_state();
+            digests.push(state.digest());
+            buffer.mark_all_dirty();
+            buffer.push(state.clone(), &state.memory, &state.framebuffer);
+        }
+        assert_eq!(buffer.len(), 50);
+        assert!(buffer.nbytes() < 2 * snapshot_len);
+
+        let mut memory = Vec::new();
+        while let Some(mut state) = buffer.pop(&mut memory) {
+            state.memory = memory.clone();
+            assert_eq!(Some(state.digest()), digests.pop());
+        }
+        assert!(digests.is_empty());
+    }
+
+    /// Test that the rewind buffer forgets old snapshots to stay under its byte limit when every frame changes a
+    /// lot, like a MEGA-CHIP program redrawing its whole screen in RAM, but always keeps the newest one.
+    #[test]
+    fn test_rewind_buffer_limit() {
+        let state = chip8::Chip8::without_debugger(Backend::headless(Keypad::new())).save_state();
+        let max_bytes = 4 * 1024 * 1024;
+        let mut buffer = RewindBuffer::new(1800, max_bytes);
+        for n in 0..50 {
+            buffer.mark_all_dirty();
+            buffer.push(state.clone(), &vec![n as u8 + 1; 1024 * 1024], &state.framebuffer);
+            assert!(buffer.nbytes() <= max_bytes);
+        }
+        assert!(buffer.len() > 1 && buffer.len() <= 4);
+        let mut memory = Vec::new();
+        assert!(buffer.pop(&mut memory).is_some());
+        assert_eq!(memory, vec![50; 1024 * 1024]);
+        assert!(buffer.pop(&mut memory).is_some());
+        assert_eq!(memory, vec![49; 1024 * 1024]);
+
+        // A snapshot too big for the limit on its own still gets kept
+        let mut buffer = RewindBuffer::new(1800, 16);
+        buffer.push(state.clone(), &[1; 100], &state.framebuffer);
+        buffer.mark_all_dirty();
+        buffer.push(state.clone(), &[2; 100], &state.framebuffer);
+        assert_eq!(buffer.len(), 1);
+        assert!(buffer.pop(&mut memory).is_some());
+        assert_eq!(memory, vec![2; 100]);
+    }
+
+    /// Test that the timers tick at exactly 60 Hz of emulated time, whatever the clock rate, and that throttling
+    /// makes frames take real time.
+    #[test]
+    fn test_scheduler() {
+        // LD V0, 60 then LD DT, V0 then spin forever
+        let program = [0x60, 0x3C, 0xF0, 0x15, 0x12, 0x04];
+
+        // At 1000 Hz, one second is 1000 instructions, and the last tick comes right before the 1000th one
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.load(&program).expect("Could not load program");
+        assert_eq!(emu.run_for(Budget::Cycles(1000)), StopReason::BudgetExhausted);
+        assert_eq!(emu.delay_timer(), 1);
+        emu.step();
+        assert_eq!(emu.delay_timer(), 0);
+
+        // Below 60 Hz, more than one tick goes by per instruction instead of dividing by zero
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.load(&program).expect("Could not load program");
+        emu.set_clock_rate(30);
+        assert_eq!(emu.run_for(Budget::Cycles(3)), StopReason::BudgetExhausted);
+        assert_eq!(emu.delay_timer(), 58);
+        assert_eq!(emu.run_for(Budget::Frames(10)), StopReason::BudgetExhausted);
+        assert_eq!(emu.delay_timer(), 48);
+
+        // A whole number of instructions per frame. Zero frames runs to the end of the one we're partway through.
+        emu.set_instructions_per_frame(10);
+        assert_eq!(emu.run_for(Budget::Frames(0)), StopReason::BudgetExhausted);
+        assert_eq!(emu.delay_timer(), 48);
+        let start = emu.instruction_count();
+        assert_eq!(emu.run_for(Budget::Frames(3)), StopReason::BudgetExhausted);
+        assert_eq!(emu.instruction_count() - start, 30);
+        assert_eq!(emu.delay_timer(), 45);
+
+        // Throttled, frames go by at 60 a second of real time
+        emu.set_throttle(true);
+        let then = time::Instant::now();
+        assert_eq!(emu.run_for(Budget::Frames(7)), StopReason::BudgetExhausted);
+        assert!(then.elapsed() >= time::Duration::from_millis(95));
+        assert_eq!(emu.delay_timer(), 38);
+    }
+
+    /// Loads `program` into a fresh headless machine with nobody on the other end of the debug pipes.
+    fn machine_with(program: &[u8]) -> chip8::Chip8 {
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.load(program).expect("Could not load program");
+        emu
+    }
+
+    /// Makes a machine that runs SCHIP programs, with `program` loaded.
+    fn schip_machine_with(program: &[u8]) -> chip8::Chip8 {
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_platform(Platform::SuperChip);
+        emu.load(program).expect("Could not load program");
+        emu
+    }
+
+    /// Is the screen pixel at (`x`, `y`) on?
+    fn pixel_on(emu: &chip8::Chip8, x: usize, y: usize) -> bool {
+        emu.framebuffer().get_pixel_at(x, y).value == Pxcolor::Black
+    }
+
+    /// Test that low resolution drawing is scaled up to fill the screen, and high resolution drawing isn't.
+    #[test]
+    fn test_schip_resolution() {
+        let program = [
+            0xA2, 0x10, // LD I, 0x210
+            0x60, 0x01, // LD V0, 1
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x00, 0xFF, // HIGH
+            0x61, 0x00, // LD V1, 0
+            0xD1, 0x11, // DRW V1, V1, 1
+            0x00, 0xA0, // BRK
+            0x00, 0x00,
+            0xFF,       // 0x210: A row of eight pixels
+        ];
+        let mut emu = schip_machine_with(&program);
+        assert!(!emu.is_hires());
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert!(emu.is_hires());
+
+        // The low resolution row is 16x2 screen pixels, starting at (2, 2)
+        assert!(pixel_on(&emu, 2, 2) && pixel_on(&emu, 17, 3));
+        assert!(!pixel_on(&emu, 1, 2) && !pixel_on(&emu, 18, 2) && !pixel_on(&emu, 2, 4));
+
+        // The high resolution one is 8x1 at (0, 0)
+        assert!(pixel_on(&emu, 0, 0) && pixel_on(&emu, 7, 0));
+        assert!(!pixel_on(&emu, 8, 0) && !pixel_on(&emu, 0, 1));
+        assert_eq!(pixels_on(emu.framebuffer()).iter().filter(|on| **on).count(), 32 + 8);
+    }
+
+    /// Test 16x16 sprites, and that high resolution mode counts the rows that collided in VF.
+    #[test]
+    fn test_schip_big_sprites() {
+        let mut program = vec![
+            0x00, 0xFF, // HIGH
+            0xA2, 0x20, // LD I, 0x220
+            0x60, 0x00, // LD V0, 0
+            0xD0, 0x00, // DRW V0, V0, 0
+            0x00, 0xA0, // BRK
+            0xD0, 0x00, // DRW V0, V0, 0
+            0x00, 0xA0, // BRK
+            0x00, 0xFE, // LOW
+            0xD0, 0x00, // DRW V0, V0, 0
+            0xD0, 0x00, // DRW V0, V0, 0
+            0x00, 0xA0, // BRK
+        ];
+        program.resize(0x20, 0);
+        program.extend_from_slice(&[0xFF; 32]);
+        let mut emu = schip_machine_with(&program);
+
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.register(15), Some(0));
+        assert!(pixel_on(&emu, 15, 15) && !pixel_on(&emu, 16, 15) && !pixel_on(&emu, 15, 16));
+
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.register(15), Some(16));
+        assert!(pixels_on(emu.framebuffer()).iter().all(|on| !on));
+
+        // In low resolution mode, it's 8x16 at double size, and VF is just whether anything collided
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.register(15), Some(1));
+        assert!(pixels_on(emu.framebuffer()).iter().all(|on| !on));
+    }
+
+    /// Test the SCHIP scrolling instructions.
+    #[test]
+    fn test_schip_scroll() {
+        let program = [
+            0x00, 0xFF, // HIGH
+            0xA2, 0x0E, // LD I, 0x20E
+            0x60, 0x00, // LD V0, 0
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x00, 0xC2, // SCD 2
+            0x00, 0xFB, // SCR
+            0x00, 0xFC, // SCL
+            0x80,       // 0x20E: A single pixel
+        ];
+        let mut emu = schip_machine_with(&program);
+        emu.run_for(Budget::Cycles(4));
+        assert!(pixel_on(&emu, 0, 0));
+
+        emu.step();
+        assert!(!pixel_on(&emu, 0, 0) && pixel_on(&emu, 0, 2));
+        emu.step();
+        assert!(!pixel_on(&emu, 0, 2) && pixel_on(&emu, 4, 2));
+        emu.step();
+        assert!(!pixel_on(&emu, 4, 2) && pixel_on(&emu, 0, 2));
+        assert_eq!(pixels_on(emu.framebuffer()).iter().filter(|on| **on).count(), 1);
+    }
+
+    /// Test the big hex font, including asking for a digit there isn't one for.
+    #[test]
+    fn test_schip_big_font() {
+        let program = [
+            0x60, 0x07, // LD V0, 7
+            0xF0, 0x30, // LD HF, V0
+            0x61, 0x10, // LD V1, 0x10
+            0xF1, 0x30, // LD HF, V1
+        ];
+        let mut emu = schip_machine_with(&program);
+        emu.run_for(Budget::Cycles(2));
+        assert_eq!(emu.index(), 0x50 + 7 * 10);
+        assert_eq!(&emu.memory()[emu.index() as usize..emu.index() as usize + 2], &[0xFF, 0xFF]);
+
+        match emu.run_for(Budget::Cycles(2)) {
+            StopReason::Fault(err) => assert_eq!(err.kind, ErrorKind::NoSuchHexSprite(0x10)),
+            reason => panic!("Should have faulted, but stopped because of {:?}", reason),
+        }
+    }
+
+    /// Test that the RPL user flags make it to disk and back, and that EXIT stops us.
+    #[test]
+    fn test_schip_rpl_flags() {
+        let path = std::env::temp_dir().join("mychip8_test_rpl.flags");
+        fs::remove_file(&path).unwrap_or(());
+
+        // LD V0, 0xAA then LD V1, 0xBB then LD R, V1 then EXIT
+        let mut emu = schip_machine_with(&[0x60, 0xAA, 0x61, 0xBB, 0xF1, 0x75, 0x00, 0xFD]);
+        emu.set_rpl_path(Some(path.clone()));
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Exited);
+        assert_eq!(emu.pc(), 0x0206);
+
+        // LD V1, R on a brand new machine
+        let mut emu = schip_machine_with(&[0xF1, 0x85]);
+        emu.set_rpl_path(Some(path.clone()));
+        assert_eq!(&emu.rpl_flags()[..3], &[0xAA, 0xBB, 0x00]);
+        emu.step();
+        assert_eq!(emu.register(0), Some(0xAA));
+        assert_eq!(emu.register(1), Some(0xBB));
+        fs::remove_file(&path).unwrap_or(());
+    }
+
+    /// Test that plain Chip-8 runs what the SCHIP added the way it always did: as SYS, or as garbage.
+    #[test]
+    fn test_schip_not_on_chip8() {
+        let program = [
+            0x00, 0xC1, // SYS 0x0C1, which would be SCD 1
+            0x00, 0xFF, // SYS 0x0FF, which would be HIGH
+            0xA2, 0x0C, // LD I, 0x20C
+            0xD0, 0x00, // DRW V0, V0, 0
+            0x00, 0xA0, // BRK
+            0xF0, 0x30, // LD HF, V0
+            0xFF,       // 0x20C: A row of eight pixels
+        ];
+        let mut emu = machine_with(&program);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert!(!emu.is_hires());
+        assert!(pixels_on(emu.framebuffer()).iter().all(|on| !on));
+        match emu.run_for(Budget::Cycles(100)) {
+            StopReason::Fault(err) => assert_eq!(err.kind, ErrorKind::InvalidOpcode),
+            reason => panic!("Should have faulted, but stopped because of {:?}", reason),
+        }
+
+        // The same program is SCHIP's on the SCHIP
+        let mut emu = schip_machine_with(&program);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert!(emu.is_hires());
+        assert!(pixel_on(&emu, 0, 0));
+    }
+
+    /// Test XO-CHIP's 64K of RAM, long I loads, skipping over them, and saving and loading ranges of registers.
+    #[test]
+    fn test_xochip_memory() {
+        let program = [
+            0xF0, 0x00, 0xFF, 0xF0, // LD I, 0xFFF0
+            0x61, 0x01,             // LD V1, 1
+            0x62, 0x02,             // LD V2, 2
+            0x63, 0x03,             // LD V3, 3
+            0x51, 0x32,             // SAVE V1 - V3
+            0x30, 0x00,             // SE V0, 0
+            0xF0, 0x00, 0x00, 0x00, // LD I, 0x0000 (skipped, all four bytes of it)
+            0x00, 0xA0,             // BRK
+            0x56, 0x43,             // LOAD V6 - V4
+            0x00, 0xA0,             // BRK
+        ];
+
+        // None of this means anything on a plain Chip-8
+        let mut emu = machine_with(&program);
+        assert!(emu.load(&[0u8; 5000]).is_err());
+        match emu.step() {
+            StopReason::Fault(err) => assert_eq!(err.kind, ErrorKind::InvalidOpcode),
+            reason => panic!("Should have faulted, but stopped because of {:?}", reason),
+        }
+
+        // There, 5xy2 is still SE Vx, Vy, and Fn01 is garbage
+        let mut emu = machine_with(&[0x51, 0x32, 0x61, 0x05, 0xF1, 0x01]);
+        match emu.run_for(Budget::Cycles(100)) {
+            StopReason::Fault(err) => assert_eq!(err.kind, ErrorKind::InvalidOpcode),
+            reason => panic!("Should have faulted, but stopped because of {:?}", reason),
+        }
+        assert_eq!(emu.register(1), Some(0));
+        assert_eq!(emu.pc(), 0x0204);
+
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_platform(Platform::XoChip);
+        assert_eq!(emu.memory().len(), 0x10000);
+        emu.load(&[0u8; 5000]).expect("A big program should fit on XO-CHIP");
+        emu.load(&program).expect("Could not load program");
+
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.pc(), 0x0212);
+        assert_eq!(emu.index(), 0xFFF0);
+        assert_eq!(&emu.memory()[0xFFF0..0xFFF4], &[1, 2, 3, 0]);
+
+        // Loading backwards puts the first byte in the first register named
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!((4..7).map(|x| emu.register(x).unwrap()).collect::<Vec<u8>>(), vec![3, 2, 1]);
+        assert_eq!(emu.index(), 0xFFF0);
+    }
+
+    /// Test drawing onto both XO-CHIP planes, and clearing and scrolling just one of them.
+    #[test]
+    fn test_xochip_planes() {
+        let mut program = vec![
+            0xF3, 0x01, // PLANE 3
+            0xA2, 0x20, // LD I, 0x220
+            0x60, 0x00, // LD V0, 0
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x00, 0xA0, // BRK
+            0xD0, 0x01, // DRW V0, V0, 1
+            0xD0, 0x01, // DRW V0, V0, 1
+            0xF2, 0x01, // PLANE 2
+            0x00, 0xE0, // CLS
+            0xF1, 0x01, // PLANE 1
+            0x00, 0xC1, // SCD 1
+            0x00, 0xA0, // BRK
+        ];
+        program.resize(0x20, 0);
+        program.extend_from_slice(&[0xF0, 0x3C]);
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_platform(Platform::XoChip);
+        emu.load(&program).expect("Could not load program");
+        let color = |emu: &chip8::Chip8, x, y| emu.framebuffer().get_pixel_at(x, y).value;
+
+        // The first byte goes on the first plane and the second on the second, each pixel twice the size in low resolution
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.planes(), ALL_PLANES);
+        assert_eq!(color(&emu, 0, 0), Pxcolor::Black);
+        assert_eq!(color(&emu, 4, 1), Pxcolor::BothPlanes);
+        assert_eq!(color(&emu, 8, 0), Pxcolor::Plane2);
+        assert_eq!(color(&emu, 12, 0), Pxcolor::White);
+        assert_eq!(emu.register(15), Some(0));
+
+        // Both planes go into save states
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        assert_eq!(state.platform, Platform::XoChip);
+        assert_eq!(state.planes, ALL_PLANES);
+        assert_eq!(state.framebuffer.get_pixel_at(4, 1).value, Pxcolor::BothPlanes);
+
+        // Clearing the second plane and scrolling the first leaves the first plane's pixels two screen pixels down
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.register(15), Some(0));
+        assert_eq!(color(&emu, 0, 1), Pxcolor::White);
+        assert_eq!(color(&emu, 0, 2), Pxcolor::Black);
+        assert_eq!(color(&emu, 4, 3), Pxcolor::Black);
+        assert_eq!(color(&emu, 8, 2), Pxcolor::White);
+        assert_eq!(pixels_on(emu.framebuffer()).iter().filter(|on| **on).count(), 8 * 2);
+    }
+
+    /// Test the CHIP-8X colors, second keypad, and ports.
+    #[test]
+    fn test_chip8x() {
+        let program = [
+            0x02, 0xA0, // BGCOL
+            0x60, 0x21, // LD V0, 0x21
+            0x61, 0x04, // LD V1, 4
+            0x62, 0x10, // LD V2, 0x10
+            0xB0, 0x20, // COL V0, V2, 0
+            0x63, 0x05, // LD V3, 5
+            0xE3, 0xF2, // SKP2 V3
+            0x12, 0x0C, // JP 0x20C
+            0xF3, 0xF8, // OUT V3
+            0xF4, 0xFB, // IN V4
+            0x00, 0xA0, // BRK
+        ];
+        let keypad = Keypad::new();
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(keypad.clone()));
+        emu.set_platform(Platform::Chip8X);
+        emu.load(&program).expect("Could not load program");
+        let foreground = |emu: &chip8::Chip8, x: usize, y: usize| {
+            let grid = emu.framebuffer();
+            grid.colors.as_ref().expect("CHIP-8X should have colors").foreground[y * grid.ncols as usize + x]
+        };
+
+        // The background goes from blue to black, and columns 1 to 3 of rows 0 and 1 of the zones turn green.
+        // Zones are 8x4 low resolution pixels, so 16x8 on the screen.
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::BudgetExhausted);
+        assert_eq!(emu.framebuffer().colors.as_ref().map(|c| c.background), Some(1));
+        assert_eq!(foreground(&emu, 15, 0), 1);
+        assert_eq!(foreground(&emu, 16, 0), 4);
+        assert_eq!(foreground(&emu, 63, 15), 4);
+        assert_eq!(foreground(&emu, 64, 0), 1);
+        assert_eq!(foreground(&emu, 16, 16), 1);
+
+        // The colors go into save states
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        assert_eq!(state.platform, Platform::Chip8X);
+        assert_eq!(state.framebuffer.colors, emu.framebuffer().colors);
+
+        // Only the second keypad counts for SKP2
+        keypad.press(5);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::BudgetExhausted);
+        assert!(emu.pc() < 0x210);
+        keypad.press_second(5);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::BudgetExhausted);
+        assert_eq!(emu.port_output(), 5);
+
+        // IN waits for something on the input port
+        assert_eq!(emu.pc(), 0x212);
+        emu.set_port_input(0x42);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.register(4), Some(0x42));
+
+        // Everywhere else, Bnnn is still a jump and the CHIP-8X instructions are garbage
+        let mut emu = machine_with(&[0xB2, 0x04, 0xE3, 0xF2, 0x00, 0xA0]);
+        assert!(emu.framebuffer().colors.is_none());
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        let mut emu = machine_with(&[0xE3, 0xF2]);
+        assert!(matches!(emu.run_for(Budget::Cycles(100)), StopReason::Fault(_)));
+    }
+
+    /// Test that HIRES programs are spotted, start at 0x2C0, draw on a 64x64 screen, and clear it with 0230.
+    #[test]
+    fn test_hires_chip8() {
+        let mut program = vec![0x12, 0x60]; // JP 0x260
+        program.extend_from_slice(&[0xF8, 0x02, 0xB3, 0xF8, 0x60, 0xA3, 0xD3]); // Stands in for the interpreter's patch
+        assert_eq!(Platform::detect(&program), None);
+        program.resize(0xC0, 0);
+        program.extend_from_slice(&[
+            0xA2, 0xD0, // LD I, 0x2D0
+            0x60, 0x3C, // LD V0, 60
+            0x61, 0x3E, // LD V1, 62
+            0xD0, 0x11, // DRW V0, V1, 1
+            0x00, 0xA0, // BRK
+            0x02, 0x30, // CLS
+            0x00, 0xA0, // BRK
+        ]);
+        program.resize(0xD0, 0);
+        program.push(0xFF);
+        assert_eq!(Platform::detect(&program), Some(Platform::HiresChip8));
+        assert_eq!(Platform::detect(&[0x00, 0xE0]), None);
+
+        // Plain programs that happen to start by jumping to 0x260 aren't HIRES programs, and neither is anything
+        // run on some other platform on purpose
+        let mut plain = vec![0x12, 0x60];
+        plain.resize(0x100, 0);
+        assert_eq!(Platform::detect(&plain), None);
+        let options = Options { platform: Some(Platform::Chip8), ..Options::default() };
+        let progpath = std::env::temp_dir().join("mychip8_test_hires_chip8.bin");
+        fs::write(&progpath, &program).expect("Could not write program");
+        let (emu, tx, rx, _mockinput) = emulate_with(&progpath, false, true, options).expect("Could not start emulator");
+        match send_and_receive(EmulatorCommand::PeekFramebuffer, &tx, &rx) {
+            EmulatorResponse::Framebuffer(grid) => assert_eq!((grid.ncols, grid.nrows), (128, 64)),
+            response => panic!("Response {:?} makes no sense...", response),
+        }
+        exit_and_join(emu, &tx);
+        fs::remove_file(&progpath).unwrap_or(());
+
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_platform(Platform::HiresChip8);
+        emu.load(&program).expect("Could not load program");
+        assert_eq!(emu.pc(), 0x2C0);
+        assert_eq!((emu.framebuffer().ncols, emu.framebuffer().nrows), (64, 64));
+
+        // Pixels are one screen pixel each, and the sprite wraps around the 64 pixel wide screen
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert!(pixel_on(&emu, 60, 62));
+        assert!(pixel_on(&emu, 63, 62));
+        assert!(pixel_on(&emu, 3, 62));
+        assert!(!pixel_on(&emu, 4, 62));
+        assert!(!pixel_on(&emu, 60, 63));
+
+        // The screen's size goes into save states, so they load into a machine with a different screen
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        let mut other = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        other.load_state(&state).expect("Could not load save state");
+        assert_eq!(other.platform(), Platform::HiresChip8);
+        assert_eq!((other.framebuffer().ncols, other.framebuffer().nrows), (64, 64));
+        assert!(pixel_on(&other, 60, 62));
+
+        // 0230 clears the screen
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert!(pixels_on(emu.framebuffer()).iter().all(|on| !on));
+    }
+
+    /// Test MEGA-CHIP mode: the palette, sprites of palette indexes, collisions, double buffering, and 24 bit I.
+    #[test]
+    fn test_megachip() {
+        let mut program = vec![
+            0x00, 0x11, // MEGAON
+            0x01, 0x00, 0x02, 0x30, // LDHI 0x000230
+            0x02, 0x02, // LDPAL 2
+            0x01, 0x00, 0x02, 0x40, // LDHI 0x000240
+            0x03, 0x02, // SPRW 2
+            0x04, 0x01, // SPRH 1
+            0x09, 0x01, // CCOL 1
+            0x60, 0x05, // LD V0, 5
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x00, 0xA0, // BRK
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x00, 0xA0, // BRK
+            0x00, 0xE0, // CLS
+            0x01, 0x01, 0x00, 0x00, // LDHI 0x010000
+            0x00, 0xA0, // BRK
+        ];
+        program.resize(0x30, 0);
+        program.extend_from_slice(&[0xFF, 0xFF, 0x00, 0x00, 0xFF, 0x00, 0x00, 0xFF]);
+        program.resize(0x40, 0);
+        program.extend_from_slice(&[0x01, 0x02]);
+
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_platform(Platform::MegaChip);
+        emu.load(&program).expect("Could not load program");
+        let argb = |emu: &chip8::Chip8| emu.framebuffer().argb.clone().expect("MEGA-CHIP mode should be on");
+
+        // The sprite is drawn in the palette's colors onto the screen being drawn, which isn't shown yet
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!((emu.framebuffer().ncols, emu.framebuffer().nrows), (256, 192));
+        let pos = 5 * 256 + 5;
+        assert_eq!(argb(&emu).drawing[pos..pos + 3], [0xFFFF_0000, 0xFF00_00FF, 0]);
+        assert_eq!(argb(&emu).shown[pos], 0);
+        assert_eq!(emu.register(15), Some(0));
+
+        // The MEGA-CHIP state goes into save states
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        assert_eq!(state.platform, Platform::MegaChip);
+        assert_eq!(state.framebuffer.argb, emu.framebuffer().argb);
+        assert_eq!((state.sprite_width, state.sprite_height, state.collision_index), (2, 1, 1));
+        assert_eq!(state.palette[2], 0xFF00_00FF);
+
+        // And into the rewind buffer
+        let state = emu.save_state();
+        let mut buffer = RewindBuffer::new(10, REWIND_MAX_BYTES);
+        buffer.push(state.clone(), &state.memory, &state.framebuffer);
+        let mut memory = Vec::new();
+        let rewound = buffer.pop(&mut memory).expect("Could not rewind");
+        assert!(memory == state.memory);
+        assert_eq!(rewound.framebuffer.argb, state.framebuffer.argb);
+        assert_eq!(rewound.palette, state.palette);
+
+        // Drawing over palette entry 1 is a collision
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.register(15), Some(1));
+
+        // CLS shows what was drawn and starts on a blank screen, and I can go past 0xFFFF
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(argb(&emu).shown[pos], 0xFFFF_0000);
+        assert_eq!(argb(&emu).drawing[pos], 0);
+        assert_eq!(emu.index(), 0x10000);
+
+        // Everywhere else, 0011 is just a SYS call, which does nothing
+        let mut emu = machine_with(&[0x00, 0x11, 0x00, 0xA0]);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert!(emu.framebuffer().argb.is_none());
+    }
+
+    /// Test that in the VIP memory layout, the screen and stack are in RAM, and writing to them there changes them.
+    #[test]
+    fn test_vip_layout() {
+        let mut program = vec![0x22, 0x10]; // CALL 0x210
+        program.resize(0x10, 0);
+        program.extend_from_slice(&[
+            0xA2, 0x40, // LD I, 0x240
+            0x60, 0x00, // LD V0, 0
+            0xD0, 0x01, // DRW V0, V0, 1
+            0xAE, 0xA0, // LD I, 0xEA0
+            0xF1, 0x65, // LD V1, [I]
+            0x00, 0xA0, // BRK
+            0xAF, 0x01, // LD I, 0xF01
+            0x60, 0xFF, // LD V0, 0xFF
+            0xF0, 0x55, // LD [I], V0
+            0xAE, 0xA0, // LD I, 0xEA0
+            0x60, 0x02, // LD V0, 0x02
+            0x61, 0x30, // LD V1, 0x30
+            0xF1, 0x55, // LD [I], V1
+            0x00, 0xEE, // RET
+        ]);
+        program.resize(0x32, 0);
+        program.extend_from_slice(&[0x00, 0xA0]); // BRK
+        program.resize(0x40, 0);
+        program.push(0x80);
+
+        let mut emu = machine_with(&program);
+        emu.set_vip_layout(true).expect("Chip-8 should have the VIP memory layout");
+
+        // What gets drawn shows up in RAM, and CALL puts the return address in RAM
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.memory()[0xF00], 0x80);
+        assert!(pixel_on(&emu, 0, 0));
+        assert_eq!((emu.register(0), emu.register(1)), (Some(0x02), Some(0x00)));
+        assert_eq!(emu.stack()[0], 0x0200);
+
+        // The layout goes into save states
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        assert!(state.vip_layout);
+
+        // Writing to the screen in RAM draws on it, and RET goes wherever the stack in RAM says
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.pc(), 0x0232);
+        assert!(pixel_on(&emu, 16, 0));
+        assert!(pixel_on(&emu, 31, 1));
+        assert!(!pixel_on(&emu, 32, 0));
+
+        // Only the platforms that ran on the VIP have it
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_platform(Platform::XoChip);
+        assert!(emu.set_vip_layout(true).is_err());
+        assert!(!emu.is_vip_layout());
+
+        // Asking for it anyway keeps the program from starting at all
+        let progpath = std::env::temp_dir().join("mychip8_test_vip_layout.bin");
+        fs::write(&progpath, &program).expect("Could not write program");
+        let options = Options { platform: Some(Platform::XoChip), vip_layout: true, ..Options::default() };
+        assert!(emulate_with(&progpath, false, true, options).is_err());
+        fs::remove_file(&progpath).unwrap_or(());
+    }
+
+    /// Test that SYS runs CDP1802 machine code that can see the registers and I the way the VIP interpreter kept them.
+    #[test]
+    fn test_cdp1802() {
+        let mut program = vec![
+            0x60, 0x05, // LD V0, 5
+            0xA3, 0x00, // LD I, 0x300
+            0x03, 0x10, // SYS 0x310
+            0x00, 0xA0, // BRK
+            0x03, 0x40, // SYS 0x340
+        ];
+        program.resize(0x110, 0);
+        program.extend_from_slice(&[
+            0xF8, 0x0E, // LDI 0x0E
+            0xB6,       // PHI R6
+            0xF8, 0xF0, // LDI 0xF0
+            0xA6,       // PLO R6
+            0x06,       // LDN R6
+            0xFF, 0x07, // SMI 7
+            0x3B, 0x1D, // BNF 0x31D
+            0xF8, 0x00, // LDI 0
+            0x56,       // STR R6
+            0xF8, 0x42, // LDI 0x42
+            0xAA,       // PLO RA
+            0xD4,       // SEP R4
+        ]);
+        program.resize(0x140, 0);
+        program.extend_from_slice(&[0x30, 0x40]); // BR 0x340
+
+        // Without the CDP1802, SYS does nothing
+        let mut emu = machine_with(&program);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!((emu.register(0), emu.index()), (Some(5), 0x300));
+
+        // With it, the routine works out 5 - 7 into V0, borrows, and points I at 0x342
+        let mut emu = machine_with(&program);
+        emu.set_cdp1802(true).expect("Chip-8 should have the CDP1802");
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!((emu.register(0), emu.index()), (Some(0xFE), 0x342));
+        assert_eq!(emu.pc(), 0x206);
+
+        // It works just the same with all 64 KiB of RAM
+        let mut big = machine_with(&program);
+        big.set_memory_len(0x10000).expect("Chip-8 should be able to address 64 KiB");
+        big.set_cdp1802(true).expect("Chip-8 should have the CDP1802");
+        assert_eq!(big.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!((big.register(0), big.index()), (Some(0xFE), 0x342));
+        assert_eq!(big.pc(), 0x206);
+
+        // A routine that never comes back is a fault
+        match emu.run_for(Budget::Cycles(100)) {
+            StopReason::Fault(err) => assert_eq!((err.kind, err.pc), (ErrorKind::MachineCodeRunaway, 0x208)),
+            reason => panic!("Expected the runaway routine to fault, but we stopped because of {:?}", reason),
+        }
+
+        // Only the platforms that ran on the VIP have it
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_platform(Platform::MegaChip);
+        assert!(emu.set_cdp1802(true).is_err());
+
+        // Asking for it anyway keeps the program from starting at all
+        let progpath = std::env::temp_dir().join("mychip8_test_cdp1802.bin");
+        fs::write(&progpath, [0x00, 0xA0]).expect("Could not write program");
+        let options = Options { platform: Some(Platform::MegaChip), cdp1802: true, ..Options::default() };
+        assert!(emulate_with(&progpath, false, true, options).is_err());
+        fs::remove_file(&progpath).unwrap_or(());
+    }
+
+    /// Test that with COSMAC VIP timing, each frame fits as many instructions as the interpreter had time for,
+    /// and DRW waits for the next frame.
+    #[test]
+    fn test_vip_timing() {
+        assert_eq!("vip".parse::<Timing>(), Ok(Timing::CosmacVip));
+        assert!("fast".parse::<Timing>().is_err());
+
+        // ADD takes 50 machine cycles and JP 52, so 18 times round the loop is exactly one frame's 1836
+        let mut emu = machine_with(&[0x70, 0x01, 0x12, 0x00]);
+        emu.set_timing(Timing::CosmacVip);
+        assert_eq!(emu.run_for(Budget::Frames(1)), StopReason::BudgetExhausted);
+        assert_eq!(emu.register(0), Some(18));
+        assert_eq!(emu.cycle_count(), 1836);
+
+        // The cycles go into save states
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        assert_eq!((state.timing, state.cycle_count), (Timing::CosmacVip, 1836));
+
+        // Long instructions push the next frame back: Fx33 on 199 counts out 19 digits
+        let mut emu = machine_with(&[0x60, 0xC7, 0xA3, 0x00, 0xF0, 0x33, 0x00, 0xA0]);
+        emu.set_timing(Timing::CosmacVip);
+        assert_eq!(emu.run_for(Budget::Cycles(100)), StopReason::Breakpoint);
+        assert_eq!(emu.cycle_count(), 46 + 52 + 40 + 80 + 16 * 19);
+
+        // With the display wait, only one DRW goes in each frame
+        let mut emu = machine_with(&[0xD0, 0x01, 0x70, 0x01, 0x12, 0x00]);
+        emu.set_timing(Timing::CosmacVip);
+        emu.set_quirks(Preset::CosmacVip.quirks());
+        assert_eq!(emu.run_for(Budget::Frames(5)), StopReason::BudgetExhausted);
+        assert_eq!(emu.register(0), Some(5));
+    }
+
+    /// Test moving the program, the font, and the end of RAM.
+    #[test]
+    fn test_memory_layout() {
+        assert_eq!("eti660".parse::<FontSet>(), Ok(FontSet::Eti660));
+        assert!("comicsans".parse::<FontSet>().is_err());
+
+        // 8 KiB of RAM with the VIP's font at 0x050 and the program at 0x600, like an ETI-660
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_memory_len(0x2000).expect("Could not make RAM bigger");
+        emu.set_font(FontSet::Vip.glyphs(), 0x050).expect("Could not move the font");
+        emu.set_load_address(0x600).expect("Could not move the load address");
+        assert_eq!(emu.pc(), 0x600);
+
+        // LD V0, 7 then LD F, V0
+        emu.load(&[0x60, 0x07, 0xF0, 0x29, 0x00, 0xA0]).expect("Could not load program");
+        assert_eq!(emu.run_for(Budget::Cycles(10)), StopReason::Breakpoint);
+        assert_eq!(emu.pc(), 0x604);
+        assert_eq!(emu.index(), 0x050 + 7 * 5);
+        assert_eq!(&emu.memory()[0x050 + 7 * 5..0x050 + 8 * 5], &[0xF0, 0x10, 0x10, 0x10, 0x10]);
+        assert_eq!(&emu.memory()[..0x050], &[0; 0x050][..]);
+        assert_eq!(emu.memory().len(), 0x2000);
+
+        // The font and the program stay where they were put in save states, and the bigger RAM loads back in
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        assert_eq!((state.font_address, state.load_address), (0x050, 0x600));
+        let mut copy = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        copy.load_state(&state).expect("Could not load save state with 8 KiB of RAM");
+        assert_eq!(copy.memory().len(), 0x2000);
+        assert_eq!(copy.crash_dump().load_address, Some(0x600));
+
+        // Things that don't fit are refused
+        assert!(emu.set_memory_len(0x20000).is_err());
+        assert!(emu.set_memory_len(0x100).is_err());
+        assert!(emu.set_load_address(0x2000).is_err());
+        assert!(emu.set_font(&[0xF0; 3], 0).is_err());
+        assert!(emu.set_font(FontSet::Modern.glyphs(), 0x1FF0).is_err());
+        let mut small = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        small.set_memory_len(0x800).expect("Could not make RAM smaller");
+        assert!(small.set_vip_layout(true).is_err());
+
+        // A font file has to be exactly one font
+        let path = std::env::temp_dir().join("mychip8_test_font.bin");
+        fs::write(&path, &FontSet::FishNChips.glyphs()[..]).expect("Could not write font file");
+        assert_eq!(font::load(&path), Ok(FontSet::FishNChips.glyphs().to_vec()));
+        fs::write(&path, [0xF0; 10]).expect("Could not write font file");
+        assert!(font::load(&path).is_err());
+        fs::remove_file(&path).unwrap_or(());
+    }
+
+    /// Runs `program` with `quirks` until it stops, and returns the machine.
+    fn run_with_quirks(program: &[u8], quirks: Quirks) -> chip8::Chip8 {
+        let mut emu = machine_with(program);
+        emu.set_quirks(quirks);
+        emu.set_instructions_per_frame(10);
+        emu.run_for(Budget::Cycles(1000));
+        emu
+    }
+
+    /// Test each of the quirks against the way we behave without them.
+    #[test]
+    fn test_quirks() {
+        let vip = Preset::CosmacVip.quirks();
+        let chip48 = Preset::Chip48.quirks();
+        assert_eq!("chip48".parse::<Preset>(), Ok(Preset::Chip48));
+        assert!("chip9".parse::<Preset>().is_err());
+
+        // LD V0, 1 then LD V1, 0xF0 then SHR V0, V1
+        let shift = [0x60, 0x01, 0x61, 0xF0, 0x80, 0x16, 0x00, 0xA0];
+        let emu = run_with_quirks(&shift, Quirks::default());
+        assert_eq!((emu.register(0), emu.register(15)), (Some(0x00), Some(1)));
+        let emu = run_with_quirks(&shift, vip);
+        assert_eq!((emu.register(0), emu.register(15)), (Some(0x78), Some(0)));
+
+        // LD I, 0x300 then LD [I], V1
+        let store = [0xA3, 0x00, 0xF1, 0x55, 0x00, 0xA0];
+        assert_eq!(run_with_quirks(&store, Quirks::default()).index(), 0x300);
+        assert_eq!(run_with_quirks(&store, vip).index(), 0x302);
+
+        // LD VF, 5 then OR V0, V1
+        let logic = [0x6F, 0x05, 0x80, 0x11, 0x00, 0xA0];
+        assert_eq!(run_with_quirks(&logic, Quirks::default()).register(15), Some(5));
+        assert_eq!(run_with_quirks(&logic, vip).register(15), Some(0));
+
+        // LD V0, 4 then LD V2, 8 then JP V0, 0x210 (or JP V2, 0x210)
+        let mut jump = vec![0x60, 0x04, 0x62, 0x08, 0xB2, 0x10];
+        jump.resize(0x14, 0);
+        jump.extend_from_slice(&[0x00, 0xA0, 0x00, 0x00, 0x00, 0xA0]);
+        assert_eq!(run_with_quirks(&jump, Quirks::default()).pc(), 0x214);
+        assert_eq!(run_with_quirks(&jump, chip48).pc(), 0x218);
+
+        // LD I, 0x20A then LD V0, 60 then DRW V0, V0, 1 with a row of eight pixels. Doubled up and wrapped, that is
+        // at (120, 56) on the screen, hanging off the right edge
+        let clip = [0xA2, 0x0A, 0x60, 0x3C, 0xD0, 0x01, 0x00, 0xA0, 0x00, 0x00, 0xFF];
+        let emu = run_with_quirks(&clip, Quirks::default());
+        assert!(pixel_on(&emu, 127, 56) && pixel_on(&emu, 0, 56));
+        let emu = run_with_quirks(&clip, vip);
+        assert!(pixel_on(&emu, 127, 56) && !pixel_on(&emu, 0, 56));
+
+        // CALL 0x200 forever, which overflows after however many calls the stack holds
+        for (quirks, depth) in [(Quirks::default(), 16), (vip, 12)].iter() {
+            let mut emu = machine_with(&[0x22, 0x00]);
+            emu.set_quirks(*quirks);
+            match emu.run_for(Budget::Cycles(100)) {
+                StopReason::Fault(err) => assert_eq!(err.kind, ErrorKind::StackOverflow),
+                reason => panic!("Should have overflowed, but stopped because of {:?}", reason),
+            }
+            assert_eq!(emu.sp() as usize, *depth);
+        }
+
+        // Two DRWs in a row: with the display wait, the second one idles until the next frame (ten instructions in)
+        let wait = [0xD0, 0x01, 0xD0, 0x01, 0x00, 0xA0];
+        assert_eq!(run_with_quirks(&wait, Quirks::default()).instruction_count(), 2);
+        assert_eq!(run_with_quirks(&wait, vip).instruction_count(), 11);
+
+        // The quirks go into save states
+        let emu = run_with_quirks(&wait, vip);
+        let state = SaveState::from_bytes(&emu.save_state().to_bytes()).expect("Could not read save state back in");
+        assert_eq!(state.quirks, vip);
+    }
+
+    /// Plays `movie` on a fresh machine until it finishes, returning whether it matched the recording.
+    fn play_to_end(movie: &Movie) -> bool {
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(Keypad::new()));
+        emu.set_rng(Rng::new(RngMode::Modern, 1234));
+        emu.play_movie(movie).expect("Could not play movie");
+        for _ in 0..1000 {
+            if let StopReason::MovieFinished { matched } = emu.run_for(Budget::Frames(1)) {
+                return matched;
+            }
+        }
+        panic!("The movie never finished.");
+    }
+
+    /// Test that a recorded movie plays back to exactly the same state, and that a movie with the wrong keys doesn't.
+    #[test]
+    fn test_movie() {
+        // Forever: add a random number to V2 while 0x5 is held, and wait for a key each time we do
+        let program = [0x61, 0x05, 0xC0, 0xFF, 0xE1, 0x9E, 0x12, 0x02, 0x82, 0x04, 0xF3, 0x0A, 0x12, 0x02];
+        let keypad = Keypad::new();
+        let mut emu = chip8::Chip8::without_debugger(Backend::headless(keypad.clone()));
+        emu.set_rng(Rng::new(RngMode::Modern, 42));
+        emu.load(&program).expect("Could not load program");
+
+        // Play for a while, mashing some keys
+        emu.start_recording();
+        for frame in 0..60 {
+            match frame % 7 {
+                0 => keypad.press(0x5),
+                3 => keypad.release(0x5),
+                5 => keypad.press(0xA),
+                6 => keypad.release(0xA),
+                _ => (),
+            }
+            emu.run_for(Budget::Frames(1));
+        }
+        emu.step();
+        let movie = emu.stop_recording().expect("We were recording");
+        assert!(emu.stop_recording().is_none());
+        assert!(emu.register(2) != Some(0));
+        assert!(!movie.events.is_empty());
+
+        // It survives a trip through a file, and plays back the same even though the machine starts out with a different seed
+        let path = std::env::temp_dir().join("mychip8_test_movie.movie");
+        movie.save(&path).expect("Could not save movie");
+        let loaded = Movie::load(&path).expect("Could not load movie");
+        fs::remove_file(&path).unwrap_or(());
+        assert_eq!(loaded.events, movie.events);
+        assert!(play_to_end(&loaded));
+
+        // Without the key presses, we end up somewhere else
+        let mut tampered = loaded.clone();
+        tampered.events.clear();
+        assert!(!play_to_end(&tampered));
+    }
+}
@@ -0,0 +1,7 @@
+ld0 = b'\x60\x01'  # Load 0x01 into V0
+ret = b'\x00\xEE'  # Return, but there is nothing on the stack
+nop = b'\x00\x00'
+with open("faulttest.bin", 'wb') as f:
+    f.write(ld0)   # 0x0200
+    f.write(ret)   # 0x0202  <-- Fault here and stay halted
+    f.write(nop)   # 0x0204